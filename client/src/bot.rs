@@ -0,0 +1,337 @@
+use common::grpc::shape_events_client::ShapeEventsClient;
+use common::grpc::{
+	CreateShapeRequest, MoveTo, PlayerIdentity, Point, SetQueueRequest,
+	SubscribeRequest, Task, event, task,
+};
+use common::model::{METERS, from_meters};
+use rand::Rng;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::{Code, Request};
+
+pub const DEFAULT_SERVER: &str = "http://127.0.0.1:50051";
+
+// How far from the origin random walkers wander, in millimeters
+const WANDER: i64 = 20 * METERS;
+// Spam creators make this many shapes at most, so a forgotten bot doesn't
+// grow the match forever
+const SPAM_LIMIT: usize = 500;
+// Refused orders in a row before a bot leaves its unit standing
+const ORDER_ATTEMPTS: usize = 3;
+
+pub type BotError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Clone, Debug)]
+enum Behavior {
+	// moves its unit somewhere random whenever it stops
+	RandomWalk,
+	// moves its unit between these points, in order, forever
+	Patrol(Vec<Point>),
+	// creates a shape on every beat
+	SpamCreate,
+}
+
+impl Behavior {
+	// `random-walk`, `spam-create` or `patrol:X,Y:X,Y...` with the points
+	// in meters
+	fn parse(arg: &str) -> Result<Self, String> {
+		match arg.split_once(':') {
+			None if arg == "random-walk" => Ok(Behavior::RandomWalk),
+			None if arg == "spam-create" => Ok(Behavior::SpamCreate),
+			Some(("patrol", points)) => {
+				let points = points
+					.split(':')
+					.map(parse_point)
+					.collect::<Option<Vec<_>>>()
+					.ok_or_else(|| format!("bad patrol points in {}", arg))?;
+				if points.len() < 2 {
+					return Err(format!("{} needs at least two points", arg));
+				}
+				Ok(Behavior::Patrol(points))
+			}
+			_ => Err(format!("unknown behavior {}", arg)),
+		}
+	}
+}
+
+// `X,Y` in meters
+pub fn parse_point(point: &str) -> Option<Point> {
+	let (x, y) = point.split_once(',')?;
+	let meters = |v: &str| v.trim().parse::<f64>().ok();
+	Some(Point {
+		x: from_meters(meters(x)?),
+		y: from_meters(meters(y)?),
+	})
+}
+
+struct Options {
+	server: String,
+	match_id: u64,
+	every: Duration,
+	bots: Vec<Behavior>,
+}
+
+// `[--server URL] [--match ID] [--every MS] [N*]BEHAVIOR...`
+fn parse_options(
+	mut args: impl Iterator<Item = String>,
+) -> Result<Options, String> {
+	let mut options = Options {
+		server: DEFAULT_SERVER.into(),
+		match_id: 0,
+		every: Duration::from_millis(500),
+		bots: Vec::new(),
+	};
+	while let Some(arg) = args.next() {
+		let mut value =
+			|| args.next().ok_or_else(|| format!("{} needs a value", arg));
+		match arg.as_str() {
+			"--server" => options.server = value()?,
+			"--match" => {
+				options.match_id =
+					value()?.parse().map_err(|_| "--match takes an id")?;
+			}
+			"--every" => {
+				let ms: u64 = value()?
+					.parse()
+					.map_err(|_| "--every takes milliseconds")?;
+				options.every = Duration::from_millis(ms.max(1));
+			}
+			_ => {
+				let (count, behavior) = match arg.split_once('*') {
+					Some((count, behavior)) => (
+						count
+							.parse()
+							.map_err(|_| format!("bad bot count in {}", arg))?,
+						behavior,
+					),
+					None => (1, arg.as_str()),
+				};
+				let behavior = Behavior::parse(behavior)?;
+				options.bots.extend(std::iter::repeat_n(behavior, count));
+			}
+		}
+	}
+	if options.bots.is_empty() {
+		return Err("no bots, try random-walk, spam-create or \
+			patrol:0,0:10,0"
+			.into());
+	}
+	Ok(options)
+}
+
+// Runs every bot on the command line until they all stop
+pub async fn run(
+	args: impl Iterator<Item = String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let options = parse_options(args)?;
+	let channel = Channel::from_shared(options.server.clone())?
+		.connect()
+		.await?;
+
+	let bots: Vec<_> = options
+		.bots
+		.into_iter()
+		.enumerate()
+		.map(|(index, behavior)| {
+			let bot = Bot {
+				name: format!("bot {}", index + 1),
+				client: ShapeEventsClient::new(channel.clone()),
+				identity: PlayerIdentity::default(),
+				behavior,
+				units: Vec::new(),
+				next_point: 0,
+			};
+			tokio::spawn(bot.run(options.match_id, options.every))
+		})
+		.collect();
+	println!("Started {} bots", bots.len());
+	for bot in bots {
+		if let Err(e) = bot.await? {
+			eprintln!("{}", e);
+		}
+	}
+	Ok(())
+}
+
+// One player on its own Subscribe stream
+struct Bot {
+	name: String,
+	client: ShapeEventsClient<Channel>,
+	identity: PlayerIdentity,
+	behavior: Behavior,
+	units: Vec<u64>,
+	// the patrol point the unit is heading for
+	next_point: usize,
+}
+
+impl Bot {
+	async fn run(
+		mut self,
+		match_id: u64,
+		every: Duration,
+	) -> Result<(), String> {
+		self.play(match_id, every)
+			.await
+			.map_err(|e| format!("{} stopped: {}", self.name, e))
+	}
+
+	async fn play(
+		&mut self,
+		match_id: u64,
+		every: Duration,
+	) -> Result<(), BotError> {
+		let mut stream = self
+			.client
+			.subscribe(SubscribeRequest {
+				match_id,
+				..Default::default()
+			})
+			.await?
+			.into_inner();
+
+		let mut beat = tokio::time::interval(every);
+		let mut received = 0u64;
+		loop {
+			tokio::select! {
+				message = stream.message() => {
+					let Some(ev) = message? else {
+						println!("{}: the server closed the stream", self.name);
+						return Ok(());
+					};
+					received += 1;
+					self.handle(ev.kind).await?;
+				}
+				_ = beat.tick() => {
+					// nothing to do until the server says who we are
+					if self.identity.player_id != 0 {
+						self.on_beat(received).await?;
+					}
+				}
+			}
+		}
+	}
+
+	async fn handle(
+		&mut self,
+		kind: Option<event::Kind>,
+	) -> Result<(), BotError> {
+		match kind {
+			Some(event::Kind::PlayerIdentity(identity)) => {
+				println!("{}: playing as {}", self.name, identity.player_id);
+				self.identity = identity;
+				if !matches!(self.behavior, Behavior::SpamCreate) {
+					let unit_id = self.create_shape().await?;
+					self.order(unit_id).await?;
+				}
+			}
+			Some(event::Kind::Warning(warning)) => {
+				eprintln!(
+					"{}: warning from server: {}",
+					self.name, warning.message
+				);
+			}
+			// a queue that goes nowhere means the unit stopped, time for the
+			// next order
+			Some(event::Kind::Update(update))
+				if update
+					.queue
+					.iter()
+					.all(|s| s.delta.is_none() && s.arc.is_none())
+					&& self.units.contains(&update.unit_id) =>
+			{
+				self.order(update.unit_id).await?;
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	async fn on_beat(&mut self, received: u64) -> Result<(), BotError> {
+		if matches!(self.behavior, Behavior::SpamCreate)
+			&& self.units.len() < SPAM_LIMIT
+		{
+			self.create_shape().await?;
+			if self.units.len().is_multiple_of(50) {
+				println!(
+					"{}: {} shapes, {} events received",
+					self.name,
+					self.units.len(),
+					received
+				);
+			}
+		}
+		Ok(())
+	}
+
+	async fn create_shape(&mut self) -> Result<u64, BotError> {
+		let request = authorized(
+			&self.identity,
+			CreateShapeRequest { template_id: None },
+		)?;
+		let unit_id = self.client.create_shape(request).await?.into_inner().id;
+		self.units.push(unit_id);
+		Ok(unit_id)
+	}
+
+	// Sends the unit to wherever the behavior wants it next. The server
+	// refuses a move to where the unit already is, so a refused order moves
+	// on to the next destination.
+	async fn order(&mut self, unit_id: u64) -> Result<(), BotError> {
+		for _ in 0..ORDER_ATTEMPTS {
+			let Some(destination) = self.next_destination() else {
+				return Ok(());
+			};
+			let request = authorized(
+				&self.identity,
+				SetQueueRequest {
+					unit_id,
+					tasks: vec![Task {
+						kind: Some(task::Kind::Move(MoveTo {
+							destination: Some(destination),
+						})),
+					}],
+				},
+			)?;
+			match self.client.queue(request).await {
+				Ok(_) => return Ok(()),
+				Err(status) if status.code() == Code::InvalidArgument => {}
+				Err(status) => return Err(status.into()),
+			}
+		}
+		eprintln!("{}: unit {} refuses to move", self.name, unit_id);
+		Ok(())
+	}
+
+	fn next_destination(&mut self) -> Option<Point> {
+		match &self.behavior {
+			Behavior::RandomWalk => {
+				let mut rng = rand::rng();
+				Some(Point {
+					x: rng.random_range(-WANDER..=WANDER),
+					y: rng.random_range(-WANDER..=WANDER),
+				})
+			}
+			Behavior::Patrol(points) => {
+				let point = points[self.next_point];
+				self.next_point = (self.next_point + 1) % points.len();
+				Some(point)
+			}
+			Behavior::SpamCreate => None,
+		}
+	}
+}
+
+// Wraps a message with the headers that say who is asking
+pub fn authorized<T>(
+	identity: &PlayerIdentity,
+	message: T,
+) -> Result<Request<T>, BotError> {
+	let mut request = Request::new(message);
+	request
+		.metadata_mut()
+		.insert("player-id", identity.player_id.to_string().parse()?);
+	request
+		.metadata_mut()
+		.insert("player-secret", identity.secret.parse()?);
+	Ok(request)
+}
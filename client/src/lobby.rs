@@ -0,0 +1,115 @@
+use common::lobby::{Lobby, LobbyEvent, Room};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+pub const DEFAULT_LOBBY: &str = "http://127.0.0.1:8081";
+
+// Follows the lobby's event stream and prints every change as it happens
+pub async fn watch(lobby_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+	let client =
+		Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+	let uri = format!("{}/events", lobby_url.trim_end_matches('/')).parse()?;
+	let mut body = client.get(uri).await?.into_body();
+
+	let mut lobby = Lobby::default();
+	let mut buffer = Vec::new();
+	while let Some(frame) = body.frame().await {
+		let Ok(data) = frame?.into_data() else {
+			continue;
+		};
+		buffer.extend_from_slice(&data);
+		// events are separated by a blank line
+		while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+			let message: Vec<u8> = buffer.drain(..end + 2).collect();
+			for line in std::str::from_utf8(&message)?.lines() {
+				if let Some(json) = line.strip_prefix("data: ") {
+					let event: LobbyEvent = serde_json::from_str(json)?;
+					lobby.apply(&event);
+					print_event(&event, &lobby);
+				}
+			}
+		}
+	}
+	println!("The lobby closed the connection");
+	Ok(())
+}
+
+fn player_name(lobby: &Lobby, player_id: u64) -> String {
+	lobby
+		.players
+		.iter()
+		.find(|p| p.id == player_id)
+		.map(|p| p.name.clone())
+		.unwrap_or_else(|| format!("player {}", player_id))
+}
+
+fn print_room(room: &Room) {
+	let players: Vec<String> = room
+		.players
+		.iter()
+		.map(|p| format!("{}{}", p.name, if p.ready { "*" } else { "" }))
+		.collect();
+	println!(
+		"  room {} \"{}\" [{:?}] host {} {}/{} speed {}: {}",
+		room.id,
+		room.name,
+		room.status,
+		room.host,
+		room.players.len(),
+		room.max_players,
+		room.settings.speed,
+		players.join(", ")
+	);
+}
+
+// Called after the event has been applied, so names are still known for
+// whoever just left
+fn print_event(event: &LobbyEvent, lobby: &Lobby) {
+	match event {
+		LobbyEvent::Snapshot(lobby) => {
+			println!(
+				"Lobby has {} players and {} rooms",
+				lobby.players.len(),
+				lobby.rooms.len()
+			);
+			lobby.rooms.iter().for_each(print_room);
+		}
+		LobbyEvent::PlayerCreated(player) => {
+			println!("{} arrived in the lobby", player.name);
+		}
+		LobbyEvent::PlayerDeleted { player_id } => {
+			println!("Player {} left the lobby", player_id);
+		}
+		LobbyEvent::PlayerJoined { room, player } => {
+			println!("{} joined room {}", player.name, room);
+		}
+		LobbyEvent::PlayerLeft {
+			room,
+			player_id,
+			kicked,
+		} => {
+			let name = player_name(lobby, *player_id);
+			if *kicked {
+				println!("{} was kicked from room {}", name, room);
+			} else {
+				println!("{} left room {}", name, room);
+			}
+		}
+		LobbyEvent::ReadyChanged(player) => {
+			let ready = if player.ready { "ready" } else { "not ready" };
+			println!("{} is {}", player.name, ready);
+		}
+		LobbyEvent::RoomUpdated(room) => {
+			println!("Room {} changed", room.id);
+			print_room(room);
+		}
+		LobbyEvent::RoomClosed { room } => {
+			println!("Room {} closed", room);
+		}
+		LobbyEvent::MatchStatusChanged { room, status } => {
+			println!("Room {} match is {:?}", room, status);
+		}
+	}
+}
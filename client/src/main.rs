@@ -3,8 +3,30 @@ use common::grpc::{CreateShapeRequest, CreateShapeResponse, SubscribeRequest};
 use tonic::Request;
 use tonic::transport::Channel;
 
+mod bot;
+mod lobby;
+mod repl;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	// `client lobby [url]` watches the lobby instead of joining a game,
+	// `client bots ...` plays with bots and `client repl [url]` takes
+	// commands from stdin
+	let mut args = std::env::args().skip(1);
+	match args.next().as_deref() {
+		Some("lobby") => {
+			let url =
+				args.next().unwrap_or_else(|| lobby::DEFAULT_LOBBY.into());
+			return lobby::watch(&url).await;
+		}
+		Some("bots") => return bot::run(args).await,
+		Some("repl") => {
+			let url = args.next().unwrap_or_else(|| bot::DEFAULT_SERVER.into());
+			return repl::run(&url).await;
+		}
+		_ => {}
+	}
+
 	// Build a transport channel explicitly (works even when generated client has no `connect()`).
 	let channel = Channel::from_static("http://127.0.0.1:50051")
 		.connect()
@@ -14,7 +36,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 	let mut identity = None;
 
 	// Subscribe
-	let mut stream = client.subscribe(SubscribeRequest {}).await?.into_inner();
+	let mut stream = client
+		.subscribe(SubscribeRequest::default())
+		.await?
+		.into_inner();
 
 	println!("Subscribed. Waiting for events...");
 	while let Some(ev) = stream.message().await? {
@@ -29,11 +54,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 				identity = Some(ident.player_id);
 				println!("Received player identity event");
 
-				let mut request = Request::new(CreateShapeRequest {});
+				let mut request =
+					Request::new(CreateShapeRequest { template_id: None });
 				request.metadata_mut().insert(
 					"player-id",
 					ident.player_id.to_string().parse().unwrap(),
 				);
+				request
+					.metadata_mut()
+					.insert("player-secret", ident.secret.parse().unwrap());
 
 				let resp: CreateShapeResponse =
 					client.create_shape(request).await?.into_inner();
@@ -0,0 +1,406 @@
+use crate::bot::{BotError, authorized, parse_point};
+use common::convert::parse_animation_segment;
+use common::grpc::shape_events_client::ShapeEventsClient;
+use common::grpc::{
+	ClearQueueRequest, CreateShapeRequest, GetTemplatesRequest, MoveTo,
+	PauseRequest, PlayerIdentity, ResumeRequest, SetQueueRequest,
+	SetSpeedRequest, SubscribeRequest, Task, Transfer, UnitDetails, event,
+	task,
+};
+use common::model::{self, TimeStamp, to_meters};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tonic::Streaming;
+use tonic::transport::Channel;
+
+const HELP: &str = "\
+create [template]           create a unit, of the catalog's first type
+                            unless a template id is given
+templates                   the unit types the match offers
+move <unit> <x> <y>         send a unit somewhere, in meters
+queue <unit> <task>...      replace a unit's tasks, each one either
+                            <x>,<y> or transfer:<resource>,<from>,<to>,<amount>
+clear <unit>                drop a unit's tasks
+units                       where every visible unit is right now
+watch <unit>                print the events about a unit as they come
+unwatch <unit>              stop printing them
+pause | resume | speed <x>  change the game clock
+help | quit";
+
+// The last Synchronize, to tell the game time between them
+struct TimeSync {
+	wall_time: TimeStamp,
+	game_time: TimeStamp,
+	// game milliseconds per wall millisecond, 0 while paused
+	rate: f64,
+}
+
+struct Unit {
+	queue: Vec<model::AnimationSegment>,
+	details: Option<UnitDetails>,
+}
+
+// What the server has shown this player so far
+#[derive(Default)]
+struct Mirror {
+	identity: Option<PlayerIdentity>,
+	sync: Option<TimeSync>,
+	units: BTreeMap<u64, Unit>,
+	watched: HashSet<u64>,
+}
+
+impl Mirror {
+	fn game_time(&self) -> TimeStamp {
+		let Some(sync) = &self.sync else {
+			return 0;
+		};
+		let elapsed = wall_time().saturating_sub(sync.wall_time) as f64;
+		sync.game_time + (elapsed * sync.rate) as TimeStamp
+	}
+
+	fn apply(&mut self, kind: event::Kind) {
+		let unit_id = match &kind {
+			event::Kind::Show(show) => show.unit_id,
+			event::Kind::Update(update) => update.unit_id,
+			event::Kind::Hide(hide) => hide.id,
+			_ => 0,
+		};
+		if self.watched.contains(&unit_id) {
+			println!("[unit {}] {:?}", unit_id, kind);
+		}
+		match kind {
+			event::Kind::PlayerIdentity(identity) => {
+				println!("Playing as {}", identity.player_id);
+				self.identity = Some(identity);
+			}
+			event::Kind::Begin(_) => self.units.clear(),
+			event::Kind::Synchronize(sync) => {
+				self.sync = Some(TimeSync {
+					wall_time: sync.wall_time,
+					game_time: sync.game_time,
+					rate: if sync.paused { 0.0 } else { sync.speed },
+				});
+			}
+			event::Kind::Show(show) => {
+				let queue = show.anim.map(|a| a.queue).unwrap_or_default();
+				self.units.insert(
+					unit_id,
+					Unit {
+						queue: parse_queue(&queue),
+						details: show.details,
+					},
+				);
+			}
+			event::Kind::Update(update) => {
+				let now = self.game_time();
+				let Some(unit) = self.units.get_mut(&unit_id) else {
+					eprintln!("Update for unknown unit {}", unit_id);
+					return;
+				};
+				let queue_before = std::mem::take(&mut unit.queue);
+				unit.queue = parse_queue(&update.queue);
+				if unit.queue.is_empty() {
+					// nothing left to do, the unit stays where it got to
+					unit.queue.extend(
+						current_segment(&queue_before, now)
+							.map(|s| stationary(s, now)),
+					);
+				}
+				if update.details.is_some() {
+					unit.details = update.details;
+				}
+			}
+			event::Kind::Hide(_) => {
+				self.units.remove(&unit_id);
+			}
+			event::Kind::Warning(warning) => {
+				eprintln!("Warning from server: {}", warning.message);
+			}
+		}
+	}
+
+	fn print_units(&self) {
+		let now = self.game_time();
+		println!("{} units at game time {}", self.units.len(), now);
+		for (unit_id, unit) in &self.units {
+			let Some(segment) = current_segment(&unit.queue, now) else {
+				println!("  {}: nowhere", unit_id);
+				continue;
+			};
+			let place = segment.place_at(now);
+			let moving = unit
+				.queue
+				.iter()
+				.any(|s| s.moves() && s.begin_time >= segment.begin_time);
+			let inventory = unit
+				.details
+				.as_ref()
+				.filter(|d| !d.inventory.is_empty())
+				.map(|d| {
+					d.inventory
+						.iter()
+						.map(|r| format!("{} {}", r.amount, r.name))
+						.collect::<Vec<_>>()
+						.join(", ")
+				});
+			println!(
+				"  {}: ({:.2}, {:.2}){}{}",
+				unit_id,
+				to_meters(place.point.x),
+				to_meters(place.point.y),
+				if moving { " moving" } else { "" },
+				inventory.map(|i| format!(" [{}]", i)).unwrap_or_default(),
+			);
+		}
+	}
+}
+
+fn wall_time() -> TimeStamp {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|d| d.as_millis() as TimeStamp)
+		.unwrap_or(0)
+}
+
+fn parse_queue(
+	queue: &[common::grpc::AnimationSegment],
+) -> Vec<model::AnimationSegment> {
+	queue
+		.iter()
+		.filter_map(|s| parse_animation_segment(s).ok())
+		.collect()
+}
+
+// The segment in effect at `time`, or the first one when it hasn't begun
+fn current_segment(
+	queue: &[model::AnimationSegment],
+	time: TimeStamp,
+) -> Option<&model::AnimationSegment> {
+	queue
+		.iter()
+		.rev()
+		.find(|s| s.begin_time <= time)
+		.or(queue.first())
+}
+
+fn stationary(
+	segment: &model::AnimationSegment,
+	time: TimeStamp,
+) -> model::AnimationSegment {
+	let place = segment.place_at(time);
+	model::AnimationSegment {
+		begin_time: time,
+		begin_location: place.point,
+		delta: None,
+		acceleration: None,
+		arc: None,
+		begin_orientation: place.orientation,
+		d_orientation: None,
+	}
+}
+
+fn parse_task(arg: &str) -> Option<Task> {
+	let kind = match arg.strip_prefix("transfer:") {
+		Some(transfer) => {
+			let mut values = transfer.split(',').map(str::trim);
+			let mut id = || values.next()?.parse::<u64>().ok();
+			let (resource_id, source_id, destination_id) =
+				(id()?, id()?, id()?);
+			let amount = values.next()?.parse().ok()?;
+			if values.next().is_some() {
+				return None;
+			}
+			task::Kind::Transfer(Transfer {
+				resource_id,
+				source_id,
+				destination_id,
+				amount,
+			})
+		}
+		None => task::Kind::Move(MoveTo {
+			destination: Some(parse_point(arg)?),
+		}),
+	};
+	Some(Task { kind: Some(kind) })
+}
+
+fn parse_unit(arg: Option<&str>) -> Result<u64, BotError> {
+	arg.and_then(|a| a.parse().ok())
+		.ok_or_else(|| "expected a unit id".into())
+}
+
+struct Repl {
+	client: ShapeEventsClient<Channel>,
+	mirror: Arc<Mutex<Mirror>>,
+}
+
+impl Repl {
+	fn mirror(&self) -> std::sync::MutexGuard<'_, Mirror> {
+		self.mirror.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn identity(&self) -> Result<PlayerIdentity, BotError> {
+		self.mirror()
+			.identity
+			.clone()
+			.ok_or_else(|| "the server hasn't said who we are yet".into())
+	}
+
+	async fn queue(
+		&mut self,
+		unit_id: u64,
+		tasks: Vec<Task>,
+	) -> Result<(), BotError> {
+		let request =
+			authorized(&self.identity()?, SetQueueRequest { unit_id, tasks })?;
+		self.client.queue(request).await?;
+		Ok(())
+	}
+
+	// Runs one line, false once the user is done
+	async fn execute(&mut self, line: &str) -> Result<bool, BotError> {
+		let mut words = line.split_whitespace();
+		let Some(command) = words.next() else {
+			return Ok(true);
+		};
+		match command {
+			"create" => {
+				let template_id = match words.next() {
+					Some(w) => Some(w.parse().map_err(|_| "bad template id")?),
+					None => None,
+				};
+				let request = authorized(
+					&self.identity()?,
+					CreateShapeRequest { template_id },
+				)?;
+				let id = self.client.create_shape(request).await?;
+				println!("Created unit {}", id.into_inner().id);
+			}
+			"templates" => {
+				let request =
+					authorized(&self.identity()?, GetTemplatesRequest {})?;
+				let response = self.client.get_templates(request).await?;
+				for template in response.into_inner().templates {
+					// `-` for what the template doesn't have
+					let or_dash = |v: Option<String>| v.unwrap_or("-".into());
+					println!(
+						"  {}: {} speed {} health {} cargo {}",
+						template.id,
+						template.name,
+						or_dash(
+							template.speed.map(|s| to_meters(s).to_string())
+						),
+						or_dash(template.health.map(|h| h.to_string())),
+						or_dash(template.cargo.map(|c| c.to_string())),
+					);
+				}
+			}
+			"move" => {
+				let unit_id = parse_unit(words.next())?;
+				let (x, y) = (words.next(), words.next());
+				let task = x
+					.zip(y)
+					.and_then(|(x, y)| parse_task(&format!("{},{}", x, y)))
+					.ok_or("expected a destination like 3 -2.5")?;
+				self.queue(unit_id, vec![task]).await?;
+			}
+			"queue" => {
+				let unit_id = parse_unit(words.next())?;
+				let tasks = words
+					.map(|w| parse_task(w).ok_or(format!("bad task {}", w)))
+					.collect::<Result<Vec<_>, _>>()?;
+				self.queue(unit_id, tasks).await?;
+			}
+			"clear" => {
+				let unit_id = parse_unit(words.next())?;
+				let request = authorized(
+					&self.identity()?,
+					ClearQueueRequest { unit_id },
+				)?;
+				self.client.clear_queue(request).await?;
+			}
+			"units" => self.mirror().print_units(),
+			"watch" => {
+				let unit_id = parse_unit(words.next())?;
+				self.mirror().watched.insert(unit_id);
+			}
+			"unwatch" => {
+				let unit_id = parse_unit(words.next())?;
+				self.mirror().watched.remove(&unit_id);
+			}
+			"pause" => {
+				let request = authorized(&self.identity()?, PauseRequest {})?;
+				self.client.pause(request).await?;
+			}
+			"resume" => {
+				let request = authorized(&self.identity()?, ResumeRequest {})?;
+				self.client.resume(request).await?;
+			}
+			"speed" => {
+				let speed = words
+					.next()
+					.and_then(|s| s.parse().ok())
+					.ok_or("expected a speed like 2.5")?;
+				let request =
+					authorized(&self.identity()?, SetSpeedRequest { speed })?;
+				self.client.set_speed(request).await?;
+			}
+			"help" => println!("{}", HELP),
+			"quit" | "exit" => return Ok(false),
+			_ => println!("Unknown command {}, try help", command),
+		}
+		Ok(true)
+	}
+}
+
+async fn follow(
+	mut stream: Streaming<common::grpc::Event>,
+	mirror: Arc<Mutex<Mirror>>,
+) {
+	loop {
+		match stream.message().await {
+			Ok(Some(ev)) => {
+				if let Some(kind) = ev.kind {
+					mirror
+						.lock()
+						.unwrap_or_else(|e| e.into_inner())
+						.apply(kind);
+				}
+			}
+			Ok(None) => {
+				println!("The server closed the stream");
+				return;
+			}
+			Err(status) => {
+				eprintln!("Event stream failed: {}", status);
+				return;
+			}
+		}
+	}
+}
+
+// Joins the default match and reads commands from stdin until quit
+pub async fn run(server: &str) -> Result<(), Box<dyn std::error::Error>> {
+	let channel = Channel::from_shared(server.to_string())?.connect().await?;
+	let mut client = ShapeEventsClient::new(channel);
+	let stream = client
+		.subscribe(SubscribeRequest::default())
+		.await?
+		.into_inner();
+
+	let mirror = Arc::new(Mutex::new(Mirror::default()));
+	tokio::spawn(follow(stream, mirror.clone()));
+
+	let mut repl = Repl { client, mirror };
+	println!("Connected to {}, type help for commands", server);
+	let mut lines = BufReader::new(tokio::io::stdin()).lines();
+	while let Some(line) = lines.next_line().await? {
+		match repl.execute(&line).await {
+			Ok(true) => {}
+			Ok(false) => break,
+			Err(e) => println!("Error: {}", e),
+		}
+	}
+	Ok(())
+}
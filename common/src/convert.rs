@@ -48,10 +48,7 @@ impl From<model::Point> for grpc::Point {
 
 impl From<model::Delta> for grpc::Delta {
 	fn from(d: model::Delta) -> Self {
-		grpc::Delta {
-			dx: d.dx as f64,
-			dy: d.dy as f64,
-		}
+		grpc::Delta { dx: d.dx, dy: d.dy }
 	}
 }
 
@@ -60,6 +57,8 @@ impl From<model::AnimationSegment> for grpc::AnimationSegment {
 		grpc::AnimationSegment {
 			begin_location: Some(p.begin_location.into()),
 			delta: p.delta.map(Into::into),
+			acceleration: p.acceleration.map(Into::into),
+			arc: p.arc.map(Into::into),
 			begin_time: p.begin_time,
 			begin_orientation: p.begin_orientation,
 			d_orientation: p.d_orientation,
@@ -67,11 +66,20 @@ impl From<model::AnimationSegment> for grpc::AnimationSegment {
 	}
 }
 
+impl From<model::CircularArc> for grpc::CircularArc {
+	fn from(a: model::CircularArc) -> Self {
+		grpc::CircularArc {
+			center: Some(a.center.into()),
+			angular_velocity: a.angular_velocity,
+		}
+	}
+}
+
 impl From<model::Animatable> for grpc::Animatable {
 	fn from(a: model::Animatable) -> Self {
 		grpc::Animatable {
 			unit_id: a.unit_id,
-			display_type: a.display_type as u32,
+			template_id: a.template_id,
 			// shape: Some(a.shape.into()),
 			// fill: a.fill,
 			// color: Some(grpc::Color {
@@ -84,6 +92,25 @@ impl From<model::Animatable> for grpc::Animatable {
 	}
 }
 
+impl From<model::ResourceAmount> for grpc::ResourceAmount {
+	fn from(r: model::ResourceAmount) -> Self {
+		grpc::ResourceAmount {
+			resource_id: r.resource_id,
+			name: r.name,
+			amount: r.amount,
+		}
+	}
+}
+
+impl From<model::UnitDetails> for grpc::UnitDetails {
+	fn from(d: model::UnitDetails) -> Self {
+		grpc::UnitDetails {
+			inventory_capacity: d.inventory_capacity,
+			inventory: d.inventory.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
 impl From<model::Animatable> for grpc::Show {
 	fn from(anim: model::Animatable) -> Self {
 		// let location =
@@ -136,10 +163,11 @@ impl From<grpc::AnimationSegment>
 						"AnimationSegment.begin_location",
 					)
 				})?,
-			delta: p.delta.map(|d| model::Delta {
-				dx: d.dx as f32,
-				dy: d.dy as f32,
-			}),
+			delta: p.delta.map(|d| model::Delta { dx: d.dx, dy: d.dy }),
+			acceleration: p
+				.acceleration
+				.map(|d| model::Delta { dx: d.dx, dy: d.dy }),
+			arc: p.arc.as_ref().map(parse_arc).transpose()?,
 			begin_time: p.begin_time,
 			begin_orientation: p.begin_orientation,
 			d_orientation: p.d_orientation,
@@ -162,17 +190,30 @@ pub fn parse_animation_segment(
 			})?,
 		delta: p.delta.as_ref().map(
 			// Into::into
-			|d| model::Delta {
-				dx: d.dx as f32,
-				dy: d.dy as f32,
-			},
+			|d| model::Delta { dx: d.dx, dy: d.dy },
 		),
+		acceleration: p
+			.acceleration
+			.as_ref()
+			.map(|d| model::Delta { dx: d.dx, dy: d.dy }),
+		arc: p.arc.as_ref().map(parse_arc).transpose()?,
 		begin_time: p.begin_time,
 		begin_orientation: p.begin_orientation,
 		d_orientation: p.d_orientation,
 	})
 }
 
+fn parse_arc(a: &grpc::CircularArc) -> Result<model::CircularArc, ParseError> {
+	Ok(model::CircularArc {
+		center: a
+			.center
+			.as_ref()
+			.map(model::Point::from)
+			.ok_or(ParseError::MissingRequiredField("CircularArc.center"))?,
+		angular_velocity: a.angular_velocity,
+	})
+}
+
 // impl From<model::WarningContent> for grpc::Warning {
 // 	fn from(w: model::WarningContent) -> Self {
 // 		grpc::Warning { message: w.message }
@@ -201,10 +242,13 @@ impl From<&grpc::Task> for Result<model::Task, ParseError> {
 					})?
 					.into(),
 			)),
-			grpc::task::Kind::Transfer(_) => {
-				Err(ParseError::MissingRequiredField(
-					"Task.Transfer not implemented",
-				))
+			grpc::task::Kind::Transfer(t) => {
+				Ok(model::Task::Transfer(model::Transfer {
+					resource_id: t.resource_id,
+					amount: t.amount,
+					source_id: t.source_id,
+					destination_id: t.destination_id,
+				}))
 			}
 		}
 	}
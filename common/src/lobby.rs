@@ -5,9 +5,138 @@ pub struct Player {
     pub id: u64,
     pub name: String,
     pub ready: bool,
+    // the room they are in, if any
+    pub room: Option<u64>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MatchStatus {
+    Waiting,
+    // everyone is ready, waiting on the game server
+    Starting,
+    Started,
+}
+
+// How the room's match is set up, the host can change these until it starts
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchSettings {
+    // clock speed the match starts at
+    pub speed: f64,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self { speed: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Room {
+    pub id: u64,
+    pub name: String,
+    // player id of whoever runs the room
+    pub host: u64,
+    pub max_players: usize,
+    pub settings: MatchSettings,
+    pub players: Vec<Player>,
+    pub status: MatchStatus,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Lobby {
     pub players: Vec<Player>,
+    pub rooms: Vec<Room>,
+}
+
+// What a lobby player needs to get into the match: subscribe to
+// `game_server` with `ticket` as the join ticket.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinTicket {
+    pub game_server: String,
+    pub player_id: u64,
+    pub ticket: String,
+}
+
+// Pushed to everyone watching the lobby as things change
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum LobbyEvent {
+    // everything at once, sent first and whenever a watcher falls behind
+    Snapshot(Lobby),
+    PlayerCreated(Player),
+    PlayerDeleted { player_id: u64 },
+    PlayerJoined { room: u64, player: Player },
+    PlayerLeft { room: u64, player_id: u64, kicked: bool },
+    ReadyChanged(Player),
+    // created, renamed, settings changed or a new host
+    RoomUpdated(Room),
+    RoomClosed { room: u64 },
+    MatchStatusChanged { room: u64, status: MatchStatus },
+}
+
+impl Lobby {
+    // Keeps a watcher's copy of the lobby current
+    pub fn apply(&mut self, event: &LobbyEvent) {
+        match event {
+            LobbyEvent::Snapshot(lobby) => *self = lobby.clone(),
+            LobbyEvent::PlayerCreated(player) => self.upsert_player(player),
+            LobbyEvent::PlayerDeleted { player_id } => {
+                self.players.retain(|p| p.id != *player_id);
+            }
+            LobbyEvent::PlayerJoined { room, player } => {
+                self.upsert_player(player);
+                if let Some(room) = self.rooms.iter_mut().find(|r| r.id == *room) {
+                    room.players.retain(|p| p.id != player.id);
+                    room.players.push(player.clone());
+                }
+            }
+            LobbyEvent::PlayerLeft { room, player_id, .. } => {
+                if let Some(player) = self.players.iter_mut().find(|p| p.id == *player_id) {
+                    player.room = None;
+                    player.ready = false;
+                }
+                if let Some(room) = self.rooms.iter_mut().find(|r| r.id == *room) {
+                    room.players.retain(|p| p.id != *player_id);
+                }
+            }
+            LobbyEvent::ReadyChanged(player) => {
+                self.upsert_player(player);
+                for room in &mut self.rooms {
+                    for p in room.players.iter_mut().filter(|p| p.id == player.id) {
+                        *p = player.clone();
+                    }
+                }
+            }
+            LobbyEvent::RoomUpdated(room) => {
+                match self.rooms.iter_mut().find(|r| r.id == room.id) {
+                    Some(existing) => *existing = room.clone(),
+                    None => {
+                        self.rooms.push(room.clone());
+                        self.rooms.sort_by_key(|r| r.id);
+                    }
+                }
+            }
+            LobbyEvent::RoomClosed { room } => {
+                self.rooms.retain(|r| r.id != *room);
+                for player in self.players.iter_mut().filter(|p| p.room == Some(*room)) {
+                    player.room = None;
+                    player.ready = false;
+                }
+            }
+            LobbyEvent::MatchStatusChanged { room, status } => {
+                if let Some(room) = self.rooms.iter_mut().find(|r| r.id == *room) {
+                    room.status = status.clone();
+                }
+            }
+        }
+    }
+
+    fn upsert_player(&mut self, player: &Player) {
+        match self.players.iter_mut().find(|p| p.id == player.id) {
+            Some(existing) => *existing = player.clone(),
+            None => {
+                self.players.push(player.clone());
+                self.players.sort_by_key(|p| p.id);
+            }
+        }
+    }
 }
@@ -1,46 +1,66 @@
 use crate::grpc;
 use serde::{Deserialize, Serialize};
 
-// Make this an int as well
-pub type Coord = f32;
+// Fixed point: METERS units per meter, so the simulation is integer math and
+// comes out the same on every platform
+pub type Coord = i64;
+// game time, in milliseconds
 pub type TimeStamp = u64;
 pub type Id = u64;
+// radians, turning from the x axis towards the y axis
 pub type Orientation = f32;
 pub type UnitId = u64;
 pub type TaskId = u64;
-pub type Speed = f32;
+// Coord per second
+pub type Speed = Coord;
 pub type TemplateId = u32;
 
 pub type ResourceId = u64;
 pub type PlayerId = u64;
 
-pub const METERS: Coord = 1_000 as Coord;
-pub const SECONDS: TimeStamp = 1_000_000 as TimeStamp;
+pub const METERS: Coord = 1_000;
+pub const SECONDS: TimeStamp = 1_000;
 
-// todo just do nanoseconds...
-// pub const TIME_PER_SECOND: u64 = 1_000;
+// For display only, never feed these back into the simulation
+pub fn to_meters(c: Coord) -> f64 {
+	c as f64 / METERS as f64
+}
 
-#[derive(Clone, Debug, Serialize, Deserialize, Copy)]
-pub enum UnitDisplayType {
-	SimpleUnit = 1,
+pub fn from_meters(m: f64) -> Coord {
+	(m * METERS as f64).round() as Coord
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Health {
 	pub current: i32,
 	pub max: i32,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Task {
 	MoveTo(Point),
 	Transfer(Transfer),
 }
 
+// Only the owner of a unit gets to see these
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitDetails {
+	pub inventory_capacity: i32,
+	pub inventory: Vec<ResourceAmount>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceAmount {
+	pub resource_id: ResourceId,
+	pub name: String,
+	pub amount: i32,
+}
+
 pub struct Tasks {
 	pub tasks: Vec<(u64, Task)>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transfer {
 	pub resource_id: ResourceId,
 	pub amount: i32,
@@ -55,25 +75,14 @@ pub enum Shape {
 	Rectangle(Coord, Coord),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Point {
 	pub x: Coord,
 	pub y: Coord,
 }
 
-impl PartialEq for Point {
-	// fn neq(&self, other: &Self) -> bool {
-	// 	(self.x - other.x).abs() > 1e-6 || (self.y - other.y).abs() > 1e-6
-	// }
-	fn eq(&self, other: &Self) -> bool {
-		// Todo: this is only used in an assert
-		(self.x - other.x).abs() <= 1e-6 && (self.y - other.y).abs() <= 1e-6
-	}
-}
-
-impl Eq for Point {}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// A velocity, in Coord per second
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Delta {
 	pub dx: Coord,
 	pub dy: Coord,
@@ -87,45 +96,173 @@ pub struct OrientedPoint {
 }
 
 // this should be any task
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AnimationSegment {
 	pub begin_time: TimeStamp,
 
 	pub begin_location: Point,
+	// the velocity at begin_time
 	pub delta: Option<Delta>,
+	// Coord per second per second, changes delta as time goes by. Slowing
+	// down stops where the velocity reaches zero rather than backing up.
+	#[serde(default)]
+	pub acceleration: Option<Delta>,
+	// going around a circle instead, delta and acceleration are ignored
+	#[serde(default)]
+	pub arc: Option<CircularArc>,
 
 	// move orientation out...
 	pub begin_orientation: Orientation,
+	// radians per second
 	pub d_orientation: Option<Orientation>,
 	// add a progress here?
 }
 
+// Circling `center` from the begin location at a steady angular velocity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CircularArc {
+	pub center: Point,
+	// radians per second, positive turns from the x axis towards the y axis
+	pub angular_velocity: Orientation,
+}
+
 impl AnimationSegment {
 	pub fn place_at(&self, time: TimeStamp) -> OrientedPoint {
-		let d_t = time.saturating_sub(self.begin_time) as f64;
-		let dx = self
-			.delta
-			.as_ref()
-			.map_or(0.0, |d| d.dx as f64 * (d_t as f64));
-		let dy = self
-			.delta
-			.as_ref()
-			.map_or(0.0, |d| d.dy as f64 * (d_t as f64));
+		let d_t = time.saturating_sub(self.begin_time);
 		OrientedPoint {
-			point: Point {
-				x: self.begin_location.x + dx as Coord,
-				y: self.begin_location.y + dy as Coord,
+			point: match &self.arc {
+				Some(arc) => arc.place_at(&self.begin_location, d_t),
+				None => self.place_along_line(d_t),
 			},
 			orientation: self.begin_orientation
-				+ self.d_orientation.unwrap_or(0.0),
+				+ self.d_orientation.map_or(0.0, |d| {
+					d * d_t as Orientation / SECONDS as Orientation
+				}),
+		}
+	}
+
+	// Whether the unit goes anywhere during this segment
+	pub fn moves(&self) -> bool {
+		self.delta.is_some() || self.arc.is_some()
+	}
+
+	// Anything but a constant velocity is curved in time or space
+	pub fn is_linear(&self) -> bool {
+		self.acceleration.is_none() && self.arc.is_none()
+	}
+
+	// How long the straight pieces of a curved segment can be, in
+	// milliseconds, to stay within SWEEP_TOLERANCE of it. A chord across an
+	// arc of radius r bulges out by at most r (ω t)² / 8, and one along an
+	// acceleration a by a t² / 8.
+	fn sweep_step(&self) -> TimeStamp {
+		let bulge = 8.0 * SWEEP_TOLERANCE as f64;
+		let seconds = match (&self.arc, &self.acceleration) {
+			(Some(arc), _) => {
+				let radius =
+					self.begin_location.distance_to(&arc.center) as f64;
+				(bulge / radius).sqrt() / arc.angular_velocity.abs() as f64
+			}
+			(None, Some(acceleration)) => {
+				(bulge / acceleration.length() as f64).sqrt()
+			}
+			(None, None) => return TimeStamp::MAX,
+		};
+		// a circle of no size or no acceleration at all comes out infinite,
+		// which saturates
+		((seconds * SECONDS as f64) as TimeStamp).max(1)
+	}
+
+	fn place_along_line(&self, d_t: TimeStamp) -> Point {
+		let zero = Delta { dx: 0, dy: 0 };
+		let delta = self.delta.as_ref().unwrap_or(&zero);
+		let Some(acceleration) = &self.acceleration else {
+			return Point {
+				x: self.begin_location.x + delta.travelled(delta.dx, d_t),
+				y: self.begin_location.y + delta.travelled(delta.dy, d_t),
+			};
+		};
+		let (v, a) = (delta.as_i128(), acceleration.as_i128());
+		let mut d_t = d_t as i128;
+		// slowing down stops at zero
+		let v_dot_a = v.0 * a.0 + v.1 * a.1;
+		if v_dot_a < 0 {
+			let a_dot_a = a.0 * a.0 + a.1 * a.1;
+			d_t = d_t.min(-v_dot_a * SECONDS as i128 / a_dot_a);
+		}
+		let seconds = SECONDS as i128;
+		let moved = |v: i128, a: i128| {
+			(v * d_t / seconds + a * d_t * d_t / (2 * seconds * seconds))
+				as Coord
+		};
+		Point {
+			x: self.begin_location.x + moved(v.0, a.0),
+			y: self.begin_location.y + moved(v.1, a.1),
 		}
 	}
 }
 
-#[derive(Clone, Debug)]
+impl CircularArc {
+	// The server and the browser have to agree to the millimeter, and their
+	// sin and cos don't always, so the rotation is done in fixed point
+	fn place_at(&self, begin: &Point, d_t: TimeStamp) -> Point {
+		let angle = self.angular_velocity as f64 * d_t as f64 / SECONDS as f64;
+		let (sin, cos) =
+			fixed_sin_cos((angle * FIXED_ONE as f64).round() as i64);
+		let x = (begin.x - self.center.x) as i128;
+		let y = (begin.y - self.center.y) as i128;
+		let rotated =
+			|v: i128| ((v + FIXED_ONE as i128 / 2) >> FIXED_BITS) as Coord;
+		Point {
+			x: self.center.x + rotated(x * cos as i128 - y * sin as i128),
+			y: self.center.y + rotated(x * sin as i128 + y * cos as i128),
+		}
+	}
+}
+
+// Fixed point numbers with this many bits after the point
+const FIXED_BITS: u32 = 30;
+const FIXED_ONE: i64 = 1 << FIXED_BITS;
+// pi / 2 in fixed point
+const FIXED_HALF_PI: i64 = 1_686_629_713;
+
+// Sine and cosine of a fixed point angle, in fixed point, using nothing but
+// integer arithmetic so every platform gets the same bits. Brought within
+// an eighth of a turn of zero, where a few terms of the Taylor series are
+// good to about a billionth.
+fn fixed_sin_cos(angle: i64) -> (i64, i64) {
+	let quarter = (angle + FIXED_HALF_PI / 2).div_euclid(FIXED_HALF_PI);
+	let r = (angle - quarter * FIXED_HALF_PI) as i128;
+	let r2 = (r * r) >> FIXED_BITS;
+	let one = FIXED_ONE as i128;
+	// 1 - r²/(2·3) (1 - r²/(4·5) (1 - ...)) and the like from the inside out
+	let series = |divisors: &[i128]| {
+		divisors.iter().fold(one, |term, divisor| {
+			one - ((r2 * term) >> FIXED_BITS) / divisor
+		})
+	};
+	let sin = ((r * series(&[110, 72, 42, 20, 6])) >> FIXED_BITS) as i64;
+	let cos = series(&[132, 90, 56, 30, 12, 2]) as i64;
+	match quarter.rem_euclid(4) {
+		0 => (sin, cos),
+		1 => (cos, -sin),
+		2 => (-sin, -cos),
+		_ => (-cos, sin),
+	}
+}
+
+// The smallest turn from `from` to `to`, negative when it goes the other way
+pub fn turn_between(from: Orientation, to: Orientation) -> Orientation {
+	use std::f32::consts::{PI, TAU};
+	let turn = (to - from).rem_euclid(TAU);
+	if turn > PI { turn - TAU } else { turn }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Animatable {
 	pub unit_id: UnitId,
-	pub display_type: UnitDisplayType,
+	// what the unit looks like comes from its template in the catalog
+	pub template_id: TemplateId,
 	pub queue: Vec<AnimationSegment>,
 	// pub queue: Vec<grpc::AnimationSegment>,
 	// the location for stationary objects...
@@ -142,22 +279,43 @@ pub enum Message {
 impl Delta {
 	pub fn between(from: &Point, to: &Point) -> Self {
 		Self {
-			dx: (to.x - from.x) as Coord,
-			dy: (to.y - from.y) as Coord,
+			dx: to.x - from.x,
+			dy: to.y - from.y,
 		}
 	}
+	// The way something moving along this delta faces
+	pub fn heading(&self) -> Orientation {
+		(self.dy as f64).atan2(self.dx as f64) as Orientation
+	}
+	pub fn length(&self) -> Coord {
+		isqrt(
+			self.dx as i128 * self.dx as i128
+				+ self.dy as i128 * self.dy as i128,
+		)
+	}
 	pub fn normalize(&self, radius: Coord) -> Delta {
 		// todo: can use the radius to scale differently...
-		let len = (self.dx * self.dx + self.dy * self.dy).sqrt();
-		if len < 1e-6 {
-			Delta { dx: 0.0, dy: 0.0 }
+		let len = self.length();
+		if len == 0 {
+			Delta { dx: 0, dy: 0 }
 		} else {
 			Delta {
-				dx: self.dx / len * radius,
-				dy: self.dy / len * radius,
+				dx: (self.dx as i128 * radius as i128 / len as i128) as Coord,
+				dy: (self.dy as i128 * radius as i128 / len as i128) as Coord,
 			}
 		}
 	}
+	fn as_i128(&self) -> (i128, i128) {
+		(self.dx as i128, self.dy as i128)
+	}
+	// How far one component of this velocity goes in `d_t` milliseconds
+	fn travelled(&self, component: Coord, d_t: TimeStamp) -> Coord {
+		(component as i128 * d_t as i128 / SECONDS as i128) as Coord
+	}
+}
+
+fn isqrt(value: i128) -> Coord {
+	value.max(0).isqrt() as Coord
 }
 
 impl Default for Tasks {
@@ -167,10 +325,15 @@ impl Default for Tasks {
 }
 
 impl Point {
-	pub fn distance_to(&self, other: &Point) -> f64 {
-		let dx = self.x - other.x;
-		let dy = self.y - other.y;
-		(dx * dx + dy * dy).sqrt() as f64
+	pub fn distance_to(&self, other: &Point) -> Coord {
+		Delta::between(self, other).length()
+	}
+
+	pub fn moved_by(&self, delta: &Delta) -> Point {
+		Point {
+			x: self.x + delta.dx,
+			y: self.y + delta.dy,
+		}
 	}
 }
 
@@ -215,12 +378,12 @@ impl From<(&Shape, &Point)> for PositionedShape {
 			}),
 			Shape::Rectangle(w, h) => PositionedShape::Rectangle(Rec {
 				min: Point {
-					x: t.1.x - *w / (2.0 as Coord),
-					y: t.1.y - *h / (2.0 as Coord),
+					x: t.1.x - *w / 2,
+					y: t.1.y - *h / 2,
 				},
 				max: Point {
-					x: t.1.x + *w / (2.0 as Coord),
-					y: t.1.y + *h / (2.0 as Coord),
+					x: t.1.x + *w / 2,
+					y: t.1.y + *h / 2,
 				},
 			}),
 		}
@@ -270,6 +433,7 @@ impl PositionedShape {
 	}
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Rec {
 	pub min: Point,
 	pub max: Point,
@@ -325,8 +489,8 @@ impl Rec {
 	}
 	pub fn center(&self) -> Point {
 		Point {
-			x: (self.min.x + self.max.x) / 2.0,
-			y: (self.min.y + self.max.y) / 2.0,
+			x: (self.min.x + self.max.x) / 2,
+			y: (self.min.y + self.max.y) / 2,
 		}
 	}
 }
@@ -361,264 +525,768 @@ impl CenteredCircle {
 	}
 }
 
+// Contacts closer than this (in milliseconds) to the start of a window count
+// as happening at the start, so touching shapes that move towards each other
+// still collide.
+const CONTACT_EPSILON: f64 = 1e-6;
+
+// Curved and accelerating segments are cut into straight volumes short
+// enough that none of them strays further than this from the real path
+const SWEEP_TOLERANCE: Coord = 10;
+
+// A shape moving in a straight line between two timed positions
+#[derive(Clone, Debug)]
 pub struct ShapeVolume {
 	// add orientation
-	shape: Shape,
-	begin: (TimeStamp, Point),
-	end: (TimeStamp, Point),
+	pub shape: Shape,
+	pub begin: (TimeStamp, Point),
+	pub end: (TimeStamp, Point),
+}
+
+// How two swept shapes meet within a window. Times are milliseconds from the
+// start of the window.
+struct SweptContact {
+	// they overlap at some point of the window
+	overlaps: bool,
+	// the first time they go from apart (or touching) to overlapping
+	entry: Option<f64>,
 }
 
 impl ShapeVolume {
+	pub fn new(
+		shape: Shape,
+		begin: (TimeStamp, Point),
+		end: (TimeStamp, Point),
+	) -> Self {
+		Self { shape, begin, end }
+	}
+
+	pub fn stationary(
+		shape: Shape,
+		location: Point,
+		begin_time: TimeStamp,
+		end_time: TimeStamp,
+	) -> Self {
+		Self {
+			shape,
+			begin: (begin_time, location.clone()),
+			end: (end_time, location),
+		}
+	}
+
+	pub fn from_segment(
+		shape: &Shape,
+		segment: &AnimationSegment,
+		end_time: TimeStamp,
+	) -> Self {
+		Self {
+			shape: shape.clone(),
+			begin: (segment.begin_time, segment.begin_location.clone()),
+			end: (end_time, segment.place_at(end_time).point),
+		}
+	}
+
+	// Straight volumes that follow the segment until `end_time`, closely
+	// enough for curves and changing speeds
+	pub fn along_segment(
+		shape: &Shape,
+		segment: &AnimationSegment,
+		end_time: TimeStamp,
+	) -> Vec<Self> {
+		if segment.is_linear() {
+			return vec![Self::from_segment(shape, segment, end_time)];
+		}
+		let step = segment.sweep_step();
+		let mut volumes = Vec::new();
+		let mut begin = (segment.begin_time, segment.begin_location.clone());
+		while begin.0 < end_time {
+			let time = begin.0.saturating_add(step).min(end_time);
+			let end = (time, segment.place_at(time).point);
+			volumes.push(Self::new(shape.clone(), begin, end.clone()));
+			begin = end;
+		}
+		if volumes.is_empty() {
+			volumes.push(Self::from_segment(shape, segment, end_time));
+		}
+		volumes
+	}
+
+	pub fn location_at(&self, time: TimeStamp) -> Point {
+		let (t0, p0) = &self.begin;
+		let (t1, p1) = &self.end;
+		if time <= *t0 || t1 <= t0 {
+			return p0.clone();
+		}
+		if time >= *t1 {
+			return p1.clone();
+		}
+		let lerp = |c0: Coord, c1: Coord| {
+			c0 + ((c1 - c0) as i128 * (time - t0) as i128 / (t1 - t0) as i128)
+				as Coord
+		};
+		Point {
+			x: lerp(p0.x, p1.x),
+			y: lerp(p0.y, p1.y),
+		}
+	}
+
+	// Rectangle `a` moves from a.0 to a.1 while `b` moves from b.0 to b.1,
+	// both between t1 and t2
 	pub fn moved_rect_intersects_rect(
-		_t1: TimeStamp,
-		_r1: &Rec,
-		_t2: TimeStamp,
-		_r2: &Rec,
+		t1: TimeStamp,
+		t2: TimeStamp,
+		a: (&Rec, &Rec),
+		b: (&Rec, &Rec),
 	) -> bool {
-		/* t, x, y
-		 such that:
+		rects_contact(t2.saturating_sub(t1) as f64, a, b).overlaps
+	}
 
-		 a = (t - t1) / (t2 - t1)
+	// Whether the two shapes overlap at any time both volumes exist
+	pub fn intersects(&self, other: &ShapeVolume) -> bool {
+		self.shared_window(other, 0)
+			.map(|window| self.contact(other, window).overlaps)
+			.unwrap_or(false)
+	}
 
-		x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
-		*/
-		false
+	// The earliest time, no sooner than `from`, at which the two shapes start
+	// to overlap. Shapes that already overlap at the start of the window are
+	// left alone until they separate.
+	pub fn first_contact(
+		&self,
+		other: &ShapeVolume,
+		from: TimeStamp,
+	) -> Option<TimeStamp> {
+		let window = self.shared_window(other, from)?;
+		self.contact(other, window)
+			.entry
+			.map(|s| window.0 + s as TimeStamp)
 	}
 
-	pub fn intersects(&self, _other: &ShapeVolume) -> bool {
-		false
+	// When, no sooner than `from`, the centers of the two are at most
+	// `distance` apart, whatever their shapes. Rounded inwards to whole
+	// milliseconds, the last one included.
+	pub fn within(
+		&self,
+		other: &ShapeVolume,
+		distance: Coord,
+		from: TimeStamp,
+	) -> Option<(TimeStamp, TimeStamp)> {
+		let window = self.shared_window(other, from)?;
+		let dt = (window.1 - window.0) as f64;
+		let d0 = sub(&other.location_at(window.0), &self.location_at(window.0));
+		let d1 = sub(&other.location_at(window.1), &self.location_at(window.1));
+		let v = velocity(d0, d1, dt);
+		let r = distance as f64;
+
+		let qa = v.0 * v.0 + v.1 * v.1;
+		let qb = 2.0 * (d0.0 * v.0 + d0.1 * v.1);
+		let qc = d0.0 * d0.0 + d0.1 * d0.1 - r * r;
+		let (lo, hi) = if qa == 0.0 {
+			if qc > 0.0 {
+				return None;
+			}
+			(0.0, dt)
+		} else {
+			let d = qb * qb - 4.0 * qa * qc;
+			if d < 0.0 {
+				return None;
+			}
+			((-qb - d.sqrt()) / (2.0 * qa), (-qb + d.sqrt()) / (2.0 * qa))
+		};
+		let (lo, hi) = (lo.max(0.0).ceil(), hi.min(dt).floor());
+		if lo > hi {
+			return None;
+		}
+		// standing still lasts until TimeStamp::MAX, past what f64 holds
+		let at = |s: f64| {
+			if s >= dt {
+				window.1
+			} else {
+				window.0 + s as TimeStamp
+			}
+		};
+		Some((at(lo), at(hi)))
+	}
+
+	fn shared_window(
+		&self,
+		other: &ShapeVolume,
+		from: TimeStamp,
+	) -> Option<(TimeStamp, TimeStamp)> {
+		let begin = self.begin.0.max(other.begin.0).max(from);
+		let end = self.end.0.min(other.end.0);
+		if begin <= end {
+			Some((begin, end))
+		} else {
+			None
+		}
+	}
+
+	fn contact(
+		&self,
+		other: &ShapeVolume,
+		window: (TimeStamp, TimeStamp),
+	) -> SweptContact {
+		let dt = (window.1 - window.0) as f64;
+		let a = (self.location_at(window.0), self.location_at(window.1));
+		let b = (other.location_at(window.0), other.location_at(window.1));
+		match (&self.shape, &other.shape) {
+			(Shape::Circle(ra), Shape::Circle(rb)) => {
+				circles_contact(dt, (&a.0, &a.1), (&b.0, &b.1), *ra + *rb)
+			}
+			(Shape::Rectangle(..), Shape::Rectangle(..)) => {
+				let ra = (
+					PositionedShape::rec(&self.shape, &a.0),
+					PositionedShape::rec(&self.shape, &a.1),
+				);
+				let rb = (
+					PositionedShape::rec(&other.shape, &b.0),
+					PositionedShape::rec(&other.shape, &b.1),
+				);
+				rects_contact(dt, (&ra.0, &ra.1), (&rb.0, &rb.1))
+			}
+			(Shape::Rectangle(..), Shape::Circle(r)) => {
+				let rect = (
+					PositionedShape::rec(&self.shape, &a.0),
+					PositionedShape::rec(&self.shape, &a.1),
+				);
+				rect_circle_contact(dt, (&rect.0, &rect.1), (&b.0, &b.1), *r)
+			}
+			(Shape::Circle(r), Shape::Rectangle(..)) => {
+				let rect = (
+					PositionedShape::rec(&other.shape, &b.0),
+					PositionedShape::rec(&other.shape, &b.1),
+				);
+				rect_circle_contact(dt, (&rect.0, &rect.1), (&a.0, &a.1), *r)
+			}
+		}
 	}
 }
 
-// https://chatgpt.com/c/69560574-5948-832f-9839-40b252a6e799
-// fn axis_interval(
-// 	t1: f64,
-// 	t2: f64,
-// 	w1: f64,
-// 	w2: f64,
-// 	d0: f64,
-// 	d1: f64,
-// ) -> Option<(f64, f64)> {
-// 	let dt = t2 - t1;
-// 	let l = -w2;
-// 	let u = w1;
-
-// 	let v = (d1 - d0) / dt;
-
-// 	if v == 0.0 {
-// 		if d0 >= l && d0 <= u {
-// 			return Some((t1, t2));
-// 		} else {
-// 			return None;
-// 		}
-// 	}
-
-// 	let ta = t1 + (l - d0) / v;
-// 	let tb = t1 + (u - d0) / v;
-
-// 	let enter = ta.min(tb);
-// 	let exit = ta.max(tb);
-
-// 	let start = t1.max(enter);
-// 	let end = t2.min(exit);
-
-// 	if start <= end {
-// 		Some((start, end))
-// 	} else {
-// 		None
-// 	}
-// }
+impl PositionedShape {
+	// The bounding rectangle of a shape centered at `center`
+	fn rec(shape: &Shape, center: &Point) -> Rec {
+		match PositionedShape::from((shape, center)) {
+			PositionedShape::Rectangle(rec) => rec,
+			PositionedShape::Circle(c) => Rec {
+				min: Point {
+					x: c.center.x - c.radius,
+					y: c.center.y - c.radius,
+				},
+				max: Point {
+					x: c.center.x + c.radius,
+					y: c.center.y + c.radius,
+				},
+			},
+		}
+	}
+}
 
-// fn rects_intersect_sometime(
-// 	t1: f64,
-// 	t2: f64,
-// 	r1: Rect,
-// 	r2: Rect,
-// 	r1t2: Rect,
-// 	r2t2: Rect,
-// ) -> bool {
-// 	let w1x = r1.max.x - r1.min.x;
-// 	let w1y = r1.max.y - r1.min.y;
-// 	let w2x = r2.max.x - r2.min.x;
-// 	let w2y = r2.max.y - r2.min.y;
-
-// 	let dx0 = r2.min.x - r1.min.x;
-// 	let dx1 = r2t2.min.x - r1t2.min.x;
-// 	let dy0 = r2.min.y - r1.min.y;
-// 	let dy1 = r2t2.min.y - r1t2.min.y;
-
-// 	let ix = axis_interval(t1, t2, w1x, w2x, dx0, dx1);
-// 	let iy = axis_interval(t1, t2, w1y, w2y, dy0, dy1);
-
-// 	match (ix, iy) {
-// 		(Some((sx, ex)), Some((sy, ey))) => (sx.max(sy) <= ex.min(ey)),
-// 		_ => false,
-// 	}
-// }
+fn sub(a: &Point, b: &Point) -> (f64, f64) {
+	((a.x - b.x) as f64, (a.y - b.y) as f64)
+}
 
-// #[derive(Clone, Copy)]
-// struct Point { x: f64, y: f64 }
-// #[derive(Clone, Copy)]
-// struct Rect { min: Point, max: Point }
-
-// fn dot(a: Point, b: Point) -> f64 { a.x*b.x + a.y*b.y }
-// fn sub(a: Point, b: Point) -> Point { Point { x: a.x-b.x, y: a.y-b.y } }
-// fn add(a: Point, b: Point) -> Point { Point { x: a.x+b.x, y: a.y+b.y } }
-// fn mul(a: Point, k: f64) -> Point { Point { x: a.x*k, y: a.y*k } }
-
-// fn quad_has_solution_in_interval(a: f64, b: f64, c: f64, s0: f64, s1: f64) -> bool {
-//     // Check if a*s^2 + b*s + c <= 0 has any s in [s0,s1]
-//     if a == 0.0 {
-//         if b == 0.0 { return c <= 0.0; }
-//         // linear: b*s + c <= 0  -> s <= -c/b (if b>0) else s >= -c/b
-//         let r = -c / b;
-//         return if b > 0.0 { s0 <= r } else { r <= s1 };
-//     }
-//     let d = b*b - 4.0*a*c;
-//     if d < 0.0 {
-//         // No real roots: inequality holds everywhere or nowhere depending on value at a point
-//         let mid = 0.5*(s0+s1);
-//         return a*mid*mid + b*mid + c <= 0.0;
-//     }
-//     let sqrt_d = d.sqrt();
-//     let r1 = (-b - sqrt_d) / (2.0*a);
-//     let r2 = (-b + sqrt_d) / (2.0*a);
-//     let lo = r1.min(r2);
-//     let hi = r1.max(r2);
-
-//     if a > 0.0 {
-//         // <=0 between roots
-//         let seg_lo = lo.max(s0);
-//         let seg_hi = hi.min(s1);
-//         seg_lo <= seg_hi
-//     } else {
-//         // <=0 outside roots
-//         // true if [s0,s1] intersects (-inf,lo] or [hi,inf)
-//         s0 <= lo || hi <= s1
-//     }
-// }
+fn velocity(from: (f64, f64), to: (f64, f64), dt: f64) -> (f64, f64) {
+	if dt <= 0.0 {
+		(0.0, 0.0)
+	} else {
+		((to.0 - from.0) / dt, (to.1 - from.1) / dt)
+	}
+}
 
-// fn circles_intersect_sometime(
-//     t1: f64, t2: f64,
-//     a0: Point, a1: Point, ra: f64,
-//     b0: Point, b1: Point, rb: f64,
-// ) -> bool {
-//     let dt = t2 - t1;
-//     let d0 = sub(b0, a0);
-//     let va = mul(sub(a1, a0), 1.0/dt);
-//     let vb = mul(sub(b1, b0), 1.0/dt);
-//     let v  = sub(vb, va);
-
-//     let r = ra + rb;
-//     let a = dot(v, v);
-//     let b = 2.0 * dot(d0, v);
-//     let c = dot(d0, d0) - r*r;
-
-//     quad_has_solution_in_interval(a, b, c, 0.0, dt)
-// }
+// Whether a*s^2 + b*s + c < 0 somewhere in [s0, s1], for a >= 0
+fn quad_negative_in(a: f64, b: f64, c: f64, s0: f64, s1: f64) -> bool {
+	let f = |s: f64| a * s * s + b * s + c;
+	if f(s0) < 0.0 || f(s1) < 0.0 {
+		return true;
+	}
+	if a > 0.0 {
+		let m = -b / (2.0 * a);
+		m > s0 && m < s1 && f(m) < 0.0
+	} else {
+		false
+	}
+}
 
-// fn rect_circle_intersect_sometime(
-//     t1: f64, t2: f64,
-//     rect0: Rect, rect1: Rect,
-//     c0: Point, c1: Point, r: f64,
-// ) -> bool {
-//     let dt = t2 - t1;
-
-//     let w = rect0.max.x - rect0.min.x;
-//     let h = rect0.max.y - rect0.min.y;
-
-//     // rect frame: rect is [0,w]x[0,h]; circle center is q(s)=q0+u*s
-//     let q0 = sub(c0, rect0.min);
-//     let q1 = sub(c1, rect1.min);
-//     let u  = mul(sub(q1, q0), 1.0/dt);
-
-//     // breakpoints where qx hits 0 or w, qy hits 0 or h
-//     let mut cuts = vec![0.0, dt];
-//     if u.x != 0.0 {
-//         for &bnd in &[0.0, w] {
-//             let s = (bnd - q0.x) / u.x;
-//             if 0.0 < s && s < dt { cuts.push(s); }
-//         }
-//     }
-//     if u.y != 0.0 {
-//         for &bnd in &[0.0, h] {
-//             let s = (bnd - q0.y) / u.y;
-//             if 0.0 < s && s < dt { cuts.push(s); }
-//         }
-//     }
-//     cuts.sort_by(|a,b| a.partial_cmp(b).unwrap());
-//     cuts.dedup_by(|a,b| (*a-*b).abs() < 1e-12);
-
-//     // check each piece: dx,dy are linear (or 0) => quadratic inequality
-//     for win in cuts.windows(2) {
-//         let s0 = win[0];
-//         let s1 = win[1];
-//         let sm = 0.5*(s0+s1);
-//         let qm = add(q0, mul(u, sm));
-
-//         // dx(s) = ax*s + bx (or 0) depending on region in this interval
-//         let (ax, bx) = if qm.x < 0.0 {
-//             // dx = -qx = -(q0x + ux*s)
-//             (-u.x, -q0.x)
-//         } else if qm.x > w {
-//             // dx = qx - w
-//             (u.x, q0.x - w)
-//         } else {
-//             (0.0, 0.0)
-//         };
+// The first s in [s0, s1] where a*s^2 + b*s + c goes from positive to
+// negative, for a >= 0. Grazing contacts (a double root) don't count.
+fn quad_entry(a: f64, b: f64, c: f64, s0: f64, s1: f64) -> Option<f64> {
+	if a <= 0.0 {
+		return None;
+	}
+	let d = b * b - 4.0 * a * c;
+	if d <= 0.0 {
+		return None;
+	}
+	let lo = (-b - d.sqrt()) / (2.0 * a);
+	if lo < s0 - CONTACT_EPSILON || lo > s1 {
+		return None;
+	}
+	Some(lo.max(s0))
+}
 
-//         let (ay, by) = if qm.y < 0.0 {
-//             (-u.y, -q0.y)
-//         } else if qm.y > h {
-//             (u.y, q0.y - h)
-//         } else {
-//             (0.0, 0.0)
-//         };
+fn circles_contact(
+	dt: f64,
+	a: (&Point, &Point),
+	b: (&Point, &Point),
+	radius: Coord,
+) -> SweptContact {
+	let d0 = sub(b.0, a.0);
+	let d1 = sub(b.1, a.1);
+	let v = velocity(d0, d1, dt);
+	let r = radius as f64;
+
+	let qa = v.0 * v.0 + v.1 * v.1;
+	let qb = 2.0 * (d0.0 * v.0 + d0.1 * v.1);
+	let qc = d0.0 * d0.0 + d0.1 * d0.1 - r * r;
+
+	SweptContact {
+		overlaps: quad_negative_in(qa, qb, qc, 0.0, dt),
+		entry: quad_entry(qa, qb, qc, 0.0, dt),
+	}
+}
 
-//         // (ax*s+bx)^2 + (ay*s+by)^2 - r^2 <= 0
-//         let a = ax*ax + ay*ay;
-//         let b = 2.0*(ax*bx + ay*by);
-//         let c = (bx*bx + by*by) - r*r;
+// The open interval of time during which the offset `d0 + v*s` lies strictly
+// between -below and above. None if it never does.
+fn axis_interval(
+	d0: f64,
+	v: f64,
+	below: f64,
+	above: f64,
+) -> Option<(f64, f64)> {
+	if v == 0.0 {
+		return if d0 > -below && d0 < above {
+			Some((f64::NEG_INFINITY, f64::INFINITY))
+		} else {
+			None
+		};
+	}
+	let ta = (-below - d0) / v;
+	let tb = (above - d0) / v;
+	Some((ta.min(tb), ta.max(tb)))
+}
 
-//         if quad_has_solution_in_interval(a, b, c, s0, s1) {
-//             return true;
-//         }
-//     }
-//     false
-// }
+fn rects_contact(dt: f64, a: (&Rec, &Rec), b: (&Rec, &Rec)) -> SweptContact {
+	let d0 = sub(&b.0.min, &a.0.min);
+	let d1 = sub(&b.1.min, &a.1.min);
+	let v = velocity(d0, d1, dt);
+
+	let ix = axis_interval(d0.0, v.0, b.0.width() as f64, a.0.width() as f64);
+	let iy = axis_interval(d0.1, v.1, b.0.height() as f64, a.0.height() as f64);
+	let (enter, exit) = match (ix, iy) {
+		(Some((ex, xx)), Some((ey, xy))) => (ex.max(ey), xx.min(xy)),
+		_ => {
+			return SweptContact {
+				overlaps: false,
+				entry: None,
+			};
+		}
+	};
+	let overlaps = enter < exit && enter < dt && exit > 0.0;
+	let entry = if overlaps && enter >= -CONTACT_EPSILON {
+		Some(enter.max(0.0))
+	} else {
+		None
+	};
+	SweptContact { overlaps, entry }
+}
 
-impl UnitDisplayType {
-	pub fn get_color(&self) -> (u8, u8, u8) {
-		match self {
-			UnitDisplayType::SimpleUnit => (0, 128, 255), // Example color
+fn rect_circle_contact(
+	dt: f64,
+	rect: (&Rec, &Rec),
+	circle: (&Point, &Point),
+	radius: Coord,
+) -> SweptContact {
+	let w = rect.0.width() as f64;
+	let h = rect.0.height() as f64;
+	let r = radius as f64;
+
+	// rect frame: rect is [0,w]x[0,h]; circle center is q(s)=q0+u*s
+	let q0 = sub(circle.0, &rect.0.min);
+	let q1 = sub(circle.1, &rect.1.min);
+	let u = velocity(q0, q1, dt);
+
+	// breakpoints where qx hits 0 or w, qy hits 0 or h
+	let mut cuts = vec![0.0, dt];
+	for (q, v, bounds) in [(q0.0, u.0, [0.0, w]), (q0.1, u.1, [0.0, h])] {
+		if v != 0.0 {
+			for bound in bounds {
+				let s = (bound - q) / v;
+				if 0.0 < s && s < dt {
+					cuts.push(s);
+				}
+			}
 		}
 	}
+	cuts.sort_by(|a, b| a.total_cmp(b));
+	cuts.dedup_by(|a, b| (*a - *b).abs() < 1e-12);
+
+	let mut overlaps = false;
+	let mut entry = None;
+	// each piece: dx,dy are linear (or 0) => quadratic in s
+	for piece in cuts.windows(2) {
+		let (s0, s1) = (piece[0], piece[1]);
+		let sm = 0.5 * (s0 + s1);
+		let qm = (q0.0 + u.0 * sm, q0.1 + u.1 * sm);
+
+		let (ax, bx) = if qm.0 < 0.0 {
+			(-u.0, -q0.0)
+		} else if qm.0 > w {
+			(u.0, q0.0 - w)
+		} else {
+			(0.0, 0.0)
+		};
+		let (ay, by) = if qm.1 < 0.0 {
+			(-u.1, -q0.1)
+		} else if qm.1 > h {
+			(u.1, q0.1 - h)
+		} else {
+			(0.0, 0.0)
+		};
 
-	pub fn get_shape(&self) -> Shape {
-		match self {
-			UnitDisplayType::SimpleUnit => Shape::Circle(0.5), // Example shape
+		// (ax*s+bx)^2 + (ay*s+by)^2 - r^2
+		let qa = ax * ax + ay * ay;
+		let qb = 2.0 * (ax * bx + ay * by);
+		let qc = bx * bx + by * by - r * r;
+
+		overlaps = overlaps || quad_negative_in(qa, qb, qc, s0, s1);
+		if entry.is_none() {
+			entry = quad_entry(qa, qb, qc, s0, s1);
 		}
 	}
+	if dt <= 0.0 {
+		// no pieces, just check where they are
+		let (dx, dy) = (q0.0 - q0.0.clamp(0.0, w), q0.1 - q0.1.clamp(0.0, h));
+		overlaps = dx * dx + dy * dy < r * r;
+	}
+	SweptContact { overlaps, entry }
+}
 
-	pub fn get_fill(&self) -> bool {
-		match self {
-			UnitDisplayType::SimpleUnit => true,
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const UNIT: Coord = 1000;
+
+	// Moving from `from` to `to` in the first ten seconds
+	fn moving(
+		shape: Shape,
+		from: (Coord, Coord),
+		to: (Coord, Coord),
+	) -> ShapeVolume {
+		ShapeVolume::new(
+			shape,
+			(
+				0,
+				Point {
+					x: from.0,
+					y: from.1,
+				},
+			),
+			(10_000, Point { x: to.0, y: to.1 }),
+		)
+	}
+
+	fn resting(shape: Shape, at: (Coord, Coord)) -> ShapeVolume {
+		moving(shape, at, at)
+	}
+
+	// Both only exist at the same instant
+	fn instant(shape: Shape, at: (Coord, Coord)) -> ShapeVolume {
+		ShapeVolume::stationary(shape, Point { x: at.0, y: at.1 }, 500, 500)
+	}
+
+	fn circle() -> Shape {
+		Shape::Circle(UNIT)
+	}
+
+	fn square() -> Shape {
+		Shape::Rectangle(2 * UNIT, 2 * UNIT)
+	}
+
+	// Going right at one Coord per millisecond, the leading edge starts at
+	// x = 1000 and reaches the other shape's edge at x = 4000
+	fn runner(shape: Shape, y: Coord) -> ShapeVolume {
+		moving(shape, (0, y), (10_000, y))
+	}
+
+	#[test]
+	fn circles_head_on() {
+		let a = runner(circle(), 0);
+		let b = resting(circle(), (5000, 0));
+		assert_eq!(a.first_contact(&b, 0), Some(3000));
+		assert_eq!(b.first_contact(&a, 0), Some(3000));
+		assert!(a.intersects(&b));
+		// nothing left to run into once they're past it
+		assert_eq!(a.first_contact(&b, 7000), None);
+	}
+
+	#[test]
+	fn circles_grazing() {
+		let a = runner(circle(), 0);
+		let b = resting(circle(), (5000, 2 * UNIT));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(!a.intersects(&b));
+	}
+
+	#[test]
+	fn circles_already_overlapping() {
+		let a = runner(circle(), 0);
+		let b = resting(circle(), (500, 0));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(a.intersects(&b));
+	}
+
+	#[test]
+	fn circles_zero_length_window() {
+		let a = instant(circle(), (0, 0));
+		assert_eq!(a.first_contact(&instant(circle(), (1500, 0)), 0), None);
+		assert!(a.intersects(&instant(circle(), (1500, 0))));
+		assert!(!a.intersects(&instant(circle(), (2500, 0))));
+	}
+
+	#[test]
+	fn rects_head_on() {
+		let a = runner(square(), 0);
+		let b = resting(square(), (5000, 0));
+		assert_eq!(a.first_contact(&b, 0), Some(3000));
+		assert_eq!(b.first_contact(&a, 0), Some(3000));
+		assert!(a.intersects(&b));
+		assert_eq!(a.first_contact(&b, 7000), None);
+	}
+
+	#[test]
+	fn rects_grazing() {
+		let a = runner(square(), 0);
+		let b = resting(square(), (5000, 2 * UNIT));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(!a.intersects(&b));
+	}
+
+	#[test]
+	fn rects_already_overlapping() {
+		let a = runner(square(), 0);
+		let b = resting(square(), (500, 0));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(a.intersects(&b));
+	}
+
+	#[test]
+	fn rects_zero_length_window() {
+		let a = instant(square(), (0, 0));
+		assert_eq!(a.first_contact(&instant(square(), (1500, 0)), 0), None);
+		assert!(a.intersects(&instant(square(), (1500, 0))));
+		assert!(!a.intersects(&instant(square(), (2500, 0))));
+	}
+
+	#[test]
+	fn rect_circle_head_on() {
+		let a = runner(circle(), 0);
+		let b = resting(square(), (5000, 0));
+		assert_eq!(a.first_contact(&b, 0), Some(3000));
+		assert_eq!(b.first_contact(&a, 0), Some(3000));
+		assert!(a.intersects(&b));
+		assert_eq!(a.first_contact(&b, 7000), None);
+	}
+
+	#[test]
+	fn rect_circle_grazing() {
+		let a = runner(circle(), 2 * UNIT);
+		let b = resting(square(), (5000, 0));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(!a.intersects(&b));
+	}
+
+	#[test]
+	fn rect_circle_already_overlapping() {
+		let a = runner(circle(), 0);
+		let b = resting(square(), (500, 0));
+		assert_eq!(a.first_contact(&b, 0), None);
+		assert!(a.intersects(&b));
+		assert_eq!(b.first_contact(&a, 0), None);
+		assert!(b.intersects(&a));
+	}
+
+	#[test]
+	fn within_passing_by() {
+		// two meters a second, three from the center, inside five for eight
+		// meters
+		let a = moving(circle(), (-10_000, 3000), (10_000, 3000));
+		let eye = resting(square(), (0, 0));
+		assert_eq!(eye.within(&a, 5000, 0), Some((3000, 7000)));
+		assert_eq!(eye.within(&a, 5000, 4000), Some((4000, 7000)));
+		assert_eq!(eye.within(&a, 2000, 0), None);
+	}
+
+	#[test]
+	fn within_standing_still() {
+		let eye = ShapeVolume::stationary(
+			circle(),
+			Point { x: 0, y: 0 },
+			0,
+			TimeStamp::MAX,
+		);
+		let near = ShapeVolume::stationary(
+			circle(),
+			Point { x: 3000, y: 4000 },
+			100,
+			TimeStamp::MAX,
+		);
+		assert_eq!(eye.within(&near, 5000, 0), Some((100, TimeStamp::MAX)));
+		assert_eq!(eye.within(&near, 4999, 0), None);
+	}
+
+	#[test]
+	fn rect_circle_zero_length_window() {
+		let a = instant(circle(), (0, 0));
+		assert_eq!(a.first_contact(&instant(square(), (1500, 0)), 0), None);
+		assert!(a.intersects(&instant(square(), (1500, 0))));
+		assert!(!a.intersects(&instant(square(), (2500, 0))));
+		// off the corner, inside the bounding square but clear of the circle
+		assert!(!a.intersects(&instant(square(), (1800, 1800))));
+	}
+
+	fn segment_from(
+		begin: (Coord, Coord),
+		delta: Option<Delta>,
+		acceleration: Option<Delta>,
+		arc: Option<CircularArc>,
+	) -> AnimationSegment {
+		let d_orientation = arc.as_ref().map(|arc| arc.angular_velocity);
+		AnimationSegment {
+			begin_time: 1000,
+			begin_location: Point {
+				x: begin.0,
+				y: begin.1,
+			},
+			delta,
+			acceleration,
+			arc,
+			begin_orientation: 0.0,
+			d_orientation,
 		}
 	}
 
-	// pub fn get_int(self) -> u32 {
-	// 	match self {
-	// 		UnitDisplayType::SimpleUnit => UnitDisplayType::SimpleUnit as u32,
-	// 	}
-	// }
+	// Half a turn per second around the origin, starting a meter east of it
+	fn circling() -> AnimationSegment {
+		let arc = CircularArc {
+			center: Point { x: 0, y: 0 },
+			angular_velocity: std::f32::consts::FRAC_PI_2,
+		};
+		segment_from((UNIT, 0), None, None, Some(arc))
+	}
+
+	fn at(segment: &AnimationSegment, d_t: TimeStamp) -> (Coord, Coord) {
+		let point = segment.place_at(segment.begin_time + d_t).point;
+		(point.x, point.y)
+	}
+
+	#[test]
+	fn place_while_speeding_up_and_slowing_down() {
+		let speeding_up = segment_from(
+			(0, 0),
+			Some(Delta { dx: 0, dy: 0 }),
+			Some(Delta { dx: 2000, dy: 0 }),
+			None,
+		);
+		assert_eq!(at(&speeding_up, 0), (0, 0));
+		assert_eq!(at(&speeding_up, 250), (62, 0));
+		assert_eq!(at(&speeding_up, 500), (250, 0));
+
+		let slowing_down = segment_from(
+			(0, 0),
+			Some(Delta { dx: 0, dy: -1000 }),
+			Some(Delta { dx: 0, dy: 2000 }),
+			None,
+		);
+		assert_eq!(at(&slowing_down, 250), (0, -188));
+		assert_eq!(at(&slowing_down, 500), (0, -250));
+		// and stays there instead of backing up
+		assert_eq!(at(&slowing_down, 2000), (0, -250));
+	}
+
+	#[test]
+	fn place_around_an_arc() {
+		let segment = circling();
+		assert_eq!(at(&segment, 0), (UNIT, 0));
+		assert_eq!(at(&segment, 500), (707, 707));
+		assert_eq!(at(&segment, 1000), (0, UNIT));
+		assert_eq!(at(&segment, 2000), (-UNIT, 0));
+		assert_eq!(at(&segment, 3000), (0, -UNIT));
+		assert_eq!(at(&segment, 4000), (UNIT, 0));
+		let turned = segment.place_at(segment.begin_time + 1000).orientation;
+		assert!((turned - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+
+		let clockwise = CircularArc {
+			angular_velocity: -std::f32::consts::FRAC_PI_2,
+			..segment.arc.clone().unwrap()
+		};
+		let clockwise = segment_from((UNIT, 0), None, None, Some(clockwise));
+		assert_eq!(at(&clockwise, 1000), (0, -UNIT));
+	}
+
+	// Within a few billionths of what the floats say, for angles all the
+	// way around and then some
+	#[test]
+	fn fixed_sin_cos_follows_the_floats() {
+		for step in -2000..=2000 {
+			let angle = step as f64 / 100.0;
+			let (sin, cos) =
+				fixed_sin_cos((angle * FIXED_ONE as f64).round() as i64);
+			let off = |fixed: i64, float: f64| {
+				(fixed as f64 / FIXED_ONE as f64 - float).abs()
+			};
+			assert!(off(sin, angle.sin()) < 5e-9, "sin {}", angle);
+			assert!(off(cos, angle.cos()) < 5e-9, "cos {}", angle);
+		}
+	}
 
-	pub fn parse(
-		value: u32,
-	) -> Result<UnitDisplayType, crate::convert::ParseError> {
-		match value {
-			1 => Ok(UnitDisplayType::SimpleUnit),
-			_ => {
-				Err(crate::convert::ParseError::InvalidValue("UnitDisplayType"))
+	// Every straight piece stays close to the curve it stands in for, all
+	// along it
+	#[test]
+	fn sweeps_stay_close_to_curves() {
+		let curves = [
+			circling(),
+			// the tightest turn of the default unit, half a meter across
+			segment_from(
+				(UNIT / 2, 0),
+				None,
+				None,
+				Some(CircularArc {
+					center: Point { x: 0, y: 0 },
+					angular_velocity: 2.0,
+				}),
+			),
+			segment_from(
+				(0, 0),
+				Some(Delta { dx: 3000, dy: 0 }),
+				Some(Delta { dx: -2000, dy: 0 }),
+				None,
+			),
+		];
+		for segment in &curves {
+			let end_time = segment.begin_time + 1500;
+			let volumes = ShapeVolume::along_segment(
+				&Shape::Circle(UNIT),
+				segment,
+				end_time,
+			);
+			assert!(volumes.len() > 1);
+			assert_eq!(volumes[0].begin.0, segment.begin_time);
+			assert_eq!(volumes.last().unwrap().end.0, end_time);
+			for volume in &volumes {
+				for time in volume.begin.0..=volume.end.0 {
+					let off = volume
+						.location_at(time)
+						.distance_to(&segment.place_at(time).point);
+					// give or take the rounding to millimeters
+					assert!(
+						off <= SWEEP_TOLERANCE + 1,
+						"{} off at {}",
+						off,
+						time
+					);
+				}
 			}
 		}
 	}
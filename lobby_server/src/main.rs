@@ -1,21 +1,169 @@
-use actix_web::{App, HttpResponse, HttpServer, Responder, delete, get, post, put, web};
-use common::lobby::{Lobby, Player};
-use serde::Deserialize;
+use actix_cors::Cors;
+use actix_web::http::header;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, delete, get, post, put, web};
+use common::grpc::shape_events_client::ShapeEventsClient;
+use common::grpc::{RosterEntry, StartMatchRequest};
+use common::lobby::{JoinTicket, Lobby, LobbyEvent, MatchSettings, MatchStatus, Player, Room};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tonic::transport::Channel;
+
+const DEFAULT_GAME_SERVER: &str = "http://127.0.0.1:50051";
+// the game server checks LOBBY_KEY against this header on StartMatch
+const LOBBY_KEY_HEADER: &str = "lobby-key";
+const DEFAULT_MAX_PLAYERS: usize = 8;
+const EVENT_BUFFER: usize = 256;
+
+// A room as the lobby keeps it, the players get filled in when it's sent out
+struct RoomState {
+    name: String,
+    host: u64,
+    max_players: usize,
+    settings: MatchSettings,
+    // in the order they joined
+    players: Vec<u64>,
+    status: MatchStatus,
+}
+
+impl RoomState {
+    fn view(&self, id: u64, players: &HashMap<u64, Player>) -> Room {
+        Room {
+            id,
+            name: self.name.clone(),
+            host: self.host,
+            max_players: self.max_players,
+            settings: self.settings.clone(),
+            players: self
+                .players
+                .iter()
+                .filter_map(|p| players.get(p).cloned())
+                .collect(),
+            status: self.status.clone(),
+        }
+    }
+}
 
 #[derive(Clone)]
 struct AppState {
+    // when both are needed, `players` is locked before `rooms`
     players: Arc<RwLock<HashMap<u64, Player>>>,
+    rooms: Arc<RwLock<HashMap<u64, RoomState>>>,
     next_id: Arc<RwLock<u64>>,
+    next_room_id: Arc<RwLock<u64>>,
+    // by lobby player id
+    tickets: Arc<RwLock<HashMap<u64, JoinTicket>>>,
+    // lobby player id by the token they were given when they were created
+    tokens: Arc<RwLock<HashMap<String, u64>>>,
+    game_server: String,
+    // shared with the game server, which only starts matches for lobbies
+    // that know it
+    lobby_key: Option<String>,
+    events: broadcast::Sender<LobbyEvent>,
+}
+
+impl AppState {
+    // Published while the state it describes is still locked, so watchers
+    // see changes in the order they happened
+    fn publish(&self, event: LobbyEvent) {
+        // nobody watching is fine
+        let _ = self.events.send(event);
+    }
+
+    // The lobby player making the request, from the bearer token they got
+    // when they were created
+    fn caller(&self, req: &HttpRequest) -> Option<u64> {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        self.tokens.read().unwrap().get(token).copied()
+    }
+
+    fn snapshot(&self) -> Lobby {
+        let map = self.players.read().unwrap();
+        let mut players: Vec<Player> = map.values().cloned().collect();
+        players.sort_by_key(|p| p.id);
+        let rooms = room_list(&map, &self.rooms.read().unwrap());
+        Lobby { players, rooms }
+    }
+}
+
+fn valid_settings(settings: &MatchSettings) -> bool {
+    settings.speed.is_finite() && settings.speed > 0.0
+}
+
+fn room_list(players: &HashMap<u64, Player>, rooms: &HashMap<u64, RoomState>) -> Vec<Room> {
+    let mut rooms: Vec<Room> = rooms.iter().map(|(id, r)| r.view(*id, players)).collect();
+    rooms.sort_by_key(|r| r.id);
+    rooms
+}
+
+// Takes the player out of their room. Whoever joined after the host takes
+// over when the host leaves, and the room closes once it's empty.
+fn leave_room(
+    state: &AppState,
+    players: &mut HashMap<u64, Player>,
+    rooms: &mut HashMap<u64, RoomState>,
+    player_id: u64,
+    kicked: bool,
+) {
+    let Some(player) = players.get_mut(&player_id) else {
+        return;
+    };
+    player.ready = false;
+    let Some(room_id) = player.room.take() else {
+        return;
+    };
+    let Some(room) = rooms.get_mut(&room_id) else {
+        return;
+    };
+    room.players.retain(|p| *p != player_id);
+    state.publish(LobbyEvent::PlayerLeft {
+        room: room_id,
+        player_id,
+        kicked,
+    });
+    if room.players.is_empty() {
+        rooms.remove(&room_id);
+        state.publish(LobbyEvent::RoomClosed { room: room_id });
+    } else if room.host == player_id {
+        room.host = room.players[0];
+        state.publish(LobbyEvent::RoomUpdated(room.view(room_id, players)));
+    }
 }
 
 #[get("/lobby")]
 async fn get_lobby(state: web::Data<AppState>) -> impl Responder {
-    let map = state.players.read().unwrap();
-    let mut players: Vec<Player> = map.values().cloned().collect();
-    players.sort_by_key(|p| p.id);
-    HttpResponse::Ok().json(Lobby { players })
+    HttpResponse::Ok().json(state.snapshot())
+}
+
+// Server-sent events: the whole lobby first, then every change as it happens
+#[get("/events")]
+async fn lobby_events(state: web::Data<AppState>) -> impl Responder {
+    // subscribe before taking the snapshot so nothing falls in between
+    let rx = state.events.subscribe();
+    let first = LobbyEvent::Snapshot(state.snapshot());
+    let state = state.clone();
+    let updates = BroadcastStream::new(rx).map(move |event| match event {
+        Ok(event) => event,
+        // they fell behind, start them over from the current lobby
+        Err(BroadcastStreamRecvError::Lagged(_)) => LobbyEvent::Snapshot(state.snapshot()),
+    });
+    let stream = tokio_stream::once(first).chain(updates).map(|event| {
+        serde_json::to_string(&event).map(|json| web::Bytes::from(format!("data: {}\n\n", json)))
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,6 +171,15 @@ struct CreatePlayerReq {
     name: String,
 }
 
+// Only ever sent to the player themselves, everyone else just sees the Player
+#[derive(Serialize)]
+struct CreatedPlayer {
+    #[serde(flatten)]
+    player: Player,
+    // goes in the Authorization header as a bearer token
+    token: String,
+}
+
 #[post("/players")]
 async fn create_player(
     state: web::Data<AppState>,
@@ -36,10 +193,14 @@ async fn create_player(
         id,
         name: body.name.clone(),
         ready: false,
+        room: None,
     };
 
+    let token = format!("{:032x}", rand::rng().random::<u128>());
     state.players.write().unwrap().insert(id, player.clone());
-    HttpResponse::Ok().json(player)
+    state.tokens.write().unwrap().insert(token.clone(), id);
+    state.publish(LobbyEvent::PlayerCreated(player.clone()));
+    HttpResponse::Ok().json(CreatedPlayer { player, token })
 }
 
 #[derive(Debug, Deserialize)]
@@ -50,25 +211,382 @@ struct ReadyReq {
 #[put("/players/{id}/ready")]
 async fn set_ready(
     state: web::Data<AppState>,
+    req: HttpRequest,
     path: web::Path<u64>,
     body: web::Json<ReadyReq>,
 ) -> impl Responder {
     let id = path.into_inner();
+    if state.caller(&req) != Some(id) {
+        return HttpResponse::Forbidden().finish();
+    }
+    let (player, start) = {
+        let mut map = state.players.write().unwrap();
+        let Some(p) = map.get_mut(&id) else {
+            return HttpResponse::NotFound().finish();
+        };
+        p.ready = body.ready;
+        let player = p.clone();
+        state.publish(LobbyEvent::ReadyChanged(player.clone()));
+
+        // The last one in the room to get ready kicks off its match
+        let mut rooms = state.rooms.write().unwrap();
+        let start = player.room.and_then(|room_id| {
+            let room = rooms.get_mut(&room_id)?;
+            if room.status != MatchStatus::Waiting
+                || !room.players.iter().all(|p| map.get(p).is_some_and(|p| p.ready))
+            {
+                return None;
+            }
+            room.status = MatchStatus::Starting;
+            state.publish(LobbyEvent::MatchStatusChanged {
+                room: room_id,
+                status: MatchStatus::Starting,
+            });
+            let roster: Vec<Player> = room
+                .players
+                .iter()
+                .filter_map(|p| map.get(p).cloned())
+                .collect();
+            Some((room_id, room.name.clone(), room.settings.clone(), roster))
+        });
+        (player, start)
+    };
+
+    if let Some((room_id, name, settings, roster)) = start {
+        start_match(&state, room_id, &name, &settings, roster).await;
+    }
+    HttpResponse::Ok().json(player)
+}
+
+async fn start_match(
+    state: &AppState,
+    room_id: u64,
+    name: &str,
+    settings: &MatchSettings,
+    roster: Vec<Player>,
+) {
+    let result = request_match(
+        &state.game_server,
+        state.lobby_key.as_deref(),
+        name,
+        settings,
+        &roster,
+    )
+    .await;
+    let status = match result {
+        Ok(tickets) => {
+            println!("Room {} started a match with {} players", room_id, tickets.len());
+            state.tickets.write().unwrap().extend(tickets);
+            MatchStatus::Started
+        }
+        Err(e) => {
+            // everyone stays ready, the next ready toggle tries again
+            eprintln!("Unable to start the match for room {}: {}", room_id, e);
+            MatchStatus::Waiting
+        }
+    };
+    // the room may have emptied out in the meantime
+    if let Some(room) = state.rooms.write().unwrap().get_mut(&room_id) {
+        room.status = status.clone();
+        state.publish(LobbyEvent::MatchStatusChanged { room: room_id, status });
+    }
+}
+
+// Hands the roster to the game server, which sets up a match for the room,
+// maps every lobby player to a game player and gives each a ticket to join
+// with
+async fn request_match(
+    game_server: &str,
+    lobby_key: Option<&str>,
+    name: &str,
+    settings: &MatchSettings,
+    roster: &[Player],
+) -> Result<HashMap<u64, JoinTicket>, Box<dyn std::error::Error>> {
+    let channel = Channel::from_shared(game_server.to_string())?
+        .connect()
+        .await?;
+    let mut client = ShapeEventsClient::new(channel);
+    let mut request = tonic::Request::new(StartMatchRequest {
+        players: roster
+            .iter()
+            .map(|p| RosterEntry {
+                lobby_player_id: p.id,
+                name: p.name.clone(),
+            })
+            .collect(),
+        name: name.to_string(),
+        speed: settings.speed,
+    });
+    let lobby_key = lobby_key.ok_or("LOBBY_KEY is not set")?;
+    request.metadata_mut().insert(LOBBY_KEY_HEADER, lobby_key.parse()?);
+    let response = client.start_match(request).await?.into_inner();
+    Ok(response
+        .tickets
+        .into_iter()
+        .map(|t| {
+            (
+                t.lobby_player_id,
+                JoinTicket {
+                    game_server: game_server.to_string(),
+                    player_id: t.player_id,
+                    ticket: t.ticket,
+                },
+            )
+        })
+        .collect())
+}
+
+#[get("/rooms")]
+async fn list_rooms(state: web::Data<AppState>) -> impl Responder {
+    let map = state.players.read().unwrap();
+    HttpResponse::Ok().json(room_list(&map, &state.rooms.read().unwrap()))
+}
+
+#[get("/rooms/{id}")]
+async fn get_room(state: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    let id = path.into_inner();
+    let map = state.players.read().unwrap();
+    match state.rooms.read().unwrap().get(&id) {
+        Some(room) => HttpResponse::Ok().json(room.view(id, &map)),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateRoomReq {
+    name: String,
+    max_players: Option<usize>,
+    settings: Option<MatchSettings>,
+}
+
+// Whoever creates the room joins it as its host
+#[post("/rooms")]
+async fn create_room(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<CreateRoomReq>,
+) -> impl Responder {
+    let Some(host_id) = state.caller(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let body = body.into_inner();
+    let max_players = body.max_players.unwrap_or(DEFAULT_MAX_PLAYERS);
+    if max_players == 0 {
+        return HttpResponse::BadRequest().body("a room needs room for at least one player");
+    }
+    if body.settings.as_ref().is_some_and(|s| !valid_settings(s)) {
+        return HttpResponse::BadRequest().body("speed must be a positive number");
+    }
+    let mut map = state.players.write().unwrap();
+    if !map.contains_key(&host_id) {
+        return HttpResponse::NotFound().finish();
+    }
+    let mut rooms = state.rooms.write().unwrap();
+    leave_room(&state, &mut map, &mut rooms, host_id, false);
+
+    let mut id_lock = state.next_room_id.write().unwrap();
+    let id = *id_lock;
+    *id_lock += 1;
+
+    let room = RoomState {
+        name: body.name,
+        host: host_id,
+        max_players,
+        settings: body.settings.unwrap_or_default(),
+        players: vec![host_id],
+        status: MatchStatus::Waiting,
+    };
+    let Some(host) = map.get_mut(&host_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    host.room = Some(id);
+    let host = host.clone();
+    let view = room.view(id, &map);
+    rooms.insert(id, room);
+    state.publish(LobbyEvent::RoomUpdated(view.clone()));
+    state.publish(LobbyEvent::PlayerJoined { room: id, player: host });
+    HttpResponse::Ok().json(view)
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateRoomReq {
+    name: Option<String>,
+    max_players: Option<usize>,
+    settings: Option<MatchSettings>,
+}
+
+#[put("/rooms/{id}")]
+async fn update_room(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<u64>,
+    body: web::Json<UpdateRoomReq>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let body = body.into_inner();
+    let caller = state.caller(&req);
+    let map = state.players.read().unwrap();
+    let mut rooms = state.rooms.write().unwrap();
+    let Some(room) = rooms.get_mut(&id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if caller != Some(room.host) {
+        return HttpResponse::Forbidden().finish();
+    }
+    if room.status != MatchStatus::Waiting {
+        return HttpResponse::Conflict().body("the match has already started");
+    }
+    if body.max_players.is_some_and(|max| max == 0 || max < room.players.len()) {
+        return HttpResponse::Conflict().body("the room has more players than that");
+    }
+    if body.settings.as_ref().is_some_and(|s| !valid_settings(s)) {
+        return HttpResponse::BadRequest().body("speed must be a positive number");
+    }
+    if let Some(max_players) = body.max_players {
+        room.max_players = max_players;
+    }
+    if let Some(name) = body.name {
+        room.name = name;
+    }
+    if let Some(settings) = body.settings {
+        room.settings = settings;
+    }
+    let view = room.view(id, &map);
+    state.publish(LobbyEvent::RoomUpdated(view.clone()));
+    HttpResponse::Ok().json(view)
+}
+
+// Only the host can close the room, everyone in it is sent back to the lobby
+#[delete("/rooms/{id}")]
+async fn delete_room(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let caller = state.caller(&req);
+    let mut map = state.players.write().unwrap();
+    let mut rooms = state.rooms.write().unwrap();
+    let Some(room) = rooms.get(&id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if caller != Some(room.host) {
+        return HttpResponse::Forbidden().finish();
+    }
+    if let Some(room) = rooms.remove(&id) {
+        for player_id in room.players {
+            if let Some(p) = map.get_mut(&player_id) {
+                p.room = None;
+                p.ready = false;
+            }
+        }
+        state.publish(LobbyEvent::RoomClosed { room: id });
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[post("/rooms/{id}/players")]
+async fn join_room(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let Some(player_id) = state.caller(&req) else {
+        return HttpResponse::Unauthorized().finish();
+    };
     let mut map = state.players.write().unwrap();
-    match map.get_mut(&id) {
-        Some(p) => {
-            p.ready = body.ready;
-            HttpResponse::Ok().json(p)
+    let mut rooms = state.rooms.write().unwrap();
+    let Some(player) = map.get(&player_id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let Some(room) = rooms.get(&id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if player.room != Some(id) {
+        if room.status != MatchStatus::Waiting {
+            return HttpResponse::Conflict().body("the match has already started");
         }
+        if room.players.len() >= room.max_players {
+            return HttpResponse::Conflict().body("the room is full");
+        }
+        leave_room(&state, &mut map, &mut rooms, player_id, false);
+        if let Some(room) = rooms.get_mut(&id) {
+            room.players.push(player_id);
+        }
+        if let Some(player) = map.get_mut(&player_id) {
+            player.room = Some(id);
+            state.publish(LobbyEvent::PlayerJoined {
+                room: id,
+                player: player.clone(),
+            });
+        }
+    }
+    match rooms.get(&id) {
+        Some(room) => HttpResponse::Ok().json(room.view(id, &map)),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Leaving the room, or being kicked from it when the host asks
+#[delete("/rooms/{id}/players/{player_id}")]
+async fn leave_or_kick(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(u64, u64)>,
+) -> impl Responder {
+    let (id, player_id) = path.into_inner();
+    let caller = state.caller(&req);
+    let mut map = state.players.write().unwrap();
+    let mut rooms = state.rooms.write().unwrap();
+    let Some(room) = rooms.get(&id) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !room.players.contains(&player_id) {
+        return HttpResponse::NotFound().finish();
+    }
+    let kicked = match caller {
+        None => return HttpResponse::Unauthorized().finish(),
+        Some(by) if by == player_id => false,
+        Some(by) if by == room.host => true,
+        Some(_) => return HttpResponse::Forbidden().finish(),
+    };
+    leave_room(&state, &mut map, &mut rooms, player_id, kicked);
+    HttpResponse::NoContent().finish()
+}
+
+// Only for the player it was issued to, anyone else could join as them
+#[get("/players/{id}/ticket")]
+async fn get_ticket(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if state.caller(&req) != Some(id) {
+        return HttpResponse::Forbidden().finish();
+    }
+    match state.tickets.read().unwrap().get(&id) {
+        Some(ticket) => HttpResponse::Ok().json(ticket),
         None => HttpResponse::NotFound().finish(),
     }
 }
 
 #[delete("/players/{id}")]
-async fn delete_player(state: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+async fn delete_player(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<u64>,
+) -> impl Responder {
     let id = path.into_inner();
+    if state.caller(&req) != Some(id) {
+        return HttpResponse::Forbidden().finish();
+    }
     let mut map = state.players.write().unwrap();
+    leave_room(&state, &mut map, &mut state.rooms.write().unwrap(), id, false);
+    state.tokens.write().unwrap().retain(|_, player_id| *player_id != id);
     if map.remove(&id).is_some() {
+        state.publish(LobbyEvent::PlayerDeleted { player_id: id });
         HttpResponse::NoContent().finish()
     } else {
         HttpResponse::NotFound().finish()
@@ -79,15 +597,34 @@ async fn delete_player(state: web::Data<AppState>, path: web::Path<u64>) -> impl
 async fn main() -> std::io::Result<()> {
     let state = AppState {
         players: Arc::new(RwLock::new(HashMap::new())),
+        rooms: Arc::new(RwLock::new(HashMap::new())),
         next_id: Arc::new(RwLock::new(1)),
+        next_room_id: Arc::new(RwLock::new(1)),
+        tickets: Arc::new(RwLock::new(HashMap::new())),
+        tokens: Arc::new(RwLock::new(HashMap::new())),
+        game_server: std::env::var("GAME_SERVER")
+            .unwrap_or_else(|_| DEFAULT_GAME_SERVER.to_string()),
+        lobby_key: std::env::var("LOBBY_KEY").ok().filter(|k| !k.is_empty()),
+        events: broadcast::channel(EVENT_BUFFER).0,
     };
 
     HttpServer::new(move || {
         App::new()
+            // the UI watches /events from another origin
+            .wrap(Cors::permissive())
             .app_data(web::Data::new(state.clone()))
             .service(get_lobby)
+            .service(lobby_events)
             .service(create_player)
             .service(set_ready)
+            .service(get_ticket)
+            .service(list_rooms)
+            .service(get_room)
+            .service(create_room)
+            .service(update_room)
+            .service(delete_room)
+            .service(join_room)
+            .service(leave_or_kick)
             .service(delete_player)
     })
     .bind(("127.0.0.1", 8081))?
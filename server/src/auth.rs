@@ -0,0 +1,235 @@
+use crate::matches::MatchId;
+use common::model::PlayerId;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+pub const PLAYER_ID_HEADER: &str = "player-id";
+pub const SECRET_HEADER: &str = "player-secret";
+// Shared between the game server and the lobby, set in LOBBY_KEY on both
+pub const LOBBY_KEY_HEADER: &str = "lobby-key";
+
+// Only the hash of the secret is kept, the player holds the secret itself
+struct Session {
+	secret_hash: String,
+	match_id: MatchId,
+}
+
+// The secret handed to each player when they subscribe, along with the match
+// it is good for. Shared between the service, which issues them, and the
+// interceptor, which checks them.
+#[derive(Clone, Default)]
+pub struct Secrets {
+	sessions: Arc<Mutex<HashMap<PlayerId, Session>>>,
+}
+
+impl Secrets {
+	pub fn issue(&self, player_id: PlayerId, match_id: MatchId) -> String {
+		let secret = format!("{:032x}", rand::rng().random::<u128>());
+		self.sessions
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.insert(
+				player_id,
+				Session {
+					secret_hash: hashed(&secret),
+					match_id,
+				},
+			);
+		secret
+	}
+
+	// The match the player is in, if the secret is theirs
+	pub fn verify(&self, player_id: PlayerId, secret: &str) -> Option<MatchId> {
+		let secret_hash = hashed(secret);
+		let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+		sessions
+			.get(&player_id)
+			.filter(|session| same_secret(&session.secret_hash, &secret_hash))
+			.map(|session| session.match_id)
+	}
+
+	// Everyone in the match, for its snapshot
+	pub fn saved(&self, match_id: MatchId) -> Vec<SavedSession> {
+		let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+		sessions
+			.iter()
+			.filter(|(_, session)| session.match_id == match_id)
+			.map(|(player_id, session)| SavedSession {
+				player_id: *player_id,
+				secret_hash: session.secret_hash.clone(),
+			})
+			.collect()
+	}
+
+	// Sessions from a snapshot, now good for the match it was restored as
+	pub fn restore(&self, match_id: MatchId, saved: &[SavedSession]) {
+		let mut sessions =
+			self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+		for session in saved {
+			sessions.insert(
+				session.player_id,
+				Session {
+					secret_hash: session.secret_hash.clone(),
+					match_id,
+				},
+			);
+		}
+	}
+
+	// Who was issued this secret, for picking a session back up
+	pub fn resume(&self, secret: &str) -> Option<(PlayerId, MatchId)> {
+		let secret_hash = hashed(secret);
+		let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+		sessions
+			.iter()
+			.find(|(_, session)| {
+				same_secret(&session.secret_hash, &secret_hash)
+			})
+			.map(|(player_id, session)| (*player_id, session.match_id))
+	}
+}
+
+// A player's session as it goes into the snapshot of their match, so they
+// can resume with the same secret after a restart. The snapshot only gets
+// the hash, anyone reading it still can't pass for the player.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedSession {
+	pub player_id: PlayerId,
+	pub secret_hash: String,
+}
+
+// One use tickets for reserved players, each already tied to the PlayerId
+// it was promised and the match it is in
+#[derive(Clone, Default)]
+pub struct JoinTickets {
+	tickets: Arc<Mutex<HashMap<String, (PlayerId, MatchId)>>>,
+}
+
+impl JoinTickets {
+	pub fn issue(&self, player_id: PlayerId, match_id: MatchId) -> String {
+		let ticket = format!("{:032x}", rand::rng().random::<u128>());
+		self.tickets
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.insert(ticket.clone(), (player_id, match_id));
+		ticket
+	}
+
+	pub fn redeem(&self, ticket: &str) -> Option<(PlayerId, MatchId)> {
+		self.tickets
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.remove(ticket)
+	}
+}
+
+// Secrets are long and random, so there's nothing to gain from salting or
+// stretching them
+fn hashed(secret: &str) -> String {
+	Sha256::digest(secret.as_bytes())
+		.iter()
+		.map(|byte| format!("{:02x}", byte))
+		.collect()
+}
+
+// Doesn't give away how much of the secret was right
+fn same_secret(expected: &str, secret: &str) -> bool {
+	expected.len() == secret.len()
+		&& expected
+			.bytes()
+			.zip(secret.bytes())
+			.fold(0u8, |diff, (a, b)| diff | (a ^ b))
+			== 0
+}
+
+// Put on the request by the interceptor once the credentials check out
+#[derive(Clone, Copy, Debug)]
+pub struct AuthenticatedPlayer {
+	pub player_id: PlayerId,
+	pub match_id: MatchId,
+}
+
+// Put on the request by the interceptor when it carries the lobby key
+#[derive(Clone, Copy, Debug)]
+pub struct AuthenticatedLobby;
+
+// Requests without credentials go through untouched, that is how Subscribe
+// gets a secret in the first place. Requests with credentials have to
+// match the secret that was issued, or the lobby key.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+	secrets: Secrets,
+	// no lobby gets in without one
+	lobby_key: Option<Arc<str>>,
+}
+
+impl AuthInterceptor {
+	pub fn new(secrets: Secrets, lobby_key: Option<String>) -> Self {
+		Self {
+			secrets,
+			lobby_key: lobby_key.map(Into::into),
+		}
+	}
+}
+
+impl Interceptor for AuthInterceptor {
+	fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+		let metadata = req.metadata();
+		if let Some(key) = metadata.get(LOBBY_KEY_HEADER) {
+			let key = key.to_str().ok().filter(|key| {
+				self.lobby_key
+					.as_ref()
+					.is_some_and(|expected| same_secret(expected, key))
+			});
+			if key.is_none() {
+				return Err(Status::unauthenticated("invalid lobby key"));
+			}
+			req.extensions_mut().insert(AuthenticatedLobby);
+			return Ok(req);
+		}
+		let player_id = metadata.get(PLAYER_ID_HEADER);
+		let secret = metadata.get(SECRET_HEADER);
+		if player_id.is_none() && secret.is_none() {
+			return Ok(req);
+		}
+		let player_id = player_id
+			.and_then(|v| v.to_str().ok())
+			.and_then(|s| s.parse::<PlayerId>().ok())
+			.ok_or_else(|| {
+				Status::unauthenticated("invalid player-id header")
+			})?;
+		let secret = secret
+			.and_then(|v| v.to_str().ok())
+			.ok_or_else(|| Status::unauthenticated("missing player-secret"))?;
+		let match_id =
+			self.secrets.verify(player_id, secret).ok_or_else(|| {
+				Status::unauthenticated("invalid player credentials")
+			})?;
+		req.extensions_mut().insert(AuthenticatedPlayer {
+			player_id,
+			match_id,
+		});
+		Ok(req)
+	}
+}
+
+pub fn authenticated_player<T>(
+	req: &Request<T>,
+) -> Result<AuthenticatedPlayer, Status> {
+	req.extensions()
+		.get::<AuthenticatedPlayer>()
+		.copied()
+		.ok_or_else(|| Status::unauthenticated("missing player credentials"))
+}
+
+pub fn authenticated_lobby<T>(req: &Request<T>) -> Result<(), Status> {
+	req.extensions()
+		.get::<AuthenticatedLobby>()
+		.map(|_| ())
+		.ok_or_else(|| Status::unauthenticated("missing lobby key"))
+}
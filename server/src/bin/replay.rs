@@ -0,0 +1,32 @@
+use anyhow::{Context, Result, anyhow};
+use server::replay;
+use std::path::PathBuf;
+
+// Plays a recorded match back through the current simulation and reports
+// the first step where it publishes something else than the recording
+fn main() -> Result<()> {
+	let path: PathBuf = std::env::args()
+		.nth(1)
+		.context("usage: replay <match.replay>")?
+		.into();
+	let report = replay::replay(&path)
+		.map_err(|e| anyhow!("{}", e))
+		.with_context(|| format!("unable to replay {}", path.display()))?;
+	let Some(divergence) = report.divergence else {
+		println!("Replayed {} steps, no divergence", report.steps);
+		return Ok(());
+	};
+	println!(
+		"Diverged at step {}, game time {}",
+		divergence.step, divergence.game_time
+	);
+	println!("Recorded:");
+	for event in &divergence.expected {
+		println!("  {:?}", event);
+	}
+	println!("Replayed:");
+	for event in &divergence.actual {
+		println!("  {:?}", event);
+	}
+	std::process::exit(1);
+}
@@ -0,0 +1,135 @@
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+use crate::state::inventory::ResourceRegistry;
+use crate::state::templates::UnitTemplate;
+use common::grpc;
+use common::model::{self, TemplateId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+// One kind of unit players can create
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CatalogEntry {
+	pub id: TemplateId,
+	pub name: String,
+	// how players see it, the shape is the unit's own
+	pub color: (u8, u8, u8),
+	#[serde(default = "filled")]
+	pub fill: bool,
+	// anything left out is taken from the default template
+	#[serde(flatten)]
+	pub template: UnitTemplate,
+}
+
+fn filled() -> bool {
+	true
+}
+
+// The unit types of a match, read from a JSON file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Catalog {
+	// the first one is what players get when they don't ask for one
+	pub templates: Vec<CatalogEntry>,
+}
+
+// The one blue unit everybody had before there were catalogs
+impl Default for Catalog {
+	fn default() -> Self {
+		Self {
+			templates: vec![CatalogEntry {
+				id: 1,
+				name: "unit".to_string(),
+				color: (0, 128, 255),
+				fill: true,
+				template: UnitTemplate::default(),
+			}],
+		}
+	}
+}
+
+fn invalid(path: &Path, reason: impl std::fmt::Display) -> EngineError {
+	eprintln!("Invalid catalog {}: {}", path.display(), reason);
+	engine_error!(EngineErrorKind::InvalidCatalog)
+}
+
+impl Catalog {
+	pub fn load(path: &Path) -> Result<Self, EngineError> {
+		let json = std::fs::read(path).map_err(|e| invalid(path, e))?;
+		let catalog: Self =
+			serde_json::from_slice(&json).map_err(|e| invalid(path, e))?;
+		catalog.check().map_err(|reason| invalid(path, reason))?;
+		Ok(catalog)
+	}
+
+	fn check(&self) -> Result<(), String> {
+		if self.templates.is_empty() {
+			return Err("there are no templates".to_string());
+		}
+		let mut ids = HashSet::new();
+		for entry in &self.templates {
+			if !ids.insert(entry.id) {
+				return Err(format!("template {} is there twice", entry.id));
+			}
+			let template = &entry.template;
+			let size = match &template.shape {
+				Some(model::Shape::Circle(r)) => *r,
+				Some(model::Shape::Rectangle(w, h)) => *w.min(h),
+				None => return Err(format!("{} has no shape", entry.name)),
+			};
+			if size <= 0
+				|| template.speed.is_some_and(|speed| speed < 0)
+				|| template.health.as_ref().is_some_and(|h| h.max <= 0)
+			{
+				return Err(format!("bad template {:?}", entry));
+			}
+			let registry = ResourceRegistry::default();
+			let mut used = 0;
+			for (name, amount) in &template.stock {
+				let Some(resource) =
+					registry.find(name).and_then(|id| registry.get(id))
+				else {
+					return Err(format!("unknown resource {}", name));
+				};
+				if *amount < 0 {
+					return Err(format!("bad stock of {}", name));
+				}
+				used += amount * resource.size;
+			}
+			if used > template.inventory_capacity.unwrap_or(0) {
+				return Err(format!("{} can't hold its stock", entry.name));
+			}
+		}
+		Ok(())
+	}
+
+	pub fn get(&self, id: TemplateId) -> Option<&CatalogEntry> {
+		self.templates.iter().find(|entry| entry.id == id)
+	}
+
+	pub fn default_id(&self) -> TemplateId {
+		self.templates.first().map_or(0, |entry| entry.id)
+	}
+}
+
+impl From<&CatalogEntry> for grpc::UnitTemplate {
+	fn from(entry: &CatalogEntry) -> Self {
+		let (r, g, b) = entry.color;
+		grpc::UnitTemplate {
+			id: entry.id,
+			name: entry.name.clone(),
+			shape: entry.template.shape.clone().map(Into::into),
+			color: Some(grpc::Color {
+				r: r.into(),
+				g: g.into(),
+				b: b.into(),
+			}),
+			fill: entry.fill,
+			speed: entry.template.speed,
+			health: entry.template.health.as_ref().map(|h| h.max),
+			vision: entry.template.vision,
+			cargo: entry.template.inventory_capacity,
+		}
+	}
+}
@@ -0,0 +1,133 @@
+use common::model::TimeStamp;
+
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+
+// Game time only advances while the clock is running, at `speed` game
+// milliseconds per wall millisecond. Every change re-anchors the clock so
+// that game time stays continuous.
+#[derive(Debug, Clone)]
+pub struct GameClock {
+	anchor_wall_ms: TimeStamp,
+	anchor_game_time: TimeStamp,
+	speed: f64,
+	paused: bool,
+}
+
+impl GameClock {
+	pub fn new(wall_ms: TimeStamp) -> Self {
+		Self {
+			anchor_wall_ms: wall_ms,
+			anchor_game_time: 0,
+			speed: 1.0,
+			paused: false,
+		}
+	}
+
+	// Picks up where a saved clock left off
+	pub fn restored(
+		wall_ms: TimeStamp,
+		game_time: TimeStamp,
+		speed: f64,
+		paused: bool,
+	) -> Self {
+		Self {
+			anchor_wall_ms: wall_ms,
+			anchor_game_time: game_time,
+			speed,
+			paused,
+		}
+	}
+
+	pub fn game_time(&self, wall_ms: TimeStamp) -> TimeStamp {
+		if self.paused {
+			return self.anchor_game_time;
+		}
+		let elapsed = wall_ms.saturating_sub(self.anchor_wall_ms) as f64;
+		self.anchor_game_time + (elapsed * self.speed) as TimeStamp
+	}
+
+	pub fn speed(&self) -> f64 {
+		self.speed
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.paused
+	}
+
+	pub fn pause(&mut self, wall_ms: TimeStamp) {
+		self.reanchor(wall_ms);
+		self.paused = true;
+	}
+
+	pub fn resume(&mut self, wall_ms: TimeStamp) {
+		self.reanchor(wall_ms);
+		self.paused = false;
+	}
+
+	pub fn set_speed(
+		&mut self,
+		wall_ms: TimeStamp,
+		speed: f64,
+	) -> Result<(), EngineError> {
+		if !speed.is_finite() || speed <= 0.0 {
+			return Err(engine_error!(EngineErrorKind::MalformedRequest));
+		}
+		self.reanchor(wall_ms);
+		self.speed = speed;
+		Ok(())
+	}
+
+	fn reanchor(&mut self, wall_ms: TimeStamp) {
+		self.anchor_game_time = self.game_time(wall_ms);
+		self.anchor_wall_ms = wall_ms;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn paused_game_time_stands_still() {
+		let mut clock = GameClock::new(1_000);
+		clock.pause(3_000);
+		assert_eq!(clock.game_time(3_000), 2_000);
+		assert_eq!(clock.game_time(10_000), 2_000);
+		clock.resume(10_000);
+		assert_eq!(clock.game_time(10_000), 2_000);
+		assert_eq!(clock.game_time(11_000), 3_000);
+	}
+
+	#[test]
+	fn speed_changes_carry_on_from_the_same_time() {
+		let mut clock = GameClock::new(0);
+		clock.set_speed(2_000, 3.0).unwrap();
+		assert_eq!(clock.game_time(2_000), 2_000);
+		assert_eq!(clock.game_time(3_000), 5_000);
+		clock.set_speed(3_000, 0.5).unwrap();
+		assert_eq!(clock.game_time(3_000), 5_000);
+		assert_eq!(clock.game_time(5_000), 6_000);
+	}
+
+	#[test]
+	fn speed_changes_while_paused_wait_for_the_resume() {
+		let mut clock = GameClock::new(0);
+		clock.pause(1_000);
+		clock.set_speed(2_000, 2.0).unwrap();
+		assert_eq!(clock.game_time(4_000), 1_000);
+		clock.resume(4_000);
+		assert_eq!(clock.game_time(5_000), 3_000);
+	}
+
+	#[test]
+	fn invalid_speeds_are_refused() {
+		let mut clock = GameClock::new(0);
+		for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+			assert!(clock.set_speed(1_000, speed).is_err());
+		}
+		assert_eq!(clock.speed(), 1.0);
+		assert_eq!(clock.game_time(2_000), 2_000);
+	}
+}
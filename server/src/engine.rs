@@ -1,9 +1,17 @@
+use crate::catalog::Catalog;
+use crate::clock::GameClock;
+use crate::map::Map;
+use crate::replay::Recorder;
+use crate::snapshot;
+use crate::snapshot::Snapshot;
 use crate::state::game::GameState;
 use crate::state::game::TaskProgress;
+use crate::state::game::{TRANSFER_DURATION, TRANSFER_RANGE};
+use crate::state::motion::{self, Mobility};
 use crate::state::tasks;
 use crate::state::tasks::CompletedTask;
 use crate::state::tasks::SimulatedTask;
-use crate::state::templates::UnitTemplate;
+use crate::state::templates::CollisionPolicy;
 
 // use single_value_channel::channel_starting_with;
 use tokio::sync::broadcast;
@@ -11,8 +19,8 @@ use tokio::sync::mpsc;
 use tokio::time::{Duration, interval};
 
 use crate::event;
+use common::model::TimeStamp;
 use common::model::{self};
-use common::model::{Coord, TimeStamp};
 
 use common::grpc;
 use std::error::Error;
@@ -24,6 +32,10 @@ pub enum EngineErrorKind {
 	UnableToSend,
 	InternalError,
 	InvalidUnitId,
+	NotOwner,
+	SnapshotFailed,
+	InvalidMap,
+	InvalidCatalog,
 }
 
 #[derive(Debug)]
@@ -37,6 +49,10 @@ impl EngineError {
 	pub fn new(kind: EngineErrorKind, file: &'static str, line: u32) -> Self {
 		Self { kind, file, line }
 	}
+
+	pub fn kind(&self) -> &EngineErrorKind {
+		&self.kind
+	}
 }
 
 #[macro_export]
@@ -61,14 +77,31 @@ fn wall_time() -> u64 {
 		.as_millis() as u64
 }
 
-async fn tick(
+fn send_synchronize(
 	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
-	game_state: &mut GameState,
+	clock: &GameClock,
+	wall_ms: TimeStamp,
 ) -> Result<(), EngineError> {
-	// For now: game_time == wall_time (you can change this later)
+	tick_completion_sender
+		.send(event::PublishEvent::TickCompleted(
+			event::TickCompletedEvent {
+				wall_ms,
+				game_time: clock.game_time(wall_ms),
+				speed: clock.speed(),
+				paused: clock.is_paused(),
+			},
+		))
+		.map_err(|_e| engine_error!(EngineErrorKind::UnableToSend))?;
+	Ok(())
+}
 
-	let wall_ms = wall_time();
-	let game_time = wall_ms;
+pub fn tick(
+	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
+	game_state: &mut GameState,
+	clock: &GameClock,
+	wall_ms: TimeStamp,
+) -> Result<(), EngineError> {
+	let game_time = clock.game_time(wall_ms);
 
 	while let Some(finish_time) = game_state.get_next_completion() {
 		if finish_time > game_time {
@@ -83,29 +116,132 @@ async fn tick(
 			tasks::SimulationEvent::TaskCompleted(ct) => {
 				game_state.task_completed(progress.finish_time, ct)?;
 			}
+			tasks::SimulationEvent::Collision(collision) => {
+				handle_collision(game_state, progress.finish_time, collision)?;
+			}
+			tasks::SimulationEvent::Sighting(sighting) => {
+				game_state.sighting_due(progress.finish_time, &sighting)?;
+				// right away, it may be out of sight again by the end of
+				// the tick
+				game_state.send_perspective_updates(
+					sighting.player_id,
+					tick_completion_sender,
+				)?;
+			}
 		}
 	}
 
 	game_state.advance_to_time(game_time);
 	game_state.send_incremental_updates(tick_completion_sender)?;
 
-	tick_completion_sender
-		.send(event::PublishEvent::TickCompleted(
-			event::TickCompletedEvent {
-				wall_ms: wall_ms,
-				game_time: game_time,
-			},
-		))
-		.map_err(|_e| engine_error!(EngineErrorKind::UnableToSend))?;
+	send_synchronize(tick_completion_sender, clock, wall_ms)?;
+
+	Ok(())
+}
 
+fn handle_collision(
+	game_state: &mut GameState,
+	game_time: TimeStamp,
+	collision: tasks::Collision,
+) -> Result<(), EngineError> {
+	println!(
+		"Units {} and {} collided at {}",
+		collision.unit_id, collision.other_id, game_time
+	);
+	// both stop first, so neither re-plans around where the other was headed
+	let mut stopped = Vec::new();
+	for (unit_id, other_id) in [
+		(collision.unit_id, collision.other_id),
+		(collision.other_id, collision.unit_id),
+	] {
+		// units standing still just get bumped into
+		if let Some(queue) = game_state.stop_unit(unit_id, game_time)? {
+			stopped.push((unit_id, other_id, queue));
+		}
+	}
+	for (unit_id, other_id, queue) in stopped {
+		match game_state.get_collision_policy(unit_id) {
+			CollisionPolicy::Stop => {}
+			CollisionPolicy::Replan => {
+				// A failed re-plan leaves the unit where it stopped
+				if let Err(e) =
+					replan(game_state, unit_id, other_id, queue, game_time)
+				{
+					eprintln!("Unable to re-plan unit {}: {}", unit_id, e);
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+// Carries on with the interrupted task from where the unit stopped, then
+// the rest of its queue, going around the unit it ran into
+fn replan(
+	game_state: &mut GameState,
+	unit_id: model::UnitId,
+	other_id: model::UnitId,
+	queue: Vec<model::Task>,
+	game_time: TimeStamp,
+) -> Result<(), EngineError> {
+	if queue.is_empty() {
+		return Ok(());
+	}
+	let simulated =
+		game_state.plan_around(&[other_id], game_time, |game_state| {
+			simulate_tasks(game_state, unit_id, queue, game_time)
+		})?;
+	game_state.set_task_queue_requested(unit_id, game_time, simulated)?;
+	// setting off straight into something again would never end
+	if game_state.collides_at(unit_id, game_time) {
+		game_state.stop_unit(unit_id, game_time)?;
+		return Err(engine_error!(EngineErrorKind::MalformedRequest));
+	}
 	Ok(())
 }
 
 pub async fn run_engine(
-	mut user_requests_receiver: mpsc::Receiver<event::PlayerRequest>,
+	map: Map,
+	catalog: Catalog,
+	user_requests_receiver: mpsc::Receiver<event::PendingRequest>,
+	tick_completion_sender: broadcast::Sender<event::PublishEvent>,
+	recorder: Option<Recorder>,
+) {
+	run_engine_with(
+		GameState::new(map, catalog),
+		GameClock::new(wall_time()),
+		user_requests_receiver,
+		tick_completion_sender,
+		recorder,
+	)
+	.await;
+}
+
+// Carries on a match from a snapshot
+pub async fn run_restored_engine(
+	snapshot: Snapshot,
+	user_requests_receiver: mpsc::Receiver<event::PendingRequest>,
+	tick_completion_sender: broadcast::Sender<event::PublishEvent>,
+	recorder: Option<Recorder>,
+) {
+	let (game_state, clock) = snapshot.restore(wall_time());
+	run_engine_with(
+		game_state,
+		clock,
+		user_requests_receiver,
+		tick_completion_sender,
+		recorder,
+	)
+	.await;
+}
+
+async fn run_engine_with(
+	mut game_state: GameState,
+	mut clock: GameClock,
+	mut user_requests_receiver: mpsc::Receiver<event::PendingRequest>,
 	mut tick_completion_sender: broadcast::Sender<event::PublishEvent>,
+	mut recorder: Option<Recorder>,
 ) {
-	let mut game_state = GameState::default();
 	let (tick_sender, mut tick_receiver) =
 		tokio::sync::watch::channel::<event::EngineEvent>(
 			event::EngineEvent::Tick(wall_time()),
@@ -116,30 +252,59 @@ pub async fn run_engine(
 	loop {
 		tokio::select! {
 			Ok(_) = tick_receiver.changed() => {
+				let wall_ms = wall_time();
 				match tick(
 					&mut tick_completion_sender,
 					&mut game_state,
-				).await {
+					&clock,
+					wall_ms,
+				) {
 					Ok(_) => {},
 					Err(e) => {
 						eprintln!("Error during tick: {:?}", e);
 					}
 				}
+				record(&mut recorder, |r| r.tick(clock.game_time(wall_ms)));
 			},
-			Some(request) = user_requests_receiver.recv() => {
-				match handle_user_request(request, &mut game_state,
-					 &mut tick_completion_sender).await {
-					Ok(_) => {},
-					Err(e) => {
-						eprintln!("Error handling user request: {}", e);
-					}
+			pending = user_requests_receiver.recv() => {
+				// the match was closed, nobody can send it anything anymore
+				let Some(pending) = pending else {
+					break;
+				};
+				let wall_ms = wall_time();
+				// the game time before the request, which may change the clock
+				let game_time = clock.game_time(wall_ms);
+				let request = recorder.as_ref().map(|_| pending.request.clone());
+				let result = handle_user_request(pending.request,
+					&mut game_state, &mut clock, wall_ms,
+					&mut tick_completion_sender);
+				if let Err(e) = &result {
+					eprintln!("Error handling user request: {}", e);
+				}
+				if let Some(request) = request {
+					record(&mut recorder, |r| r.request(game_time, request));
+				}
+				if let Some(reply) = pending.reply {
+					// the caller may have given up already
+					let _ = reply.send(result);
 				}
 			}
 		}
 	}
 }
 
-async fn handle_player_joined(
+// A replay that can't be written is given up on, the match carries on
+fn record(
+	recorder: &mut Option<Recorder>,
+	step: impl FnOnce(&mut Recorder) -> Result<(), EngineError>,
+) {
+	if let Some(Err(e)) = recorder.as_mut().map(step) {
+		eprintln!("Stopped recording: {}", e);
+		*recorder = None;
+	}
+}
+
+fn handle_player_joined(
 	player_id: u64,
 	game_state: &mut GameState,
 	_tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
@@ -147,45 +312,94 @@ async fn handle_player_joined(
 	println!("Player joined: {}", player_id);
 
 	// todo: send existing units to player...
-	game_state.add_player(player_id);
+	game_state.add_player(player_id)
+}
 
-	Ok(())
+fn handle_player_resumed(
+	player_id: u64,
+	game_state: &mut GameState,
+	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
+) -> Result<(), EngineError> {
+	println!("Player resumed: {}", player_id);
+
+	// Whatever they were shown before is gone with the old stream, so start
+	// over and bring them up to date without waiting for the next tick
+	game_state.add_player(player_id)?;
+	tick_completion_sender
+		.send(event::PublishEvent::Begin(
+			player_id,
+			game_state.get_current_time(),
+		))
+		.map_err(|_| engine_error!(EngineErrorKind::UnableToSend))?;
+	game_state.send_perspective_updates(player_id, tick_completion_sender)
 }
 
-async fn handle_player_left(
+fn handle_player_left(
 	player_id: u64,
-	_game_state: &mut GameState,
+	game_state: &mut GameState,
 	_tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
 ) -> Result<(), EngineError> {
 	println!("Player left: {}", player_id);
+	game_state.remove_player(player_id);
 	Ok(())
 }
 
-async fn handle_create_unit(
+fn handle_create_unit(
 	player_id: model::PlayerId,
 	unit_id: model::UnitId,
+	template_id: model::TemplateId,
 	game_state: &mut GameState,
 	_tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
 ) -> Result<(), EngineError> {
 	println!("Create unit: {}", unit_id);
+	let point = game_state.spawn_point(player_id);
 	game_state.add_unit(
 		player_id,
 		unit_id,
-		UnitTemplate::default(),
+		template_id,
 		model::OrientedPoint {
-			point: model::Point { x: 0.0, y: 0.0 },
+			point,
 			orientation: 0.0,
 		},
-	);
+	)?;
 	Ok(())
 }
 
-async fn handle_update_intentions(
+// Players can only command their own units. The player hears about a
+// refusal as a warning, on top of the error going back to the service.
+fn check_owner(
+	player_id: model::PlayerId,
+	unit_id: model::UnitId,
+	game_state: &GameState,
+	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
+) -> Result<(), EngineError> {
+	let Some(owner) = game_state.get_owner(unit_id) else {
+		return Err(engine_error!(EngineErrorKind::InvalidUnitId));
+	};
+	if owner == player_id {
+		return Ok(());
+	}
+	tick_completion_sender
+		.send(event::PublishEvent::Warning(event::WarningContent {
+			user_id: player_id,
+			message: format!("Unit {} does not belong to you", unit_id),
+		}))
+		.map_err(|_| engine_error!(EngineErrorKind::UnableToSend))?;
+	Err(engine_error!(EngineErrorKind::NotOwner))
+}
+
+fn handle_update_intentions(
+	player_id: model::PlayerId,
 	request: grpc::SetQueueRequest,
 	game_state: &mut GameState,
-	_tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
+	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
 ) -> Result<(), EngineError> {
-	// get current position...
+	check_owner(
+		player_id,
+		request.unit_id,
+		game_state,
+		tick_completion_sender,
+	)?;
 
 	let tasks = request
 		.tasks
@@ -200,7 +414,24 @@ async fn handle_update_intentions(
 		)
 		.map_err(|_| engine_error!(EngineErrorKind::MalformedRequest))?;
 
-	let simulated = simulate_tasks(game_state, request.unit_id, tasks)?;
+	// Handing over resources is fine, taking them needs the source
+	for task in &tasks {
+		if let model::Task::Transfer(transfer) = task {
+			check_owner(
+				player_id,
+				transfer.source_id,
+				game_state,
+				tick_completion_sender,
+			)?;
+		}
+	}
+
+	let simulated = simulate_tasks(
+		game_state,
+		request.unit_id,
+		tasks,
+		game_state.get_current_time(),
+	)?;
 	game_state.set_task_queue_requested(
 		request.unit_id,
 		game_state.get_current_time(),
@@ -262,38 +493,74 @@ async fn handle_update_intentions(
 	Ok(())
 }
 
-async fn handle_user_request(
+fn handle_change_clock(
+	change: event::ClockChange,
+	clock: &mut GameClock,
+	wall_ms: TimeStamp,
+	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
+) -> Result<(), EngineError> {
+	match change {
+		event::ClockChange::Pause => clock.pause(wall_ms),
+		event::ClockChange::Resume => clock.resume(wall_ms),
+		event::ClockChange::SetSpeed(speed) => {
+			clock.set_speed(wall_ms, speed)?
+		}
+	}
+	println!(
+		"Clock changed: speed {}, paused {}",
+		clock.speed(),
+		clock.is_paused()
+	);
+
+	// Don't make subscribers wait for the next tick to learn the new rate
+	send_synchronize(tick_completion_sender, clock, wall_ms)
+}
+
+pub fn handle_user_request(
 	request: event::PlayerRequest,
 	game_state: &mut GameState,
+	clock: &mut GameClock,
+	wall_ms: TimeStamp,
 	tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
 ) -> Result<(), EngineError> {
 	match request {
 		event::PlayerRequest::PlayerJoined(player_id) => {
-			handle_player_joined(player_id, game_state, tick_completion_sender)
-				.await?
+			handle_player_joined(player_id, game_state, tick_completion_sender)?
+		}
+		event::PlayerRequest::PlayerResumed(player_id) => {
+			handle_player_resumed(
+				player_id,
+				game_state,
+				tick_completion_sender,
+			)?
 		}
-		event::PlayerRequest::CreateUnit(player_id, unit_id) => {
+		event::PlayerRequest::CreateUnit(player_id, unit_id, template_id) => {
 			handle_create_unit(
 				player_id,
 				unit_id,
+				template_id,
 				game_state,
 				tick_completion_sender,
-			)
-			.await?
+			)?
 		}
-		event::PlayerRequest::UpdateIntentions(request) => {
+		event::PlayerRequest::UpdateIntentions(player_id, request) => {
 			handle_update_intentions(
+				player_id,
 				request,
 				game_state,
 				tick_completion_sender,
-			)
-			.await?
+			)?
 		}
 		event::PlayerRequest::PlayerLeft(player_id) => {
-			handle_player_left(player_id, game_state, tick_completion_sender)
-				.await?
+			handle_player_left(player_id, game_state, tick_completion_sender)?
 		}
-		event::PlayerRequest::ClearQueue(unit_id) => {
+		event::PlayerRequest::ClearQueue(player_id, unit_id) => {
+			check_owner(
+				player_id,
+				unit_id,
+				game_state,
+				tick_completion_sender,
+			)?;
 			game_state.set_task_queue_requested(
 				unit_id,
 				game_state.get_current_time(),
@@ -302,12 +569,20 @@ async fn handle_user_request(
 			tick_completion_sender
 				.send(event::PublishEvent::TasksUpdated(
 					event::TasksUpdatedEvent {
+						player_id,
 						unit_id,
 						tasks: vec![],
+						details: None,
 					},
 				))
 				.map_err(|_| engine_error!(EngineErrorKind::UnableToSend))?;
 		}
+		event::PlayerRequest::ChangeClock(change) => {
+			handle_change_clock(change, clock, wall_ms, tick_completion_sender)?
+		}
+		event::PlayerRequest::SaveSnapshot(path, sessions) => {
+			snapshot::save(&path, game_state, &sessions, clock, wall_ms)?
+		}
 	}
 
 	Ok(())
@@ -430,29 +705,33 @@ fn simulate_move(
 	to: model::Point,
 ) -> Result<SimulatedTask, EngineError> {
 	let speed = game_state.get_unit_speed(unit_id)?;
-	if speed < 1e-6 as Coord {
+	if speed <= 0 {
 		return Err(engine_error!(EngineErrorKind::MalformedRequest));
 	}
-	let dist = scratch_pad.current_location.point.distance_to(&to);
-	if dist < 1e-6 {
+	let from = scratch_pad.current_location.point.clone();
+	if from.distance_to(&to) == 0 {
 		return Err(engine_error!(EngineErrorKind::MalformedRequest));
 	}
-	let duration = dist / speed as f64;
-	let delta = model::Delta::between(&scratch_pad.current_location.point, &to)
-		.normalize(speed);
-	let finish_time = scratch_pad.current_time + duration as TimeStamp;
+	let waypoints = game_state.find_path(unit_id, &from, &to)?;
+	let mobility = Mobility {
+		speed,
+		acceleration: game_state.get_unit_acceleration(unit_id),
+		turn_rate: game_state.get_unit_turn_rate(unit_id),
+	};
+	let planned = motion::plan_move(
+		&mobility,
+		scratch_pad.current_time,
+		&scratch_pad.current_location,
+		&waypoints,
+		|a, b| game_state.is_clear(unit_id, a, b),
+	);
+	let finish_time = planned.finish_time;
 	let simulation_id = game_state.get_next_id();
 
 	let ret = SimulatedTask {
 		id: simulation_id,
 		task: task,
-		animation: model::AnimationSegment {
-			begin_time: scratch_pad.current_time,
-			begin_location: scratch_pad.current_location.point.clone(),
-			delta: Some(delta),
-			begin_orientation: 0.0,
-			d_orientation: None,
-		},
+		animation: planned.animation,
 		progress: TaskProgress {
 			finish_time,
 			completion: tasks::SimulationEvent::TaskCompleted(CompletedTask {
@@ -466,12 +745,80 @@ fn simulate_move(
 	scratch_pad.current_time = finish_time;
 	scratch_pad.current_location = model::OrientedPoint {
 		point: to,
-		orientation: 0.0,
+		orientation: planned.orientation,
 	};
 
 	return Ok(ret);
 }
 
+fn simulate_transfer(
+	game_state: &mut GameState,
+	unit_id: model::UnitId,
+	task: model::Task,
+	scratch_pad: &mut SimScratchPad,
+
+	transfer: model::Transfer,
+) -> Result<SimulatedTask, EngineError> {
+	if transfer.amount <= 0 || transfer.source_id == transfer.destination_id {
+		return Err(engine_error!(EngineErrorKind::MalformedRequest));
+	}
+	// The unit doing the transfer has to be one of the two ends
+	let other_id = if transfer.source_id == unit_id {
+		transfer.destination_id
+	} else if transfer.destination_id == unit_id {
+		transfer.source_id
+	} else {
+		return Err(engine_error!(EngineErrorKind::MalformedRequest));
+	};
+	if !game_state.has_unit(other_id) {
+		return Err(engine_error!(EngineErrorKind::InvalidUnitId));
+	}
+	if game_state.resources().get(transfer.resource_id).is_none()
+		|| !game_state.has_inventory(transfer.source_id)
+		|| !game_state.has_inventory(transfer.destination_id)
+	{
+		return Err(engine_error!(EngineErrorKind::MalformedRequest));
+	}
+	// The other unit may still move away, so the range is checked again when
+	// the transfer completes
+	let other =
+		game_state.expected_location(other_id, scratch_pad.current_time)?;
+	if scratch_pad.current_location.point.distance_to(&other.point)
+		> TRANSFER_RANGE
+	{
+		return Err(engine_error!(EngineErrorKind::MalformedRequest));
+	}
+
+	let finish_time = scratch_pad.current_time + TRANSFER_DURATION;
+	let simulation_id = game_state.get_next_id();
+
+	let ret = SimulatedTask {
+		id: simulation_id,
+		task: task.clone(),
+		animation: vec![model::AnimationSegment {
+			begin_time: scratch_pad.current_time,
+			begin_location: scratch_pad.current_location.point.clone(),
+			delta: None,
+			acceleration: None,
+			arc: None,
+			begin_orientation: scratch_pad.current_location.orientation,
+			d_orientation: None,
+		}],
+		progress: TaskProgress {
+			finish_time,
+			completion: tasks::SimulationEvent::TaskCompleted(CompletedTask {
+				unit_id,
+				simulation_id,
+				task,
+			}),
+		},
+	};
+
+	scratch_pad.current_time = finish_time;
+
+	Ok(ret)
+}
+
 fn simulate_task(
 	game_state: &mut GameState,
 	unit_id: model::UnitId,
@@ -484,7 +831,9 @@ fn simulate_task(
 		model::Task::MoveTo(to) => {
 			simulate_move(game_state, unit_id, t, scratch_pad, to)
 		}
-		_ => Err(engine_error!(EngineErrorKind::MalformedRequest)),
+		model::Task::Transfer(transfer) => {
+			simulate_transfer(game_state, unit_id, t, scratch_pad, transfer)
+		}
 	}
 }
 
@@ -492,9 +841,9 @@ fn simulate_tasks(
 	game_state: &mut GameState,
 	unit_id: model::UnitId,
 	tasks: Vec<model::Task>,
+	begin_time: TimeStamp,
 ) -> Result<Vec<SimulatedTask>, EngineError> {
 	// TODO: skip things in the past
-	let begin_time = game_state.get_current_time();
 	let mut scratch_pad = SimScratchPad {
 		current_time: begin_time,
 		current_location: game_state.get_unit_location(unit_id, begin_time)?,
@@ -1,47 +1,111 @@
 use common::model;
 use common::model::PlayerId;
+use common::model::TemplateId;
 use common::model::TimeStamp;
 use common::model::UnitId;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::oneshot;
+
+use crate::auth::SavedSession;
+use crate::engine::EngineError;
 
 pub struct TickEvent {
 	pub wall_ms: TimeStamp,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TickCompletedEvent {
 	pub wall_ms: TimeStamp,
 	pub game_time: TimeStamp,
+	pub speed: f64,
+	pub paused: bool,
 }
 
 pub struct UpdateIntentionsEvent {}
 
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ClockChange {
+	Pause,
+	Resume,
+	SetSpeed(f64),
+}
+
+// Serialized for replays, see crate::replay
+#[derive(Clone, Serialize, Deserialize)]
 pub enum PlayerRequest {
 	PlayerJoined(PlayerId),
-	CreateUnit(PlayerId, UnitId),
-	UpdateIntentions(common::grpc::SetQueueRequest),
-	ClearQueue(UnitId),
+	PlayerResumed(PlayerId),
+	CreateUnit(PlayerId, UnitId, TemplateId),
+	UpdateIntentions(
+		PlayerId,
+		#[serde(with = "crate::replay::protobuf")]
+		common::grpc::SetQueueRequest,
+	),
+	ClearQueue(PlayerId, UnitId),
 	PlayerLeft(PlayerId),
+	ChangeClock(ClockChange),
+	// write the whole match to this file, along with the sessions of its
+	// players
+	SaveSnapshot(PathBuf, Vec<SavedSession>),
+}
+
+// What the service hands the engine. `reply` is there when the caller waits
+// to hear whether the request was accepted.
+pub struct PendingRequest {
+	pub request: PlayerRequest,
+	pub reply: Option<oneshot::Sender<Result<(), EngineError>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WarningContent {
 	pub user_id: PlayerId,
 	pub message: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitCreatedEvent {
+	pub player_id: PlayerId,
+	pub details: Option<model::UnitDetails>,
+	pub anim: model::Animatable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TasksUpdatedEvent {
+	pub player_id: PlayerId,
 	pub unit_id: UnitId,
+	#[serde(with = "crate::replay::protobufs")]
 	pub tasks: Vec<common::grpc::AnimationSegment>,
+	pub details: Option<model::UnitDetails>,
 }
 
-#[derive(Debug, Clone)]
+// Serialized for replays too, which compare what was published
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PublishEvent {
 	Warning(WarningContent),
-	UnitCreated(/*model::UnitDetails, */ model::Animatable),
+	UnitCreated(UnitCreatedEvent),
 	TickCompleted(TickCompletedEvent),
 	TasksUpdated(TasksUpdatedEvent),
-	TasksCompleted(UnitId),
+	TasksCompleted(PlayerId, UnitId),
+	UnitHidden(PlayerId, UnitId),
+	// the player's view was reset, a snapshot at this game time follows
+	Begin(PlayerId, TimeStamp),
+}
+
+impl PublishEvent {
+	// The only player whose viewer should forward this, None for events
+	// every player gets
+	pub fn recipient(&self) -> Option<PlayerId> {
+		match self {
+			PublishEvent::Warning(warning) => Some(warning.user_id),
+			PublishEvent::UnitCreated(created) => Some(created.player_id),
+			PublishEvent::TickCompleted(_) => None,
+			PublishEvent::TasksUpdated(updates) => Some(updates.player_id),
+			PublishEvent::TasksCompleted(player_id, _) => Some(*player_id),
+			PublishEvent::UnitHidden(player_id, _) => Some(*player_id),
+			PublishEvent::Begin(player_id, _) => Some(*player_id),
+		}
+	}
 }
 
 pub enum EngineEvent {
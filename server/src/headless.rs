@@ -0,0 +1,740 @@
+use crate::clock::GameClock;
+use crate::engine;
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+use crate::event::{PlayerRequest, PublishEvent};
+use crate::snapshot::Snapshot;
+use crate::state::game::GameState;
+use common::model::TimeStamp;
+use tokio::sync::broadcast;
+
+// Same cadence as the ticker in run_engine
+pub const DEFAULT_TICK_INTERVAL: TimeStamp = 30;
+
+const EVENT_BUFFER: usize = 1 << 16;
+
+// Drives a GameState without tokio timers or the system clock.
+// Time only moves when `run_until` is called, ticks happen every
+// `tick_interval` wall milliseconds of the driver's own clock, and scheduled
+// requests are applied right after a tick at exactly their time.
+pub struct HeadlessEngine {
+	game_state: GameState,
+	clock: GameClock,
+	wall_ms: TimeStamp,
+	tick_interval: TimeStamp,
+	// kept sorted by time, requests at the same time stay in insertion order
+	scheduled: Vec<(TimeStamp, PlayerRequest)>,
+	sender: broadcast::Sender<PublishEvent>,
+	receiver: broadcast::Receiver<PublishEvent>,
+}
+
+impl Default for HeadlessEngine {
+	fn default() -> Self {
+		Self::with_clock(GameClock::new(0), 0)
+	}
+}
+
+impl HeadlessEngine {
+	// `wall_ms` is where the driver's clock starts; it should match the
+	// wall time the game clock was created with.
+	pub fn with_clock(clock: GameClock, wall_ms: TimeStamp) -> Self {
+		Self::with_state(GameState::default(), clock, wall_ms)
+	}
+
+	// Runs `game_state` instead of an empty match on the default map
+	pub fn with_state(
+		game_state: GameState,
+		clock: GameClock,
+		wall_ms: TimeStamp,
+	) -> Self {
+		let (sender, receiver) = broadcast::channel(EVENT_BUFFER);
+		Self {
+			game_state,
+			clock,
+			wall_ms,
+			tick_interval: DEFAULT_TICK_INTERVAL,
+			scheduled: Vec::new(),
+			sender,
+			receiver,
+		}
+	}
+
+	// Carries on from a snapshot, with the driver's clock at `wall_ms`
+	pub fn restored(snapshot: Snapshot, wall_ms: TimeStamp) -> Self {
+		let (game_state, clock) = snapshot.restore(wall_ms);
+		Self::with_state(game_state, clock, wall_ms)
+	}
+
+	pub fn set_tick_interval(&mut self, tick_interval: TimeStamp) {
+		self.tick_interval = tick_interval.max(1);
+	}
+
+	pub fn game_state(&self) -> &GameState {
+		&self.game_state
+	}
+
+	// For setting up a match, e.g. placing obstacles, before running it
+	pub fn game_state_mut(&mut self) -> &mut GameState {
+		&mut self.game_state
+	}
+
+	pub fn wall_time(&self) -> TimeStamp {
+		self.wall_ms
+	}
+
+	pub fn game_time(&self) -> TimeStamp {
+		self.clock.game_time(self.wall_ms)
+	}
+
+	pub fn schedule(&mut self, at: TimeStamp, request: PlayerRequest) {
+		let index = self.scheduled.partition_point(|(t, _)| *t <= at);
+		self.scheduled.insert(index, (at, request));
+	}
+
+	pub fn schedule_all(
+		&mut self,
+		requests: impl IntoIterator<Item = (TimeStamp, PlayerRequest)>,
+	) {
+		for (at, request) in requests {
+			self.schedule(at, request);
+		}
+	}
+
+	// Advances the driver's clock to `target`, ticking and applying every
+	// request scheduled up to and including it. Requests scheduled in the
+	// past are applied immediately. Returns everything that was published
+	// along the way, in order.
+	pub fn run_until(
+		&mut self,
+		target: TimeStamp,
+	) -> Result<Vec<PublishEvent>, EngineError> {
+		let mut events = Vec::new();
+		loop {
+			let next_tick = self.wall_ms + self.tick_interval;
+			let next_request = self.scheduled.first().map(|(at, _)| *at);
+			match next_request {
+				Some(at) if at <= next_tick && at <= target => {
+					let (at, request) = self.scheduled.remove(0);
+					self.wall_ms = self.wall_ms.max(at);
+					self.tick()?;
+					// Like run_engine, a bad request doesn't stop the game
+					if let Err(e) = self.apply(request) {
+						eprintln!("Error handling user request: {}", e);
+					}
+				}
+				_ if next_tick <= target => {
+					self.wall_ms = next_tick;
+					self.tick()?;
+				}
+				_ => break,
+			}
+			self.drain(&mut events)?;
+		}
+		if self.wall_ms < target {
+			self.wall_ms = target;
+			self.tick()?;
+			self.drain(&mut events)?;
+		}
+		Ok(events)
+	}
+
+	// Applies a request now, without advancing the clock
+	pub fn apply_now(
+		&mut self,
+		request: PlayerRequest,
+	) -> Result<Vec<PublishEvent>, EngineError> {
+		let mut events = Vec::new();
+		self.apply(request)?;
+		self.drain(&mut events)?;
+		Ok(events)
+	}
+
+	// Ticks once with the game clock stopped at `game_time`, for stepping
+	// through times that were recorded rather than at the driver's pace
+	pub fn tick_at(
+		&mut self,
+		game_time: TimeStamp,
+	) -> Result<Vec<PublishEvent>, EngineError> {
+		let mut events = Vec::new();
+		self.stop_clock_at(game_time);
+		self.tick()?;
+		self.drain(&mut events)?;
+		Ok(events)
+	}
+
+	// Applies a request with the game clock stopped at `game_time`. A
+	// refused request is logged like in run_until, what it published is
+	// still returned.
+	pub fn apply_at(
+		&mut self,
+		game_time: TimeStamp,
+		request: PlayerRequest,
+	) -> Result<Vec<PublishEvent>, EngineError> {
+		let mut events = Vec::new();
+		self.stop_clock_at(game_time);
+		if let Err(e) = self.apply(request) {
+			eprintln!("Error handling user request: {}", e);
+		}
+		self.drain(&mut events)?;
+		Ok(events)
+	}
+
+	fn stop_clock_at(&mut self, game_time: TimeStamp) {
+		self.clock = GameClock::restored(self.wall_ms, game_time, 1.0, true);
+	}
+
+	fn tick(&mut self) -> Result<(), EngineError> {
+		engine::tick(
+			&mut self.sender,
+			&mut self.game_state,
+			&self.clock,
+			self.wall_ms,
+		)
+	}
+
+	fn apply(&mut self, request: PlayerRequest) -> Result<(), EngineError> {
+		engine::handle_user_request(
+			request,
+			&mut self.game_state,
+			&mut self.clock,
+			self.wall_ms,
+			&mut self.sender,
+		)
+	}
+
+	fn drain(
+		&mut self,
+		events: &mut Vec<PublishEvent>,
+	) -> Result<(), EngineError> {
+		loop {
+			match self.receiver.try_recv() {
+				Ok(event) => events.push(event),
+				Err(broadcast::error::TryRecvError::Empty) => return Ok(()),
+				Err(_) => {
+					return Err(engine_error!(EngineErrorKind::UnableToSend));
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::auth::Secrets;
+	use crate::catalog::Catalog;
+	use crate::map::Map;
+	use crate::snapshot;
+	use crate::state::inventory::{Inventory, ResourceRegistry};
+	use crate::state::tasks::SimulationEvent;
+	use crate::state::templates::CollisionPolicy;
+	use common::grpc::{MoveTo, SetQueueRequest, Task, task};
+	use common::model::{METERS, PlayerId, Point, SECONDS, UnitId};
+
+	// Two players spawning ten meters apart so their units stay clear
+	fn engine() -> HeadlessEngine {
+		engine_with(
+			Point {
+				x: 0,
+				y: 10 * METERS,
+			},
+			Catalog::default(),
+		)
+	}
+
+	// Player 1's unit 10 at the origin, player 2's unit 20 at `second`
+	fn engine_with(second: Point, catalog: Catalog) -> HeadlessEngine {
+		let map = Map {
+			spawns: vec![Point { x: 0, y: 0 }, second],
+			..Map::default()
+		};
+		let game_state = GameState::new(map, catalog);
+		let mut engine =
+			HeadlessEngine::with_state(game_state, GameClock::new(0), 0);
+		for (player_id, unit_id) in [(1, 10), (2, 20)] {
+			engine
+				.apply_now(PlayerRequest::PlayerJoined(player_id))
+				.unwrap();
+			engine
+				.apply_now(PlayerRequest::CreateUnit(player_id, unit_id, 1))
+				.unwrap();
+		}
+		engine
+	}
+
+	fn location(engine: &HeadlessEngine, unit_id: UnitId) -> Point {
+		let at = engine.game_time();
+		engine
+			.game_state()
+			.get_unit_location(unit_id, at)
+			.unwrap()
+			.point
+	}
+
+	fn move_to(
+		player_id: PlayerId,
+		unit_id: UnitId,
+		x: i64,
+		y: i64,
+	) -> PlayerRequest {
+		PlayerRequest::UpdateIntentions(
+			player_id,
+			SetQueueRequest {
+				unit_id,
+				tasks: vec![Task {
+					kind: Some(task::Kind::Move(MoveTo {
+						destination: Some(common::grpc::Point { x, y }),
+					})),
+				}],
+			},
+		)
+	}
+
+	// The units that came to rest, in the order their owners heard about
+	// it. Player 1 owns unit 10 and player 2 unit 20.
+	fn completed(events: &[PublishEvent]) -> Vec<UnitId> {
+		events
+			.iter()
+			.filter_map(|event| match event {
+				PublishEvent::TasksUpdated(updated)
+					if updated.unit_id == 10 * updated.player_id
+						&& updated.tasks.iter().all(|segment| {
+							segment.delta.is_none()
+								&& segment.arc.is_none() && segment
+								.d_orientation
+								.is_none()
+						}) =>
+				{
+					Some(updated.unit_id)
+				}
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn move_completes_at_destination() {
+		let mut engine = engine();
+		engine.schedule(0, move_to(1, 10, 2 * METERS, 0));
+
+		let events = engine.run_until(SECONDS / 2).unwrap();
+		assert!(events.iter().any(|event| matches!(
+			event,
+			PublishEvent::TasksUpdated(updated) if updated.unit_id == 10
+		)));
+		assert!(completed(&events).is_empty());
+
+		let events = engine.run_until(20 * SECONDS).unwrap();
+		assert_eq!(completed(&events), vec![10]);
+		let at = engine.game_time();
+		let location = engine.game_state().get_unit_location(10, at).unwrap();
+		assert_eq!(
+			location.point,
+			Point {
+				x: 2 * METERS,
+				y: 0
+			}
+		);
+	}
+
+	#[test]
+	fn shorter_move_completes_first() {
+		let mut engine = engine();
+		engine.schedule(0, move_to(1, 10, 8 * METERS, 0));
+		engine.schedule(0, move_to(2, 20, 2 * METERS, 10 * METERS));
+
+		let events = engine.run_until(30 * SECONDS).unwrap();
+		assert_eq!(completed(&events), vec![20, 10]);
+	}
+
+	#[test]
+	fn queued_moves_complete_once_all_are_done() {
+		let mut engine = engine();
+		engine.schedule(0, move_to(1, 10, 2 * METERS, 0));
+		engine.schedule(SECONDS, move_to(2, 20, 2 * METERS, 10 * METERS));
+
+		let events = engine.run_until(30 * SECONDS).unwrap();
+		assert_eq!(completed(&events), vec![10, 20]);
+	}
+
+	#[test]
+	fn stopped_by_a_collision() {
+		let mut engine = engine_with(
+			Point {
+				x: 4 * METERS,
+				y: 0,
+			},
+			Catalog::default(),
+		);
+		engine.schedule(0, move_to(1, 10, 8 * METERS, 0));
+
+		engine.run_until(30 * SECONDS).unwrap();
+		assert!(location(&engine, 10).x < 4 * METERS);
+		assert_eq!(
+			location(&engine, 20),
+			Point {
+				x: 4 * METERS,
+				y: 0
+			}
+		);
+	}
+
+	#[test]
+	fn replanned_around_a_collision() {
+		let mut catalog = Catalog::default();
+		catalog.templates[0].template.collision_policy =
+			Some(CollisionPolicy::Replan);
+		let mut engine = engine_with(
+			Point {
+				x: 4 * METERS,
+				y: 0,
+			},
+			catalog,
+		);
+		engine.schedule(0, move_to(1, 10, 8 * METERS, 0));
+
+		let events = engine.run_until(30 * SECONDS).unwrap();
+		assert_eq!(completed(&events), vec![10]);
+		assert_eq!(
+			location(&engine, 10),
+			Point {
+				x: 8 * METERS,
+				y: 0
+			}
+		);
+		assert_eq!(
+			location(&engine, 20),
+			Point {
+				x: 4 * METERS,
+				y: 0
+			}
+		);
+	}
+
+	// Player 2's unit 20 walks from thirty meters east of player 1's unit to
+	// thirty meters west, passing three meters from it and within its ten
+	// meters of vision for about twenty seconds
+	fn passer_by() -> HeadlessEngine {
+		let mut engine = engine_with(
+			Point {
+				x: 30 * METERS,
+				y: 3 * METERS,
+			},
+			Catalog::default(),
+		);
+		engine.schedule(0, move_to(2, 20, -30 * METERS, 3 * METERS));
+		engine
+	}
+
+	#[test]
+	fn seen_crossing_between_ticks() {
+		let mut engine = passer_by();
+		engine.set_tick_interval(60 * SECONDS);
+
+		let events = engine.run_until(120 * SECONDS).unwrap();
+		let sightings = events
+			.iter()
+			.filter_map(|event| match event {
+				PublishEvent::UnitCreated(created)
+					if created.player_id == 1 && created.anim.unit_id == 20 =>
+				{
+					Some(true)
+				}
+				PublishEvent::UnitHidden(1, 20) => Some(false),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(sightings, vec![true, false]);
+	}
+
+	#[test]
+	fn others_see_the_queue_while_in_sight() {
+		let mut engine = passer_by();
+
+		let events = engine.run_until(120 * SECONDS).unwrap();
+		let seen = events
+			.iter()
+			.find_map(|event| match event {
+				PublishEvent::UnitCreated(created)
+					if created.player_id == 1 && created.anim.unit_id == 20 =>
+				{
+					Some(&created.anim.queue)
+				}
+				_ => None,
+			})
+			.unwrap();
+		// out of sight ten meters west, after about forty seconds
+		assert!(seen.iter().all(|segment| segment.begin_time < 42 * SECONDS));
+		let owned = events
+			.iter()
+			.find_map(|event| match event {
+				PublishEvent::TasksUpdated(updated)
+					if updated.player_id == 2 && updated.unit_id == 20 =>
+				{
+					Some(&updated.tasks)
+				}
+				_ => None,
+			})
+			.unwrap();
+		assert!(
+			owned
+				.iter()
+				.any(|segment| segment.begin_time > 50 * SECONDS)
+		);
+	}
+
+	fn transfer(
+		resource_id: u64,
+		amount: i32,
+		(source_id, destination_id): (UnitId, UnitId),
+	) -> Task {
+		Task {
+			kind: Some(task::Kind::Transfer(common::grpc::Transfer {
+				resource_id,
+				source_id,
+				destination_id,
+				amount,
+			})),
+		}
+	}
+
+	fn queue(
+		player_id: PlayerId,
+		unit_id: UnitId,
+		tasks: Vec<Task>,
+	) -> PlayerRequest {
+		PlayerRequest::UpdateIntentions(
+			player_id,
+			SetQueueRequest { unit_id, tasks },
+		)
+	}
+
+	fn gold_of(engine: &HeadlessEngine, unit_id: UnitId) -> i32 {
+		let gold = engine.game_state().resources().find("gold").unwrap();
+		engine.game_state().get_inventory_amount(unit_id, gold)
+	}
+
+	#[test]
+	fn transfer_moves_what_fits() {
+		let mut engine = engine_with(
+			Point {
+				x: 3 * METERS / 2,
+				y: 0,
+			},
+			Catalog::default(),
+		);
+		let gold = engine.game_state().resources().find("gold").unwrap();
+		// both start out with ten wood, so unit 20 has room for two more
+		engine.game_state_mut().deposit(10, gold, 5).unwrap();
+		engine.game_state_mut().deposit(20, gold, 8).unwrap();
+
+		engine.schedule(0, queue(1, 10, vec![transfer(gold, 5, (10, 20))]));
+		engine.run_until(SECONDS / 2).unwrap();
+		assert_eq!((gold_of(&engine, 10), gold_of(&engine, 20)), (5, 8));
+
+		engine.run_until(2 * SECONDS).unwrap();
+		assert_eq!((gold_of(&engine, 10), gold_of(&engine, 20)), (3, 10));
+	}
+
+	#[test]
+	fn transfer_out_of_range_is_refused() {
+		let mut engine = engine();
+		let gold = engine.game_state().resources().find("gold").unwrap();
+		engine.game_state_mut().deposit(10, gold, 5).unwrap();
+
+		let request = queue(1, 10, vec![transfer(gold, 5, (10, 20))]);
+		assert!(engine.apply_now(request).is_err());
+		engine.run_until(2 * SECONDS).unwrap();
+		assert_eq!((gold_of(&engine, 10), gold_of(&engine, 20)), (5, 0));
+	}
+
+	// The range is checked where both units will be once this one gets
+	// there, not where the other one starts out
+	#[test]
+	fn transfer_after_both_units_meet() {
+		let mut engine = engine();
+		let gold = engine.game_state().resources().find("gold").unwrap();
+		engine.game_state_mut().deposit(10, gold, 5).unwrap();
+
+		engine.schedule(0, move_to(2, 20, 0, 6 * METERS));
+		let meet = SetQueueRequest {
+			unit_id: 10,
+			tasks: vec![
+				Task {
+					kind: Some(task::Kind::Move(MoveTo {
+						destination: Some(common::grpc::Point {
+							x: 3 * METERS / 2,
+							y: 6 * METERS,
+						}),
+					})),
+				},
+				transfer(gold, 5, (10, 20)),
+			],
+		};
+		engine.schedule(0, PlayerRequest::UpdateIntentions(1, meet));
+		engine.run_until(20 * SECONDS).unwrap();
+		assert_eq!((gold_of(&engine, 10), gold_of(&engine, 20)), (0, 5));
+	}
+
+	#[test]
+	fn inventory_holds_up_to_its_capacity() {
+		let registry = ResourceRegistry::default();
+		let wood = registry.find("wood").unwrap();
+		let stone = registry.find("stone").unwrap();
+		let mut inventory = Inventory::new(20);
+		inventory.add(&registry, wood, 10).unwrap();
+		// stone takes up two each
+		assert_eq!(inventory.room_for(&registry, stone).unwrap(), 5);
+		assert!(inventory.add(&registry, stone, 6).is_err());
+		assert!(inventory.add(&registry, stone, -1).is_err());
+		inventory.add(&registry, stone, 5).unwrap();
+		assert_eq!(inventory.room_for(&registry, wood).unwrap(), 0);
+
+		inventory.remove(wood, 3).unwrap();
+		assert_eq!(inventory.room_for(&registry, stone).unwrap(), 1);
+		assert_eq!(inventory.room_for(&registry, wood).unwrap(), 3);
+		assert!(inventory.room_for(&registry, 0).is_err());
+	}
+
+	#[test]
+	fn deposits_past_capacity_are_refused() {
+		let mut engine = engine();
+		let wood = engine.game_state().resources().find("wood").unwrap();
+		assert!(engine.game_state_mut().deposit(10, wood, 11).is_err());
+		assert_eq!(engine.game_state().get_inventory_amount(10, wood), 10);
+		engine.game_state_mut().deposit(10, wood, 10).unwrap();
+		assert_eq!(engine.game_state().get_inventory_amount(10, wood), 20);
+		assert!(engine.game_state_mut().withdraw(10, wood, 21).is_err());
+	}
+
+	#[test]
+	fn inventory_details_reach_only_the_owner() {
+		// close enough for each to see the other's unit
+		let mut engine = engine_with(
+			Point {
+				x: 3 * METERS / 2,
+				y: 0,
+			},
+			Catalog::default(),
+		);
+		let gold = engine.game_state().resources().find("gold").unwrap();
+		let mut events = engine.run_until(SECONDS).unwrap();
+		engine.game_state_mut().deposit(10, gold, 3).unwrap();
+		events.extend(engine.run_until(2 * SECONDS).unwrap());
+
+		// what each player heard about unit 10, as it was created and then
+		// once the gold came in
+		let details = |player_id| {
+			events
+				.iter()
+				.filter_map(|event| match event {
+					PublishEvent::UnitCreated(created)
+						if created.player_id == player_id
+							&& created.anim.unit_id == 10 =>
+					{
+						Some(created.details.clone())
+					}
+					PublishEvent::TasksUpdated(updated)
+						if updated.player_id == player_id
+							&& updated.unit_id == 10 =>
+					{
+						Some(updated.details.clone())
+					}
+					_ => None,
+				})
+				.collect::<Vec<_>>()
+		};
+		let owned = details(1);
+		assert_eq!(owned.len(), 2);
+		let gold_held = |details: &Option<common::model::UnitDetails>| {
+			let details = details.as_ref().unwrap();
+			assert_eq!(details.inventory_capacity, 20);
+			details
+				.inventory
+				.iter()
+				.find(|held| held.resource_id == gold)
+				.map_or(0, |held| held.amount)
+		};
+		assert_eq!(gold_held(&owned[0]), 0);
+		assert_eq!(gold_held(&owned[1]), 3);
+		let seen = details(2);
+		assert!(!seen.is_empty());
+		assert!(seen.iter().all(Option::is_none));
+	}
+
+	#[test]
+	fn nothing_is_worked_out_for_players_who_left() {
+		let mut engine = engine_with(
+			Point {
+				x: 3 * METERS / 2,
+				y: 0,
+			},
+			Catalog::default(),
+		);
+		engine.run_until(SECONDS).unwrap();
+		engine.apply_now(PlayerRequest::PlayerLeft(2)).unwrap();
+		engine.schedule(2 * SECONDS, move_to(1, 10, 0, 5 * METERS));
+		let events = engine.run_until(20 * SECONDS).unwrap();
+		assert!(events.iter().all(|event| event.recipient() != Some(2)));
+		assert!(!engine.game_state().in_progress.iter().any(|tp| matches!(
+			&tp.completion,
+			SimulationEvent::Sighting(sighting) if sighting.player_id == 2
+		)));
+
+		// back in, and unit 10 is still close enough to see
+		let events = engine.apply_now(PlayerRequest::PlayerResumed(2)).unwrap();
+		assert!(events.iter().any(|event| matches!(
+			event,
+			PublishEvent::UnitCreated(created)
+				if created.player_id == 2 && created.anim.unit_id == 10
+		)));
+	}
+
+	// Saved between ticks, after the move finished but before any tick
+	// noticed. Restored much later by the wall clock.
+	#[test]
+	fn restore_carries_on_from_the_snapshot() {
+		let mut engine = engine();
+		engine.set_tick_interval(SECONDS);
+		engine.schedule(0, move_to(1, 10, 2 * METERS, 0));
+		let events = engine.run_until(2 * SECONDS).unwrap();
+		assert!(completed(&events).is_empty());
+
+		let secrets = Secrets::default();
+		let secret = secrets.issue(1, 1);
+		let path = std::env::temp_dir()
+			.join(format!("headless-{}.json", std::process::id()));
+		snapshot::save(
+			&path,
+			engine.game_state(),
+			&secrets.saved(1),
+			&GameClock::new(0),
+			3 * SECONDS,
+		)
+		.unwrap();
+		let json = std::fs::read_to_string(&path).unwrap();
+		assert!(!json.contains(&secret));
+		let snapshot = Snapshot::load(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let restored_secrets = Secrets::default();
+		restored_secrets.restore(7, snapshot.sessions());
+		assert_eq!(restored_secrets.resume(&secret), Some((1, 7)));
+		assert_eq!(restored_secrets.verify(1, "not it"), None);
+
+		let wall = 100 * SECONDS;
+		let mut engine = HeadlessEngine::restored(snapshot, wall);
+		assert_eq!(engine.game_time(), 3 * SECONDS);
+		let events = engine.run_until(wall + SECONDS / 10).unwrap();
+		assert_eq!(engine.game_time(), 3 * SECONDS + SECONDS / 10);
+		assert_eq!(completed(&events), vec![10]);
+		assert_eq!(
+			location(&engine, 10),
+			Point {
+				x: 2 * METERS,
+				y: 0
+			}
+		);
+	}
+}
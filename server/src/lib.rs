@@ -0,0 +1,13 @@
+pub mod auth;
+pub mod catalog;
+pub mod clock;
+pub mod engine;
+pub mod event;
+pub mod headless;
+pub mod map;
+pub mod matches;
+pub mod replay;
+pub mod snapshot;
+pub mod state;
+pub mod svc;
+pub mod viewer;
@@ -1,40 +1,132 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use common::grpc::shape_events_server::ShapeEventsServer;
 use http::header::HeaderName;
+use server::auth::AuthInterceptor;
+use server::catalog::Catalog;
+use server::map::Map;
+use server::matches::Matches;
+use server::snapshot::Snapshot;
+use server::svc;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use tokio::sync::broadcast;
-use tokio::sync::mpsc;
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use tower_http::cors::{AllowOrigin, CorsLayer};
 
-mod engine;
-mod event;
-mod state;
-mod svc;
-mod viewer;
+#[derive(Default)]
+struct Options {
+	// snapshot to carry on the default match from
+	restore: Option<PathBuf>,
+	snapshot_dir: Option<PathBuf>,
+	// seconds between snapshots of every match, none when unset
+	snapshot_every: Option<u64>,
+	// every match is recorded for replay here, when set
+	record_dir: Option<PathBuf>,
+	// what new matches are played on, an open field when unset
+	map: Option<PathBuf>,
+	// the unit types new matches offer, a single default one when unset
+	catalog: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+	let mut options = Options::default();
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		let mut value = || {
+			args.next()
+				.with_context(|| format!("{} needs a value", arg))
+		};
+		match arg.as_str() {
+			"--restore" => options.restore = Some(value()?.into()),
+			"--snapshot-dir" => options.snapshot_dir = Some(value()?.into()),
+			"--record-dir" => options.record_dir = Some(value()?.into()),
+			"--map" => options.map = Some(value()?.into()),
+			"--catalog" => options.catalog = Some(value()?.into()),
+			"--snapshot-every" => {
+				let seconds = value()?
+					.parse()
+					.context("--snapshot-every takes seconds")?;
+				if seconds == 0 {
+					bail!("--snapshot-every needs at least a second");
+				}
+				options.snapshot_every = Some(seconds);
+			}
+			_ => bail!("unknown argument {}", arg),
+		}
+	}
+	Ok(options)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-	let (user_requests_sender, user_requests_receiver) =
-		mpsc::channel::<crate::event::PlayerRequest>(1024);
-	let (tick_completion_sender, _tick_completion_receiver) =
-		broadcast::channel::<crate::event::PublishEvent>(1024);
+	let options = parse_options()?;
 
-	{
-		let tick_completion_sender = tick_completion_sender.clone();
+	let mut matches = Matches::default();
+	if let Some(snapshot_dir) = options.snapshot_dir {
+		matches.set_snapshot_dir(snapshot_dir);
+	}
+	if let Some(record_dir) = options.record_dir {
+		matches.set_record_dir(record_dir);
+	}
+	if let Some(path) = &options.map {
+		let map = Map::load(path).with_context(|| {
+			format!("unable to load map {}", path.display())
+		})?;
+		println!("Playing on {} from {}", map.name, path.display());
+		matches.set_map(map);
+	}
+	if let Some(path) = &options.catalog {
+		let catalog = Catalog::load(path).with_context(|| {
+			format!("unable to load catalog {}", path.display())
+		})?;
+		println!(
+			"Offering {} unit types from {}",
+			catalog.templates.len(),
+			path.display()
+		);
+		matches.set_catalog(catalog);
+	}
+	let restored = match &options.restore {
+		Some(path) => Some(Snapshot::load(path).with_context(|| {
+			format!("unable to restore {}", path.display())
+		})?),
+		None => None,
+	};
+	let highest_id = restored.as_ref().map(Snapshot::highest_id);
+	let default_match = match restored {
+		Some(snapshot) => {
+			println!("Restoring the default match at {}", snapshot.game_time());
+			matches.restore("default".to_string(), snapshot)
+		}
+		None => matches.create("default".to_string()),
+	};
+	matches.keep_open(default_match.id);
+
+	if let Some(seconds) = options.snapshot_every {
+		let matches = matches.clone();
 		tokio::spawn(async move {
-			crate::engine::run_engine(
-				user_requests_receiver,
-				tick_completion_sender,
-			)
-			.await;
+			let mut every = tokio::time::interval(Duration::from_secs(seconds));
+			// the first tick is immediate, there is nothing to save yet
+			every.tick().await;
+			loop {
+				every.tick().await;
+				matches.save_all().await;
+			}
 		});
 	}
 
+	let secrets = matches.secrets();
+	let lobby_key = std::env::var("LOBBY_KEY").ok().filter(|k| !k.is_empty());
+	if lobby_key.is_none() {
+		println!("LOBBY_KEY is not set, the lobby can't start matches");
+	}
 	let service =
-		svc::ShapeSvc::new(user_requests_sender, tick_completion_sender);
+		svc::ShapeSvc::new(matches, default_match.id, secrets.clone());
+	if let Some(highest_id) = highest_id {
+		service.reserve_ids_through(highest_id);
+	}
 
 	// CORS for browsers (grpc-web). You can tighten this later.
 	let cors = CorsLayer::new()
@@ -61,7 +153,10 @@ async fn main() -> Result<()> {
 		.accept_http1(true)
 		.layer(cors)
 		.layer(GrpcWebLayer::new())
-		.add_service(ShapeEventsServer::new(service))
+		.add_service(ShapeEventsServer::with_interceptor(
+			service,
+			AuthInterceptor::new(secrets, lobby_key),
+		))
 		.serve(addr)
 		.await?;
 
@@ -0,0 +1,146 @@
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+use crate::state::inventory::ResourceRegistry;
+use crate::state::navigation::Obstacle;
+use common::grpc;
+use common::model::{self, Coord, Point, Rec};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// How far the map without a file goes from the origin each way
+const DEFAULT_EXTENT: Coord = 100 * model::METERS;
+
+// Something to gather, placed by the map
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResourceNode {
+	pub location: Point,
+	// a name from the resource registry
+	pub resource: String,
+	pub amount: i32,
+}
+
+// The ground a match is played on, read from a JSON file. Coordinates are
+// in millimeters like everywhere else.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+	#[serde(default)]
+	pub name: String,
+	// units can't be sent outside
+	pub bounds: Rec,
+	#[serde(default)]
+	pub obstacles: Vec<Obstacle>,
+	// one per player slot, players get them in the order they join
+	#[serde(default)]
+	pub spawns: Vec<Point>,
+	#[serde(default)]
+	pub resources: Vec<ResourceNode>,
+}
+
+// An open field around the origin, what matches were played on before maps
+impl Default for Map {
+	fn default() -> Self {
+		Self {
+			name: "open field".to_string(),
+			bounds: Rec {
+				min: Point {
+					x: -DEFAULT_EXTENT,
+					y: -DEFAULT_EXTENT,
+				},
+				max: Point {
+					x: DEFAULT_EXTENT,
+					y: DEFAULT_EXTENT,
+				},
+			},
+			obstacles: Vec::new(),
+			spawns: vec![Point { x: 0, y: 0 }],
+			resources: Vec::new(),
+		}
+	}
+}
+
+fn invalid(path: &Path, reason: impl std::fmt::Display) -> EngineError {
+	eprintln!("Invalid map {}: {}", path.display(), reason);
+	engine_error!(EngineErrorKind::InvalidMap)
+}
+
+impl Map {
+	pub fn load(path: &Path) -> Result<Self, EngineError> {
+		let json = std::fs::read(path).map_err(|e| invalid(path, e))?;
+		let map: Self =
+			serde_json::from_slice(&json).map_err(|e| invalid(path, e))?;
+		map.check().map_err(|reason| invalid(path, reason))?;
+		Ok(map)
+	}
+
+	// Everything has to be inside the bounds and make sense on its own
+	fn check(&self) -> Result<(), String> {
+		let bounds = &self.bounds;
+		if bounds.min.x >= bounds.max.x || bounds.min.y >= bounds.max.y {
+			return Err("the bounds are empty".to_string());
+		}
+		for obstacle in &self.obstacles {
+			let size = match obstacle.shape {
+				model::Shape::Circle(r) => r,
+				model::Shape::Rectangle(w, h) => w.min(h),
+			};
+			if size <= 0 || !bounds.contains_point(&obstacle.center) {
+				return Err(format!("bad obstacle {:?}", obstacle));
+			}
+		}
+		for spawn in &self.spawns {
+			if !bounds.contains_point(spawn)
+				|| self.obstacles.iter().any(|o| o.covers(spawn))
+			{
+				return Err(format!("spawn {:?} is out of reach", spawn));
+			}
+		}
+		let registry = ResourceRegistry::default();
+		for node in &self.resources {
+			if registry.find(&node.resource).is_none() {
+				return Err(format!("unknown resource {}", node.resource));
+			}
+			if node.amount <= 0 || !bounds.contains_point(&node.location) {
+				return Err(format!("bad resource node {:?}", node));
+			}
+		}
+		Ok(())
+	}
+
+	// Where the player in `slot` starts, the slots wrap around when there
+	// are more players than spawns
+	pub fn spawn(&self, slot: usize) -> Point {
+		if self.spawns.is_empty() {
+			return Point { x: 0, y: 0 };
+		}
+		self.spawns[slot % self.spawns.len()].clone()
+	}
+}
+
+impl From<&Map> for grpc::Map {
+	fn from(map: &Map) -> Self {
+		grpc::Map {
+			name: map.name.clone(),
+			min: Some(map.bounds.min.clone().into()),
+			max: Some(map.bounds.max.clone().into()),
+			obstacles: map
+				.obstacles
+				.iter()
+				.map(|o| grpc::Obstacle {
+					center: Some(o.center.clone().into()),
+					shape: Some(o.shape.clone().into()),
+				})
+				.collect(),
+			spawns: map.spawns.iter().map(|s| s.clone().into()).collect(),
+			resources: map
+				.resources
+				.iter()
+				.map(|node| grpc::ResourceNode {
+					location: Some(node.location.clone().into()),
+					resource: node.resource.clone(),
+					amount: node.amount,
+				})
+				.collect(),
+		}
+	}
+}
@@ -0,0 +1,318 @@
+use crate::auth::Secrets;
+use crate::catalog::Catalog;
+use crate::engine;
+use crate::event::{PendingRequest, PlayerRequest, PublishEvent};
+use crate::map::Map;
+use crate::replay::Recorder;
+use crate::snapshot::Snapshot;
+use common::model::PlayerId;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+
+pub type MatchId = u64;
+
+const REQUEST_BUFFER: usize = 1024;
+const PUBLISH_BUFFER: usize = 1024;
+const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+// how long a match waits for someone to join or come back before it closes
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// What the service needs to talk to one running match
+#[derive(Clone)]
+pub struct MatchHandle {
+	pub id: MatchId,
+	pub name: String,
+	pub requests_tx: mpsc::Sender<PendingRequest>,
+	pub publish_tx: broadcast::Sender<PublishEvent>,
+	// neither changes once the match is running
+	pub map: Arc<Map>,
+	pub catalog: Arc<Catalog>,
+}
+
+struct MatchEntry {
+	handle: MatchHandle,
+	// never read, it keeps publishing from failing while nobody watches
+	_publish_rx: broadcast::Receiver<PublishEvent>,
+	// the ones watching it right now, by how many streams they have open
+	players: HashMap<PlayerId, usize>,
+	// since the last player left, or since it was created
+	idle_since: Option<Instant>,
+	// stays open even when nobody is in it
+	kept: bool,
+	// the one player who gets to pause the match or change its speed
+	host: Option<PlayerId>,
+}
+
+// Every match hosted by this server, each with its own engine task
+#[derive(Clone)]
+pub struct Matches {
+	next_id: Arc<AtomicU64>,
+	matches: Arc<Mutex<BTreeMap<MatchId, MatchEntry>>>,
+	snapshot_dir: PathBuf,
+	// when the server started, in seconds. Match ids start over with every
+	// run, so this keeps snapshots and replays from overwriting those of an
+	// earlier one.
+	run: u64,
+	// the players' sessions, restored matches bring theirs along
+	secrets: Secrets,
+	// matches are only recorded when set
+	record_dir: Option<PathBuf>,
+	// what new matches are played on, and with
+	map: Arc<Map>,
+	catalog: Arc<Catalog>,
+}
+
+impl Default for Matches {
+	fn default() -> Self {
+		Self {
+			next_id: Arc::new(AtomicU64::new(1)),
+			matches: Arc::default(),
+			snapshot_dir: PathBuf::from(DEFAULT_SNAPSHOT_DIR),
+			run: std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.map_or(0, |since| since.as_secs()),
+			secrets: Secrets::default(),
+			record_dir: None,
+			map: Arc::default(),
+			catalog: Arc::default(),
+		}
+	}
+}
+
+impl Matches {
+	pub fn set_snapshot_dir(&mut self, snapshot_dir: PathBuf) {
+		self.snapshot_dir = snapshot_dir;
+	}
+
+	pub fn set_record_dir(&mut self, record_dir: PathBuf) {
+		self.record_dir = Some(record_dir);
+	}
+
+	pub fn set_map(&mut self, map: Map) {
+		self.map = Arc::new(map);
+	}
+
+	pub fn set_catalog(&mut self, catalog: Catalog) {
+		self.catalog = Arc::new(catalog);
+	}
+
+	pub fn secrets(&self) -> Secrets {
+		self.secrets.clone()
+	}
+
+	pub fn create(&self, name: String) -> MatchHandle {
+		self.start(name, None)
+	}
+
+	// A match that carries on from where the snapshot left it
+	pub fn restore(&self, name: String, snapshot: Snapshot) -> MatchHandle {
+		self.start(name, Some(snapshot))
+	}
+
+	fn start(&self, name: String, snapshot: Option<Snapshot>) -> MatchHandle {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let name = if name.is_empty() {
+			format!("match {}", id)
+		} else {
+			name
+		};
+		let (requests_tx, requests_rx) = mpsc::channel(REQUEST_BUFFER);
+		let (publish_tx, publish_rx) = broadcast::channel(PUBLISH_BUFFER);
+		// a restored match keeps the map and units it was saved with
+		let (map, catalog) = match &snapshot {
+			Some(snapshot) => (
+				Arc::new(snapshot.map().clone()),
+				Arc::new(snapshot.catalog().clone()),
+			),
+			None => (self.map.clone(), self.catalog.clone()),
+		};
+		// its players keep their secrets under the new match id
+		if let Some(snapshot) = &snapshot {
+			self.secrets.restore(id, snapshot.sessions());
+		}
+		// a match that can't be recorded is still worth playing
+		let recorder = self.record_dir.as_ref().and_then(|dir| {
+			Recorder::create(
+				&dir.join(format!("match-{}-{}.replay", self.run, id)),
+				snapshot.as_ref().map(Snapshot::path),
+				&map,
+				&catalog,
+				&publish_tx,
+			)
+			.map_err(|e| {
+				eprintln!("Match {} won't be recorded: {:?}", id, e);
+			})
+			.ok()
+		});
+		{
+			let publish_tx = publish_tx.clone();
+			let map = Map::clone(&map);
+			let catalog = Catalog::clone(&catalog);
+			tokio::spawn(async move {
+				match snapshot {
+					Some(snapshot) => {
+						engine::run_restored_engine(
+							snapshot,
+							requests_rx,
+							publish_tx,
+							recorder,
+						)
+						.await
+					}
+					None => {
+						engine::run_engine(
+							map,
+							catalog,
+							requests_rx,
+							publish_tx,
+							recorder,
+						)
+						.await
+					}
+				}
+			});
+		}
+
+		let handle = MatchHandle {
+			id,
+			name,
+			requests_tx,
+			publish_tx,
+			map,
+			catalog,
+		};
+		println!("Created match {} ({})", id, handle.name);
+		self.lock().insert(
+			id,
+			MatchEntry {
+				handle: handle.clone(),
+				_publish_rx: publish_rx,
+				players: HashMap::new(),
+				idle_since: Some(Instant::now()),
+				kept: false,
+				host: None,
+			},
+		);
+		self.close_when_idle(id);
+		handle
+	}
+
+	// For the default match, which players land in without asking
+	pub fn keep_open(&self, id: MatchId) {
+		if let Some(entry) = self.lock().get_mut(&id) {
+			entry.kept = true;
+		}
+	}
+
+	pub fn get(&self, id: MatchId) -> Option<MatchHandle> {
+		self.lock().get(&id).map(|entry| entry.handle.clone())
+	}
+
+	// Every match along with how many players are in it
+	pub fn list(&self) -> Vec<(MatchHandle, usize)> {
+		self.lock()
+			.values()
+			.map(|entry| (entry.handle.clone(), entry.players.len()))
+			.collect()
+	}
+
+	pub fn add_player(&self, id: MatchId, player_id: PlayerId) {
+		if let Some(entry) = self.lock().get_mut(&id) {
+			*entry.players.entry(player_id).or_default() += 1;
+			entry.idle_since = None;
+			// without a lobby to say otherwise, the first one in hosts
+			entry.host.get_or_insert(player_id);
+		}
+	}
+
+	pub fn set_host(&self, id: MatchId, player_id: PlayerId) {
+		if let Some(entry) = self.lock().get_mut(&id) {
+			entry.host = Some(player_id);
+		}
+	}
+
+	pub fn is_host(&self, id: MatchId, player_id: PlayerId) -> bool {
+		self.lock()
+			.get(&id)
+			.is_some_and(|entry| entry.host == Some(player_id))
+	}
+
+	// The engine hears about a player leaving once their last stream is
+	// gone, and once the last player is gone the match closes, unless
+	// someone comes back in time
+	pub fn remove_player(&self, id: MatchId, player_id: PlayerId) {
+		let empty = self.lock().get_mut(&id).is_some_and(|entry| {
+			if let Some(streams) = entry.players.get_mut(&player_id) {
+				*streams -= 1;
+				if *streams == 0 {
+					entry.players.remove(&player_id);
+					// still under the lock, so it can't overtake a
+					// subscribe that registered the player again
+					let left = PendingRequest {
+						request: PlayerRequest::PlayerLeft(player_id),
+						reply: None,
+					};
+					if let Err(e) = entry.handle.requests_tx.try_send(left) {
+						eprintln!("Player {} left unnoticed: {}", player_id, e);
+					}
+				}
+			}
+			if entry.players.is_empty() && entry.idle_since.is_none() {
+				entry.idle_since = Some(Instant::now());
+			}
+			entry.players.is_empty()
+		});
+		if empty {
+			self.close_when_idle(id);
+		}
+	}
+
+	// Dropping the entry drops the last request sender, which stops the
+	// engine
+	fn close_when_idle(&self, id: MatchId) {
+		let matches = self.clone();
+		tokio::spawn(async move {
+			tokio::time::sleep(IDLE_TIMEOUT).await;
+			let mut entries = matches.lock();
+			if entries.get(&id).is_some_and(|entry| {
+				!entry.kept
+					&& entry
+						.idle_since
+						.is_some_and(|since| since.elapsed() >= IDLE_TIMEOUT)
+			}) {
+				entries.remove(&id);
+				println!("Closed match {}, nobody is in it", id);
+			}
+		});
+	}
+
+	pub fn snapshot_path(&self, id: MatchId) -> PathBuf {
+		self.snapshot_dir
+			.join(format!("match-{}-{}.json", self.run, id))
+	}
+
+	// Asks every match to write its snapshot, without waiting for them
+	pub async fn save_all(&self) {
+		for (game, _) in self.list() {
+			let request = PendingRequest {
+				request: PlayerRequest::SaveSnapshot(
+					self.snapshot_path(game.id),
+					self.secrets.saved(game.id),
+				),
+				reply: None,
+			};
+			if game.requests_tx.send(request).await.is_err() {
+				eprintln!("Match {} is not taking requests", game.id);
+			}
+		}
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<MatchId, MatchEntry>> {
+		self.matches.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
@@ -0,0 +1,430 @@
+use crate::catalog::Catalog;
+use crate::clock::GameClock;
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+use crate::event::{PlayerRequest, PublishEvent};
+use crate::headless::HeadlessEngine;
+use crate::map::Map;
+use crate::snapshot::Snapshot;
+use crate::state::game::GameState;
+use common::model::TimeStamp;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+
+// Bump whenever the meaning of a recorded step changes
+pub const REPLAY_VERSION: u32 = 3;
+
+// One line of a replay file. Ticks that published nothing are left out,
+// except for the last one before a request, which the request is simulated
+// from.
+#[derive(Serialize, Deserialize)]
+pub enum ReplayEntry {
+	Begin {
+		version: u32,
+		// the snapshot the match was restored from, if it didn't start
+		// empty, relative to the replay file
+		snapshot: Option<PathBuf>,
+		// what an empty match started on, older replays were all on the
+		// default map
+		#[serde(default)]
+		map: Option<Map>,
+		// the unit types it was played with, set along with the map
+		catalog: Option<Catalog>,
+	},
+	Tick {
+		game_time: TimeStamp,
+		published: Vec<PublishEvent>,
+	},
+	Request {
+		game_time: TimeStamp,
+		request: PlayerRequest,
+		published: Vec<PublishEvent>,
+	},
+}
+
+// TickCompleted carries the wall clock, so it can never match and is left
+// out
+fn recorded(event: &PublishEvent) -> bool {
+	!matches!(event, PublishEvent::TickCompleted(_))
+}
+
+// The same events in any order, the game state hands them out in hash map
+// order
+fn same_events(expected: &[PublishEvent], actual: &[PublishEvent]) -> bool {
+	let mut unmatched = actual.iter().collect::<Vec<_>>();
+	expected.len() == actual.len()
+		&& expected.iter().all(|event| {
+			unmatched
+				.iter()
+				.position(|other| *other == event)
+				.map(|index| unmatched.swap_remove(index))
+				.is_some()
+		})
+}
+
+fn write_error(e: impl std::fmt::Display) -> EngineError {
+	eprintln!("Unable to write replay: {}", e);
+	engine_error!(EngineErrorKind::InternalError)
+}
+
+// Writes down every step the engine takes along with what it published
+pub struct Recorder {
+	out: BufWriter<File>,
+	published: broadcast::Receiver<PublishEvent>,
+	// the game time of the last tick, if it published nothing and isn't
+	// written yet
+	quiet_tick: Option<TimeStamp>,
+}
+
+impl Recorder {
+	// `snapshot` is copied beside the replay, the original may well be
+	// overwritten by the next save of the match. Without one the match
+	// starts empty on `map` with the units of `catalog`.
+	pub fn create(
+		path: &Path,
+		snapshot: Option<&Path>,
+		map: &Map,
+		catalog: &Catalog,
+		publisher: &broadcast::Sender<PublishEvent>,
+	) -> Result<Self, EngineError> {
+		path.parent()
+			.map_or(Ok(()), std::fs::create_dir_all)
+			.map_err(write_error)?;
+		let (snapshot, map, catalog) = match snapshot {
+			Some(snapshot) => {
+				let copy = path.with_extension("start.json");
+				std::fs::copy(snapshot, &copy).map_err(write_error)?;
+				(copy.file_name().map(PathBuf::from), None, None)
+			}
+			None => (None, Some(map.clone()), Some(catalog.clone())),
+		};
+		let mut recorder = Self {
+			out: BufWriter::new(File::create(path).map_err(write_error)?),
+			published: publisher.subscribe(),
+			quiet_tick: None,
+		};
+		recorder.write(&ReplayEntry::Begin {
+			version: REPLAY_VERSION,
+			snapshot,
+			map,
+			catalog,
+		})?;
+		println!("Recording to {}", path.display());
+		Ok(recorder)
+	}
+
+	pub fn tick(&mut self, game_time: TimeStamp) -> Result<(), EngineError> {
+		let published = self.drain();
+		if published.is_empty() {
+			self.quiet_tick = Some(game_time);
+			return Ok(());
+		}
+		self.quiet_tick = None;
+		self.write(&ReplayEntry::Tick {
+			game_time,
+			published,
+		})
+	}
+
+	pub fn request(
+		&mut self,
+		game_time: TimeStamp,
+		request: PlayerRequest,
+	) -> Result<(), EngineError> {
+		// snapshots don't change the match, and replaying them would write
+		// files
+		if matches!(request, PlayerRequest::SaveSnapshot(..)) {
+			self.drain();
+			return Ok(());
+		}
+		let published = self.drain();
+		if let Some(game_time) = self.quiet_tick.take() {
+			self.write(&ReplayEntry::Tick {
+				game_time,
+				published: Vec::new(),
+			})?;
+		}
+		self.write(&ReplayEntry::Request {
+			game_time,
+			request,
+			published,
+		})
+	}
+
+	fn drain(&mut self) -> Vec<PublishEvent> {
+		let mut published = Vec::new();
+		loop {
+			match self.published.try_recv() {
+				Ok(event) if recorded(&event) => published.push(event),
+				Ok(_) => {}
+				Err(broadcast::error::TryRecvError::Lagged(missed)) => {
+					eprintln!("Replay missed {} published events", missed);
+				}
+				Err(_) => return published,
+			}
+		}
+	}
+
+	// Flushed every step so a crash still leaves a usable replay
+	fn write(&mut self, entry: &ReplayEntry) -> Result<(), EngineError> {
+		serde_json::to_writer(&mut self.out, entry).map_err(write_error)?;
+		self.out
+			.write_all(b"\n")
+			.and_then(|_| self.out.flush())
+			.map_err(write_error)
+	}
+}
+
+// The first step where the replay published something else than the match
+// did
+#[derive(Debug)]
+pub struct Divergence {
+	// counted from the first step after Begin
+	pub step: usize,
+	pub game_time: TimeStamp,
+	pub expected: Vec<PublishEvent>,
+	pub actual: Vec<PublishEvent>,
+}
+
+#[derive(Debug)]
+pub struct ReplayReport {
+	pub steps: usize,
+	pub divergence: Option<Divergence>,
+}
+
+fn read_error(path: &Path, e: impl std::fmt::Display) -> EngineError {
+	eprintln!("Unable to read replay {}: {}", path.display(), e);
+	engine_error!(EngineErrorKind::MalformedRequest)
+}
+
+// Feeds a replay back through a fresh GameState, stopping at the first step
+// that publishes something different
+pub fn replay(path: &Path) -> Result<ReplayReport, EngineError> {
+	let file = File::open(path).map_err(|e| read_error(path, e))?;
+	let mut lines = BufReader::new(file).lines();
+
+	let first = lines
+		.next()
+		.ok_or_else(|| read_error(path, "the file is empty"))?
+		.map_err(|e| read_error(path, e))?;
+	let game_state = match serde_json::from_str(&first)
+		.map_err(|e| read_error(path, e))?
+	{
+		ReplayEntry::Begin { version, .. } if version != REPLAY_VERSION => {
+			return Err(read_error(
+				path,
+				format!("version {}, expected {}", version, REPLAY_VERSION),
+			));
+		}
+		ReplayEntry::Begin {
+			snapshot: Some(snapshot),
+			..
+		} => {
+			let dir = path.parent().unwrap_or(Path::new(""));
+			Snapshot::load(&dir.join(snapshot))?.restore(0).0
+		}
+		ReplayEntry::Begin {
+			snapshot: None,
+			map,
+			catalog,
+			..
+		} => {
+			GameState::new(map.unwrap_or_default(), catalog.unwrap_or_default())
+		}
+		_ => return Err(read_error(path, "missing the Begin line")),
+	};
+
+	// replays step through the recorded game times themselves
+	let mut engine =
+		HeadlessEngine::with_state(game_state, GameClock::new(0), 0);
+	let mut steps = 0;
+	for line in lines {
+		let line = line.map_err(|e| read_error(path, e))?;
+		let entry: ReplayEntry =
+			serde_json::from_str(&line).map_err(|e| read_error(path, e))?;
+		let (game_time, expected, replayed) = match entry {
+			ReplayEntry::Begin { .. } => {
+				return Err(read_error(path, "a second Begin line"));
+			}
+			ReplayEntry::Tick {
+				game_time,
+				published,
+			} => (game_time, published, engine.tick_at(game_time)?),
+			ReplayEntry::Request {
+				game_time,
+				request,
+				published,
+			} => {
+				// refused requests were refused in the match too
+				let replayed = engine.apply_at(game_time, request)?;
+				(game_time, published, replayed)
+			}
+		};
+		steps += 1;
+
+		let actual: Vec<PublishEvent> =
+			replayed.into_iter().filter(recorded).collect();
+		if !same_events(&expected, &actual) {
+			return Ok(ReplayReport {
+				steps,
+				divergence: Some(Divergence {
+					step: steps,
+					game_time,
+					expected,
+					actual,
+				}),
+			});
+		}
+	}
+	Ok(ReplayReport {
+		steps,
+		divergence: None,
+	})
+}
+
+// SetQueueRequest is a protobuf message without serde, so it goes into the
+// replay as its wire bytes in hex
+pub mod protobuf {
+	use prost::Message;
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<M: Message, S: Serializer>(
+		message: &M,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&to_hex(message))
+	}
+
+	pub fn deserialize<'de, M: Message + Default, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<M, D::Error> {
+		from_hex(&String::deserialize(deserializer)?)
+			.ok_or_else(|| serde::de::Error::custom("invalid hex"))?
+			.map_err(serde::de::Error::custom)
+	}
+
+	pub(super) fn to_hex<M: Message>(message: &M) -> String {
+		message
+			.encode_to_vec()
+			.iter()
+			.map(|b| format!("{:02x}", b))
+			.collect()
+	}
+
+	pub(super) fn from_hex<M: Message + Default>(
+		hex: &str,
+	) -> Option<Result<M, prost::DecodeError>> {
+		let bytes = (0..hex.len())
+			.step_by(2)
+			.map(|i| {
+				hex.get(i..i + 2)
+					.and_then(|b| u8::from_str_radix(b, 16).ok())
+			})
+			.collect::<Option<Vec<u8>>>()?;
+		Some(M::decode(bytes.as_slice()))
+	}
+}
+
+// The same for a list of them, like the animation segments of published
+// events
+pub mod protobufs {
+	use super::protobuf::{from_hex, to_hex};
+	use prost::Message;
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<M: Message, S: Serializer>(
+		messages: &[M],
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(messages.iter().map(to_hex))
+	}
+
+	pub fn deserialize<'de, M: Message + Default, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Vec<M>, D::Error> {
+		Vec::<String>::deserialize(deserializer)?
+			.iter()
+			.map(|hex| {
+				from_hex(hex)
+					.ok_or_else(|| serde::de::Error::custom("invalid hex"))?
+					.map_err(serde::de::Error::custom)
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::engine::run_engine;
+	use crate::event::PendingRequest;
+	use common::grpc::{MoveTo, SetQueueRequest, Task, task};
+	use common::model::METERS;
+	use std::time::Duration;
+	use tokio::sync::mpsc;
+
+	// A short match on the real engine, recorded as it goes and then fed
+	// back through a fresh one
+	#[tokio::test]
+	async fn recorded_match_replays_the_same() {
+		let path = std::env::temp_dir()
+			.join(format!("replay-{}.replay", std::process::id()));
+		let (map, catalog) = (Map::default(), Catalog::default());
+		let (requests_tx, requests_rx) = mpsc::channel(16);
+		let (publish_tx, _) = broadcast::channel(1024);
+		let recorder =
+			Recorder::create(&path, None, &map, &catalog, &publish_tx).unwrap();
+		let engine = tokio::spawn(run_engine(
+			map,
+			catalog,
+			requests_rx,
+			publish_tx,
+			Some(recorder),
+		));
+
+		let move_to = |unit_id, x| {
+			PlayerRequest::UpdateIntentions(
+				1,
+				SetQueueRequest {
+					unit_id,
+					tasks: vec![Task {
+						kind: Some(task::Kind::Move(MoveTo {
+							destination: Some(common::grpc::Point { x, y: 0 }),
+						})),
+					}],
+				},
+			)
+		};
+		for request in [
+			PlayerRequest::PlayerJoined(1),
+			PlayerRequest::CreateUnit(1, 10, 1),
+			move_to(10, METERS / 2),
+		] {
+			let pending = PendingRequest {
+				request,
+				reply: None,
+			};
+			requests_tx.send(pending).await.unwrap();
+		}
+		tokio::time::sleep(Duration::from_millis(1500)).await;
+		// the engine stops once nobody can send it anything
+		drop(requests_tx);
+		engine.await.unwrap();
+
+		let lines = std::fs::read_to_string(&path).unwrap();
+		let report = replay(&path);
+		std::fs::remove_file(&path).unwrap();
+		let report = report.unwrap();
+		assert!(report.divergence.is_none(), "{:?}", report.divergence);
+		// the requests, the ticks before them and those that showed the
+		// move, but none of the idle ones in between
+		assert!(report.steps >= 4);
+		assert!(
+			lines.lines().filter(|l| l.starts_with("{\"Tick\"")).count() < 10
+		);
+	}
+}
@@ -0,0 +1,157 @@
+use crate::auth::SavedSession;
+use crate::catalog::Catalog;
+use crate::clock::GameClock;
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+use crate::map::Map;
+use crate::state::game::GameState;
+use common::model::TimeStamp;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Bump whenever anything serialized inside GameState changes shape. Older
+// snapshots are refused instead of being half understood.
+pub const SNAPSHOT_VERSION: u32 = 9;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+	version: u32,
+	game_time: TimeStamp,
+	speed: f64,
+	paused: bool,
+	state: &'a GameState,
+	sessions: &'a [SavedSession],
+}
+
+// Read first so a snapshot from another version is turned away before the
+// rest of it fails to parse
+#[derive(Deserialize)]
+struct SnapshotHeader {
+	version: u32,
+}
+
+// A whole match as it was at `game_time`. The version was checked by the
+// time one of these exists.
+#[derive(Deserialize)]
+pub struct Snapshot {
+	game_time: TimeStamp,
+	speed: f64,
+	paused: bool,
+	state: GameState,
+	// so the players can resume after the restore
+	sessions: Vec<SavedSession>,
+	// where it was loaded from, recordings of the match start from it
+	#[serde(skip)]
+	path: PathBuf,
+}
+
+pub fn save(
+	path: &Path,
+	game_state: &GameState,
+	sessions: &[SavedSession],
+	clock: &GameClock,
+	wall_ms: TimeStamp,
+) -> Result<(), EngineError> {
+	let snapshot = SnapshotRef {
+		version: SNAPSHOT_VERSION,
+		game_time: clock.game_time(wall_ms),
+		speed: clock.speed(),
+		paused: clock.is_paused(),
+		state: game_state,
+		sessions,
+	};
+	let json = serde_json::to_vec(&snapshot).map_err(|e| {
+		eprintln!("Unable to serialize snapshot: {}", e);
+		engine_error!(EngineErrorKind::SnapshotFailed)
+	})?;
+	// Written beside the target and renamed over it, so a crash halfway
+	// through never leaves a broken snapshot behind
+	let tmp = path.with_extension("tmp");
+	path.parent()
+		.map_or(Ok(()), std::fs::create_dir_all)
+		.and_then(|_| std::fs::write(&tmp, json))
+		.and_then(|_| std::fs::rename(&tmp, path))
+		.map_err(|e| {
+			eprintln!("Unable to write snapshot {}: {}", path.display(), e);
+			engine_error!(EngineErrorKind::SnapshotFailed)
+		})?;
+	println!(
+		"Saved snapshot {} at {}",
+		path.display(),
+		snapshot.game_time
+	);
+	Ok(())
+}
+
+impl Snapshot {
+	pub fn load(path: &Path) -> Result<Self, EngineError> {
+		let json = std::fs::read(path).map_err(|e| {
+			eprintln!("Unable to read snapshot {}: {}", path.display(), e);
+			engine_error!(EngineErrorKind::SnapshotFailed)
+		})?;
+		let header: SnapshotHeader =
+			serde_json::from_slice(&json).map_err(|e| {
+				eprintln!("Not a snapshot {}: {}", path.display(), e);
+				engine_error!(EngineErrorKind::SnapshotFailed)
+			})?;
+		if header.version != SNAPSHOT_VERSION {
+			eprintln!(
+				"Snapshot {} is version {}, expected {}",
+				path.display(),
+				header.version,
+				SNAPSHOT_VERSION
+			);
+			return Err(engine_error!(EngineErrorKind::SnapshotFailed));
+		}
+		let mut snapshot: Self =
+			serde_json::from_slice(&json).map_err(|e| {
+				eprintln!("Unable to parse snapshot {}: {}", path.display(), e);
+				engine_error!(EngineErrorKind::SnapshotFailed)
+			})?;
+		snapshot.path = path.to_owned();
+		Ok(snapshot)
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn game_time(&self) -> TimeStamp {
+		self.game_time
+	}
+
+	pub fn map(&self) -> &Map {
+		self.state.map()
+	}
+
+	pub fn catalog(&self) -> &Catalog {
+		self.state.catalog()
+	}
+
+	pub fn sessions(&self) -> &[SavedSession] {
+		&self.sessions
+	}
+
+	// Ids handed out after a restore have to start above this, players who
+	// left can still come back with theirs
+	pub fn highest_id(&self) -> u64 {
+		self.sessions
+			.iter()
+			.map(|session| session.player_id)
+			.fold(self.state.highest_id(), u64::max)
+	}
+
+	// Game time carries on from where the snapshot was taken, whatever the
+	// wall clock says now. Anything that finished between the last tick and
+	// the snapshot is picked up by the first tick after the restore.
+	pub fn restore(self, wall_ms: TimeStamp) -> (GameState, GameClock) {
+		let clock = GameClock::restored(
+			wall_ms,
+			self.game_time,
+			self.speed,
+			self.paused,
+		);
+		(self.state, clock)
+	}
+}
@@ -1,21 +1,33 @@
+use crate::catalog::Catalog;
 use crate::event;
+use crate::map::Map;
+use crate::state::inventory::{Inventory, ResourceRegistry};
+use crate::state::navigation::{Navigation, Obstacle};
 use crate::state::perspective::PerspectiveUpdates;
 use crate::state::perspective::PlayersGamePerspective;
+use crate::state::tasks::Collision;
 use crate::state::tasks::CompletedTask;
+use crate::state::tasks::Sighting;
 use crate::state::tasks::SimulatedTask;
 use crate::state::tasks::SimulationEvent;
 use crate::state::tasks::TaskCompletion;
 use crate::state::tasks::TaskManager;
 use crate::state::tasks::TaskTransition;
-use crate::state::templates::UnitTemplate;
+use crate::state::templates::{CollisionPolicy, UnitTemplate};
 use crate::state::types::SimulatedId;
 use common::model::OrientedPoint;
-use common::model::{Health, PlayerId, Speed, TaskId, TimeStamp, UnitId};
+use common::model::ShapeVolume;
+use common::model::{
+	Coord, Health, PlayerId, ResourceId, Speed, TaskId, TemplateId, TimeStamp,
+	UnitId,
+};
+use std::collections::BTreeMap;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use tokio::sync::broadcast;
 
 use common::model;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 use crate::engine::EngineError;
@@ -24,13 +36,17 @@ use crate::engine_error;
 
 // put this in a state mod and limit scope?
 
-#[derive(Clone, Debug)]
+// How close two units have to be, center to center, to hand resources over
+pub const TRANSFER_RANGE: Coord = 2 * model::METERS;
+pub const TRANSFER_DURATION: TimeStamp = model::SECONDS;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum UnitLocation {
 	ByMoveTask(TaskId),
 	Fixed(model::OrientedPoint),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskProgress {
 	pub finish_time: TimeStamp,
 	pub completion: SimulationEvent,
@@ -42,7 +58,7 @@ pub struct UnitCache {
 	// shape, speed, location...
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct GameState {
 	next_id: u64,
 	begin_time: TimeStamp,
@@ -53,16 +69,30 @@ pub struct GameState {
 
 	health: HashMap<UnitId, Health>,
 	speeds: HashMap<UnitId, Speed>,
+	accelerations: HashMap<UnitId, Speed>,
+	turn_rates: HashMap<UnitId, model::Orientation>,
+	shapes: HashMap<UnitId, model::Shape>,
+	collision_policies: HashMap<UnitId, CollisionPolicy>,
 	owners: HashMap<UnitId, PlayerId>,
 	locations: HashMap<UnitId, UnitLocation>,
-	unit_display_types: HashMap<UnitId, model::UnitDisplayType>,
+	unit_templates: HashMap<UnitId, TemplateId>,
+	resources: ResourceRegistry,
+	inventories: HashMap<UnitId, Inventory>,
+	vision: HashMap<UnitId, Coord>,
 
 	tasks: TaskManager,
+	map: Map,
+	catalog: Catalog,
+	navigation: Navigation,
+	// players start at the map's spawn for their slot, in joining order
+	spawn_slots: HashMap<PlayerId, usize>,
 
 	pub in_progress: BinaryHeap<TaskProgress>,
+	// the units of others each player can see right now, and when they
+	// next go out of sight, TimeStamp::MAX if they don't
+	sightings: HashMap<PlayerId, HashMap<UnitId, TimeStamp>>,
 
-	// inventory change listeners
-	// area change listeners
+	// area change listeners, one for every player who joined
 	perspectives: HashMap<PlayerId, PlayersGamePerspective>,
 
 	// put sequence numbers here?
@@ -70,6 +100,25 @@ pub struct GameState {
 }
 
 impl GameState {
+	pub fn new(map: Map, catalog: Catalog) -> Self {
+		let mut game_state = Self::default();
+		game_state.navigation.set_bounds(map.bounds.clone());
+		for obstacle in &map.obstacles {
+			game_state.navigation.add_obstacle(obstacle.clone());
+		}
+		game_state.map = map;
+		game_state.catalog = catalog;
+		game_state
+	}
+
+	pub fn map(&self) -> &Map {
+		&self.map
+	}
+
+	pub fn catalog(&self) -> &Catalog {
+		&self.catalog
+	}
+
 	pub fn get_next_completion(&self) -> Option<TimeStamp> {
 		self.in_progress.peek().map(|tp| tp.finish_time)
 	}
@@ -77,6 +126,16 @@ impl GameState {
 	pub fn advance_to_time(&mut self, game_time: TimeStamp) {
 		self.last_time = game_time;
 	}
+	// The largest unit or player id in use
+	pub fn highest_id(&self) -> u64 {
+		self.units
+			.iter()
+			.chain(self.owners.values())
+			.chain(self.spawn_slots.keys())
+			.copied()
+			.max()
+			.unwrap_or(0)
+	}
 	pub fn get_next_id(&mut self) -> u64 {
 		let id = self.next_id;
 		self.next_id += 1;
@@ -104,11 +163,8 @@ impl GameState {
 
 		let current_location = Some(self.get_unit_location(unit_id, time)?);
 
-		let transition = self.tasks.set_task_queue_requested(
-			unit_id,
-			tasks,
-			self.last_time,
-		)?;
+		let transition =
+			self.tasks.set_task_queue_requested(unit_id, tasks, time)?;
 
 		self.handle_task_transition(unit_id, &transition, current_location)?;
 
@@ -143,6 +199,7 @@ impl GameState {
 			SimulationEvent::TaskCompleted(ref completed_task) => {
 				completed_task.unit_id != unit_id
 			}
+			_ => true,
 		});
 	}
 	fn clear_upcoming_by_simulation_id(&mut self, simulation_id: SimulatedId) {
@@ -150,16 +207,373 @@ impl GameState {
 			SimulationEvent::TaskCompleted(ref completed_task) => {
 				completed_task.simulation_id != simulation_id
 			}
+			_ => true,
+		});
+	}
+	fn clear_collisions_by_unit(&mut self, unit_id: UnitId) {
+		self.in_progress.retain(|tp| match tp.completion {
+			SimulationEvent::Collision(ref collision) => {
+				!collision.involves(unit_id)
+			}
+			_ => true,
+		});
+	}
+
+	// Whether the unit is due to run into something at exactly `time`
+	pub fn collides_at(&self, unit_id: UnitId, time: TimeStamp) -> bool {
+		self.in_progress.iter().any(|tp| {
+			tp.finish_time == time
+				&& matches!(
+					&tp.completion,
+					SimulationEvent::Collision(collision)
+						if collision.involves(unit_id)
+				)
+		})
+	}
+
+	// The space a unit will sweep through from `from` on, one volume per
+	// queued segment, ending with it standing still forever
+	fn unit_volumes(
+		&self,
+		unit_id: UnitId,
+		from: TimeStamp,
+	) -> Result<Vec<ShapeVolume>, EngineError> {
+		match self.shapes.get(&unit_id) {
+			Some(shape) => self.sweep(unit_id, shape, from),
+			None => Ok(vec![]),
+		}
+	}
+
+	// The same as unit_volumes, with `shape` instead of the unit's own
+	fn sweep(
+		&self,
+		unit_id: UnitId,
+		shape: &model::Shape,
+		from: TimeStamp,
+	) -> Result<Vec<ShapeVolume>, EngineError> {
+		let mut volumes = Vec::new();
+		let resting = match self.locations.get(&unit_id) {
+			Some(UnitLocation::Fixed(position)) => {
+				(from, position.point.clone())
+			}
+			Some(UnitLocation::ByMoveTask(_)) => {
+				let unit_tasks = self
+					.tasks
+					.unit_tasks
+					.get(&unit_id)
+					.ok_or(engine_error!(EngineErrorKind::InternalError))?;
+				// a unit moved by a task always has that task queued
+				let mut resting = None;
+				for simulation_id in &unit_tasks.tasks {
+					let simulated =
+						self.tasks.simulated_tasks.get(simulation_id).ok_or(
+							engine_error!(EngineErrorKind::InternalError),
+						)?;
+					for volume in simulated.volumes(shape) {
+						resting = Some(volume.end.clone());
+						if volume.end.0 >= from {
+							volumes.push(volume);
+						}
+					}
+				}
+				resting.ok_or(engine_error!(EngineErrorKind::InternalError))?
+			}
+			None => return Err(engine_error!(EngineErrorKind::InvalidUnitId)),
+		};
+		volumes.push(ShapeVolume::stationary(
+			shape.clone(),
+			resting.1,
+			resting.0.max(from),
+			TimeStamp::MAX,
+		));
+		Ok(volumes)
+	}
+
+	// Replaces every upcoming collision of this unit with the first contact
+	// it will make with each other unit, given everyone's current queues
+	fn schedule_collisions(
+		&mut self,
+		unit_id: UnitId,
+		from: TimeStamp,
+	) -> Result<(), EngineError> {
+		self.clear_collisions_by_unit(unit_id);
+
+		let volumes = self.unit_volumes(unit_id, from)?;
+		if volumes.is_empty() {
+			return Ok(());
+		}
+		let mut others = self
+			.units
+			.iter()
+			.filter(|other_id| **other_id != unit_id)
+			.cloned()
+			.collect::<Vec<_>>();
+		others.sort();
+
+		for other_id in others {
+			let other_volumes = self.unit_volumes(other_id, from)?;
+			let first_contact = volumes
+				.iter()
+				.flat_map(|volume| {
+					other_volumes
+						.iter()
+						.filter_map(|other| volume.first_contact(other, from))
+				})
+				.min();
+			if let Some(finish_time) = first_contact {
+				self.in_progress.push(TaskProgress {
+					finish_time,
+					completion: SimulationEvent::Collision(Collision::between(
+						unit_id, other_id,
+					)),
+				});
+			}
+		}
+		Ok(())
+	}
+
+	// Works out again, from `from` on, when players see the unit and what
+	// its owner sees with it. Called whenever its queue changes, the same
+	// as schedule_collisions.
+	fn schedule_sightings(
+		&mut self,
+		unit_id: UnitId,
+		from: TimeStamp,
+	) -> Result<(), EngineError> {
+		let owner = self.owners.get(&unit_id).copied();
+		let eye = self.vision.contains_key(&unit_id);
+		// only for the players watching, the rest catch up when they're back
+		let mut pairs = Vec::new();
+		for player_id in self.perspectives.keys() {
+			if Some(*player_id) != owner {
+				pairs.push((*player_id, unit_id));
+			}
+		}
+		if let Some(owner) =
+			owner.filter(|owner| eye && self.perspectives.contains_key(owner))
+		{
+			for other_id in &self.units {
+				if self.owners.get(other_id) != Some(&owner) {
+					pairs.push((owner, *other_id));
+				}
+			}
+		}
+		// in a fixed order, the events go into a heap
+		pairs.sort();
+		pairs.dedup();
+		self.in_progress.retain(|tp| match &tp.completion {
+			SimulationEvent::Sighting(sighting) => pairs
+				.binary_search(&(sighting.player_id, sighting.unit_id))
+				.is_err(),
+			_ => true,
+		});
+		for (player_id, other_id) in pairs {
+			self.update_sighting(player_id, other_id, from)?;
+		}
+		Ok(())
+	}
+
+	// Everything the player sees from `from` on, for when they come in
+	fn schedule_player_sightings(
+		&mut self,
+		player_id: PlayerId,
+		from: TimeStamp,
+	) -> Result<(), EngineError> {
+		self.clear_sightings_by_player(player_id);
+		let mut others = self
+			.units
+			.iter()
+			.filter(|unit_id| self.owners.get(unit_id) != Some(&player_id))
+			.copied()
+			.collect::<Vec<_>>();
+		others.sort();
+		for unit_id in others {
+			self.update_sighting(player_id, unit_id, from)?;
+		}
+		Ok(())
+	}
+
+	fn clear_sightings_by_player(&mut self, player_id: PlayerId) {
+		self.in_progress.retain(|tp| match &tp.completion {
+			SimulationEvent::Sighting(sighting) => {
+				sighting.player_id != player_id
+			}
+			_ => true,
 		});
 	}
 
+	// Something went in or out of sight at `time`
+	pub fn sighting_due(
+		&mut self,
+		time: TimeStamp,
+		sighting: &Sighting,
+	) -> Result<(), EngineError> {
+		self.update_sighting(sighting.player_id, sighting.unit_id, time)
+	}
+
+	// Whether the player sees `unit_id` at `from`, and the event for when
+	// that changes next
+	fn update_sighting(
+		&mut self,
+		player_id: PlayerId,
+		unit_id: UnitId,
+		from: TimeStamp,
+	) -> Result<(), EngineError> {
+		let (hidden_at, next_change) = self.sight(player_id, unit_id, from)?;
+		let seen = self.sightings.entry(player_id).or_default();
+		match hidden_at {
+			Some(hidden_at) => {
+				let before = seen.insert(unit_id, hidden_at);
+				// what they were sent of its queue ends somewhere else now
+				if before.is_some_and(|before| before != hidden_at)
+					&& let Some(perspective) =
+						self.perspectives.get_mut(&player_id)
+				{
+					perspective.resend(unit_id);
+				}
+			}
+			None => {
+				seen.remove(&unit_id);
+			}
+		}
+		if let Some(time) = next_change {
+			self.in_progress.push(TaskProgress {
+				finish_time: time,
+				completion: SimulationEvent::Sighting(Sighting {
+					player_id,
+					unit_id,
+				}),
+			});
+		}
+		Ok(())
+	}
+
+	// When the player's units have `unit_id` within their vision, from
+	// `from` on. Returns when it goes out of sight if it is in sight at
+	// `from`, and when that next changes.
+	fn sight(
+		&self,
+		player_id: PlayerId,
+		unit_id: UnitId,
+		from: TimeStamp,
+	) -> Result<(Option<TimeStamp>, Option<TimeStamp>), EngineError> {
+		// only the centers count
+		let point = model::Shape::Circle(0);
+		let target = self.sweep(unit_id, &point, from)?;
+		let mut spans = Vec::new();
+		for (eye_id, radius) in &self.vision {
+			if self.owners.get(eye_id) != Some(&player_id) {
+				continue;
+			}
+			for eye in self.sweep(*eye_id, &point, from)? {
+				for volume in &target {
+					spans.extend(eye.within(volume, *radius, from));
+				}
+			}
+		}
+		spans.sort();
+
+		// none begin before `from`, so it is in sight when one begins right
+		// then, for as long as the spans run into each other
+		let mut seen_until = None;
+		for (begin, end) in spans {
+			match seen_until {
+				None if begin <= from => seen_until = Some(end),
+				Some(until) if begin <= until.saturating_add(1) => {
+					seen_until = Some(until.max(end));
+				}
+				Some(_) => break,
+				None => return Ok((None, Some(begin))),
+			}
+		}
+		Ok(match seen_until {
+			Some(TimeStamp::MAX) => (Some(TimeStamp::MAX), None),
+			Some(until) => (Some(until + 1), Some(until + 1)),
+			None => (None, None),
+		})
+	}
+
+	// Stops a moving unit where it is at `time`, dropping its queue. Returns
+	// the tasks that were queued, starting with the one it was busy with,
+	// or None if it wasn't moving.
+	pub fn stop_unit(
+		&mut self,
+		unit_id: UnitId,
+		time: TimeStamp,
+	) -> Result<Option<Vec<model::Task>>, EngineError> {
+		if !matches!(
+			self.locations.get(&unit_id),
+			Some(UnitLocation::ByMoveTask(_))
+		) {
+			return Ok(None);
+		}
+		let unit_tasks = self
+			.tasks
+			.unit_tasks
+			.get(&unit_id)
+			.ok_or(engine_error!(EngineErrorKind::InvalidUnitId))?;
+		let remaining = unit_tasks
+			.tasks
+			.iter()
+			.map(|simulation_id| {
+				self.tasks
+					.simulated_tasks
+					.get(simulation_id)
+					.map(|sim| sim.task.clone())
+					.ok_or(engine_error!(EngineErrorKind::InternalError))
+			})
+			.collect::<Result<Vec<_>, EngineError>>()?;
+
+		self.set_task_queue_requested(unit_id, time, vec![])?;
+		Ok(Some(remaining))
+	}
+
+	// Runs `plan` with the `others` standing where they are at `time` as
+	// obstacles, so a unit that ran into one of them goes around it
+	pub fn plan_around<T>(
+		&mut self,
+		others: &[UnitId],
+		time: TimeStamp,
+		plan: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+	) -> Result<T, EngineError> {
+		let mut blockers = Vec::new();
+		for other_id in others {
+			if let Some(shape) = self.shapes.get(other_id) {
+				blockers.push(Obstacle {
+					shape: shape.clone(),
+					center: self.get_unit_location(*other_id, time)?.point,
+				});
+			}
+		}
+		self.navigation.set_blockers(blockers);
+		let planned = plan(self);
+		self.navigation.set_blockers(Vec::new());
+		planned
+	}
+
+	pub fn get_collision_policy(&self, unit_id: UnitId) -> CollisionPolicy {
+		self.collision_policies
+			.get(&unit_id)
+			.copied()
+			.unwrap_or_default()
+	}
+
 	pub fn add_unit(
 		&mut self,
 		player_id: PlayerId,
 		unit_id: UnitId,
-		template: UnitTemplate,
+		template_id: TemplateId,
 		location: OrientedPoint,
-	) {
+	) -> Result<(), EngineError> {
+		let template = self
+			.catalog
+			.get(template_id)
+			.map(|entry| entry.template.clone())
+			.ok_or(engine_error!(EngineErrorKind::MalformedRequest))?;
+		let inventory = template
+			.inventory_capacity
+			.map(|capacity| self.stocked_inventory(capacity, &template.stock))
+			.transpose()?;
 		self.owners.insert(unit_id, player_id);
 		self.tasks.unit_created(unit_id);
 
@@ -169,21 +583,187 @@ impl GameState {
 		if let Some(speed) = template.speed {
 			self.speeds.insert(unit_id, speed);
 		}
+		if let Some(acceleration) = template.acceleration {
+			self.accelerations.insert(unit_id, acceleration);
+		}
+		if let Some(turn_rate) = template.turn_rate {
+			self.turn_rates.insert(unit_id, turn_rate);
+		}
+		if let Some(shape) = template.shape {
+			self.shapes.insert(unit_id, shape);
+		}
+		if let Some(collision_policy) = template.collision_policy {
+			self.collision_policies.insert(unit_id, collision_policy);
+		}
 		// if let Some(shape) = template.shape {
 		// 	self.locations
 		// 		.insert(unit_id, UnitLocation::Fixed(location));
 		// }
-		if let Some(display_type) = template.display_type {
-			self.unit_display_types.insert(unit_id, display_type);
+		self.unit_templates.insert(unit_id, template_id);
+		if let Some(inventory) = inventory {
+			self.inventories.insert(unit_id, inventory);
+		}
+		if let Some(vision) = template.vision {
+			self.vision.insert(unit_id, vision);
 		}
 		self.units.insert(unit_id);
 		self.locations
 			.insert(unit_id, UnitLocation::Fixed(location));
+
+		// someone may already be on their way through this spot
+		self.schedule_collisions(unit_id, self.last_time)?;
+		self.schedule_sightings(unit_id, self.last_time)
+	}
+
+	fn stocked_inventory(
+		&self,
+		capacity: i32,
+		stock: &BTreeMap<String, i32>,
+	) -> Result<Inventory, EngineError> {
+		let mut inventory = Inventory::new(capacity);
+		for (name, amount) in stock {
+			let resource_id = self
+				.resources
+				.find(name)
+				.ok_or(engine_error!(EngineErrorKind::MalformedRequest))?;
+			inventory.add(&self.resources, resource_id, *amount)?;
+		}
+		Ok(inventory)
+	}
+
+	pub fn has_unit(&self, unit_id: UnitId) -> bool {
+		self.units.contains(&unit_id)
+	}
+
+	pub fn get_owner(&self, unit_id: UnitId) -> Option<PlayerId> {
+		self.owners.get(&unit_id).copied()
+	}
+
+	pub fn resources(&self) -> &ResourceRegistry {
+		&self.resources
+	}
+
+	pub fn has_inventory(&self, unit_id: UnitId) -> bool {
+		self.inventories.contains_key(&unit_id)
 	}
 
-	pub fn add_player(&mut self, player_id: PlayerId) {
+	pub fn get_inventory_amount(
+		&self,
+		unit_id: UnitId,
+		resource_id: ResourceId,
+	) -> i32 {
+		self.inventories
+			.get(&unit_id)
+			.map_or(0, |inventory| inventory.amount(resource_id))
+	}
+
+	pub fn deposit(
+		&mut self,
+		unit_id: UnitId,
+		resource_id: ResourceId,
+		amount: i32,
+	) -> Result<(), EngineError> {
+		let inventory = self
+			.inventories
+			.get_mut(&unit_id)
+			.ok_or(engine_error!(EngineErrorKind::InvalidUnitId))?;
+		inventory.add(&self.resources, resource_id, amount)?;
+		self.tasks.touch(unit_id);
+		Ok(())
+	}
+
+	pub fn withdraw(
+		&mut self,
+		unit_id: UnitId,
+		resource_id: ResourceId,
+		amount: i32,
+	) -> Result<(), EngineError> {
+		let inventory = self
+			.inventories
+			.get_mut(&unit_id)
+			.ok_or(engine_error!(EngineErrorKind::InvalidUnitId))?;
+		inventory.remove(resource_id, amount)?;
+		self.tasks.touch(unit_id);
+		Ok(())
+	}
+
+	// Moves as much of the requested amount as the source still has and the
+	// destination has room for, as long as the two units are still close
+	// enough
+	fn complete_transfer(
+		&mut self,
+		game_time: TimeStamp,
+		transfer: &model::Transfer,
+	) -> Result<(), EngineError> {
+		let source = self.get_unit_location(transfer.source_id, game_time)?;
+		let destination =
+			self.get_unit_location(transfer.destination_id, game_time)?;
+		if source.point.distance_to(&destination.point) > TRANSFER_RANGE {
+			println!(
+				"Transfer from {} to {} failed, out of range",
+				transfer.source_id, transfer.destination_id
+			);
+			return Ok(());
+		}
+		let room = self
+			.inventories
+			.get(&transfer.destination_id)
+			.ok_or(engine_error!(EngineErrorKind::InvalidUnitId))?
+			.room_for(&self.resources, transfer.resource_id)?;
+		let amount =
+			transfer
+				.amount
+				.min(self.get_inventory_amount(
+					transfer.source_id,
+					transfer.resource_id,
+				))
+				.min(room);
+		if amount <= 0 {
+			return Ok(());
+		}
+		self.withdraw(transfer.source_id, transfer.resource_id, amount)?;
+		self.deposit(transfer.destination_id, transfer.resource_id, amount)
+	}
+
+	// What the player gets to see beyond the animation, None if it isn't
+	// theirs
+	pub fn details(
+		&self,
+		player_id: PlayerId,
+		unit_id: UnitId,
+	) -> Option<model::UnitDetails> {
+		if self.owners.get(&unit_id) != Some(&player_id) {
+			return None;
+		}
+		self.inventories
+			.get(&unit_id)
+			.map(|inventory| inventory.details(&self.resources))
+	}
+
+	pub fn add_player(
+		&mut self,
+		player_id: PlayerId,
+	) -> Result<(), EngineError> {
+		if !self.spawn_slots.contains_key(&player_id) {
+			// the first one nobody holds, players who left gave theirs up
+			let slot = (0..self.spawn_slots.len())
+				.find(|slot| !self.spawn_slots.values().any(|s| s == slot))
+				.unwrap_or(self.spawn_slots.len());
+			self.spawn_slots.insert(player_id, slot);
+		}
 		self.perspectives
 			.insert(player_id, PlayersGamePerspective::default());
+		// nothing was worked out for them while they were gone
+		self.schedule_player_sightings(player_id, self.last_time)
+	}
+
+	// Nobody is watching for them anymore. Their units stay, but nothing
+	// is worked out or sent for the player until they come back.
+	pub fn remove_player(&mut self, player_id: PlayerId) {
+		self.perspectives.remove(&player_id);
+		self.spawn_slots.remove(&player_id);
+		self.sightings.remove(&player_id);
+		self.clear_sightings_by_player(player_id);
 	}
 
 	pub fn send_incremental_updates(
@@ -206,6 +786,7 @@ impl GameState {
 		>,
 	) -> Result<(), EngineError> {
 		let mut updates = PerspectiveUpdates::new(player_id);
+		let visible = self.visible_units(player_id);
 
 		{
 			// For each player perspective, send updates about new units
@@ -215,7 +796,9 @@ impl GameState {
 				.ok_or(engine_error!(EngineErrorKind::InternalError))?;
 
 			// The tasks shouldn't be in charge of this
-			self.tasks.show_perspective(perspective, &mut updates);
+			self.tasks
+				.show_perspective(perspective, &visible, &mut updates);
+			perspective.units_hidden(&visible, &mut updates);
 		}
 
 		{
@@ -231,15 +814,32 @@ impl GameState {
 		Ok(())
 	}
 
+	// A player always sees their own units, and anything inside the vision
+	// of one of them
+	fn visible_units(&self, player_id: PlayerId) -> HashSet<UnitId> {
+		let mut visible = self
+			.units
+			.iter()
+			.filter(|unit_id| self.owners.get(unit_id) == Some(&player_id))
+			.copied()
+			.collect::<HashSet<_>>();
+		if let Some(seen) = self.sightings.get(&player_id) {
+			visible.extend(seen.keys());
+		}
+		visible
+	}
+
+	// What the player gets to know of where the unit is going. The units of
+	// others are only shown as far as the player will see them go.
 	pub fn animate(
 		&self,
-		_player_perspective: PlayerId,
+		player_id: PlayerId,
 		unit_id: UnitId,
 	) -> Result<Option<model::Animatable>, EngineError> {
 		// Create an Animatable for the unit
 
-		let display_type = match self.unit_display_types.get(&unit_id) {
-			Some(dt) => Ok(dt),
+		let template_id = match self.unit_templates.get(&unit_id) {
+			Some(template_id) => Ok(*template_id),
 			None => Err(engine_error!(EngineErrorKind::InternalError)),
 		}?;
 		let queue: Option<Vec<model::AnimationSegment>> =
@@ -260,22 +860,44 @@ impl GameState {
 									EngineErrorKind::InternalError
 								))
 						})
-						.collect::<Result<Vec<_>, EngineError>>()?,
+						.collect::<Result<Vec<_>, EngineError>>()?
+						.concat(),
 				),
 				Some(UnitLocation::Fixed(position)) => {
 					Some(vec![model::AnimationSegment {
 						begin_time: self.last_time,
 						begin_location: position.point.clone(),
 						delta: None,
+						acceleration: None,
+						arc: None,
 						begin_orientation: position.orientation,
 						d_orientation: None,
 					}])
 				}
 				_ => None,
 			};
+		let hidden_at = match self.owners.get(&unit_id) {
+			Some(owner) if *owner == player_id => TimeStamp::MAX,
+			_ => self
+				.sightings
+				.get(&player_id)
+				.and_then(|seen| seen.get(&unit_id))
+				.copied()
+				.unwrap_or(self.last_time),
+		};
+		let queue = queue.map(|mut queue| {
+			// the segment the unit is on then still shows
+			let shown = queue
+				.iter()
+				.skip(1)
+				.take_while(|segment| segment.begin_time < hidden_at)
+				.count();
+			queue.truncate(shown + 1);
+			queue
+		});
 		Ok(queue.map(|queue| model::Animatable {
 			unit_id,
-			display_type: *display_type,
+			template_id,
 			queue,
 		}))
 	}
@@ -286,53 +908,82 @@ impl GameState {
 		at_time: TimeStamp,
 	) -> Result<model::OrientedPoint, EngineError> {
 		match self.locations.get(&unit_id) {
-			Some(UnitLocation::Fixed(pos)) => {
-				println!(
-					"Getting fixed location for unit {} at time {}: {:?}",
-					unit_id, at_time, pos
-				);
-				Ok(pos.clone())
-			}
+			Some(UnitLocation::Fixed(pos)) => Ok(pos.clone()),
 			Some(UnitLocation::ByMoveTask(task_id)) => {
 				let simulated_task = self
 					.tasks
 					.simulated_tasks
 					.get(task_id)
 					.ok_or(engine_error!(EngineErrorKind::InternalError))?;
-				assert!(at_time >= simulated_task.animation.begin_time);
+				let segment = simulated_task
+					.segment_at(at_time)
+					.ok_or(engine_error!(EngineErrorKind::InternalError))?;
+				assert!(at_time >= segment.begin_time);
 				assert!(at_time <= simulated_task.progress.finish_time);
-				Ok(simulated_task.animation.place_at(at_time))
+				Ok(segment.place_at(at_time))
 			}
 			None => Err(engine_error!(EngineErrorKind::InternalError)),
 		}
 	}
 
-	/// Evaluates an animation segment at a specific time to get the position
-	fn eval_animation_segment(
+	// Where the unit will be at a later time, as far as its current move
+	// goes. Anything queued after that move hasn't been simulated yet.
+	pub fn expected_location(
 		&self,
-		seg: &model::AnimationSegment,
+		unit_id: UnitId,
 		at_time: TimeStamp,
 	) -> Result<model::OrientedPoint, EngineError> {
-		let d_t = at_time.saturating_sub(seg.begin_time) as f64;
-
-		Ok(model::OrientedPoint {
-			point: model::Point {
-				x: seg.begin_location.x
-					+ (seg
-						.delta
-						.as_ref()
-						.map_or(0.0, |d| d.dx as f64 * (d_t as f64)))
-						as model::Coord,
-				y: seg.begin_location.y
-					+ (seg
-						.delta
-						.as_ref()
-						.map_or(0.0, |d| d.dy as f64 * (d_t as f64)))
-						as model::Coord,
-			},
-			orientation: seg.begin_orientation
-				+ seg.d_orientation.unwrap_or(0.0) * (d_t as f64) as f32,
-		})
+		let at_time = match self.locations.get(&unit_id) {
+			Some(UnitLocation::ByMoveTask(task_id)) => self
+				.tasks
+				.simulated_tasks
+				.get(task_id)
+				.map_or(at_time, |task| at_time.min(task.progress.finish_time)),
+			_ => at_time,
+		};
+		self.get_unit_location(unit_id, at_time)
+	}
+
+	// Where the player's new units appear
+	pub fn spawn_point(&self, player_id: PlayerId) -> model::Point {
+		let slot = self.spawn_slots.get(&player_id).copied().unwrap_or(0);
+		self.map.spawn(slot)
+	}
+
+	// The waypoints a unit follows from `from` to `to` around the obstacles
+	pub fn find_path(
+		&self,
+		unit_id: UnitId,
+		from: &model::Point,
+		to: &model::Point,
+	) -> Result<Vec<model::Point>, EngineError> {
+		if !self.map.bounds.contains_point(to) {
+			return Err(engine_error!(EngineErrorKind::MalformedRequest));
+		}
+		self.navigation
+			.find_path(from, to, self.clearance(unit_id))
+			.ok_or(engine_error!(EngineErrorKind::MalformedRequest))
+	}
+
+	// Whether the unit fits along the straight line from `from` to `to`
+	pub fn is_clear(
+		&self,
+		unit_id: UnitId,
+		from: &model::Point,
+		to: &model::Point,
+	) -> bool {
+		self.navigation.is_clear(from, to, self.clearance(unit_id))
+	}
+
+	// wide enough for the unit whichever way it faces
+	fn clearance(&self, unit_id: UnitId) -> Coord {
+		match self.shapes.get(&unit_id) {
+			Some(model::Shape::Circle(r)) => *r,
+			Some(model::Shape::Rectangle(w, h)) => {
+				(w.pow(2) + h.pow(2)).isqrt() / 2
+			}
+			None => 0,
+		}
 	}
 
 	// TDO: should accept a wall time..
@@ -350,85 +1001,15 @@ impl GameState {
 		}
 	}
 
-	fn get_next_task(
-		&self,
-		unit_id: UnitId,
-		simulation_id: SimulatedId,
-	) -> Option<SimulatedId> {
-		self.tasks
-			.unit_tasks
-			.get(&unit_id)
-			.map(|ut| {
-				if let Some(index) =
-					ut.tasks.iter().position(|&sid| sid == simulation_id)
-				{
-					if index + 1 < ut.tasks.len() {
-						Some(ut.tasks[index + 1])
-					} else {
-						None
-					}
-				} else {
-					None
-				}
-			})
-			.flatten()
-	}
-
-	fn clear_tasks(
-		&mut self,
-		unit_id: UnitId,
-		// tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
-	) -> Result<(), EngineError> {
-		if let Some(unit_tasks) = self.tasks.unit_tasks.get_mut(&unit_id) {
-			unit_tasks.tasks.clear();
-			unit_tasks.current_simulation_id = None;
-			unit_tasks.sequence_number += 1;
-		}
-		// is this needed?
-		// tick_completion_sender
-		// 	.send(crate::event::PublishEvent::TasksCompleted(unit_id))
-		// 	.map_err(|_e| EngineError::UnableToSend)?;
-		Ok(())
-	}
-
-	fn finished_moving(&mut self, unit_id: UnitId, destination: model::Point) {
-		println!(
-			"Unit {} finished moving to destination {:?}",
-			unit_id, destination
-		);
-		self.locations.insert(
-			unit_id,
-			UnitLocation::Fixed(OrientedPoint {
-				point: destination,
-				orientation: 0.0, // TODO: set proper orientation
-			}),
-		);
+	pub fn get_unit_acceleration(&self, unit_id: UnitId) -> Option<Speed> {
+		self.accelerations.get(&unit_id).copied()
 	}
 
-	fn last_simulation_completed(
-		&mut self,
+	pub fn get_unit_turn_rate(
+		&self,
 		unit_id: UnitId,
-		simulation_id: SimulatedId,
-		// tick_completion_sender: &mut broadcast::Sender<
-		// 	crate::event::PublishEvent,
-		// >,
-	) -> Result<(), EngineError> {
-		let current_simulation = self
-			.tasks
-			.simulated_tasks
-			.get(&simulation_id)
-			.ok_or(engine_error!(EngineErrorKind::InternalError))?;
-
-		match &current_simulation.task {
-			model::Task::MoveTo(location) => {
-				self.finished_moving(unit_id, location.clone());
-			}
-			_ => {
-				// For other task types, we might not need to update the location
-			}
-		}
-		self.clear_tasks(unit_id)?;
-		Ok(())
+	) -> Option<model::Orientation> {
+		self.turn_rates.get(&unit_id).copied()
 	}
 
 	// fn transition_simulation(
@@ -474,8 +1055,24 @@ impl GameState {
 		completion: CompletedTask,
 		// tick_completion_sender: &mut broadcast::Sender<event::PublishEvent>,
 	) -> Result<(), EngineError> {
+		if let model::Task::Transfer(transfer) = &completion.task {
+			self.complete_transfer(game_time, transfer)?;
+		}
+		// an arriving unit keeps facing the way it came
+		let arrival = match &completion.task {
+			model::Task::MoveTo(destination) => self
+				.tasks
+				.simulated_tasks
+				.get(&completion.simulation_id)
+				.and_then(|simulated| simulated.animation.last())
+				.map(|segment| OrientedPoint {
+					point: destination.clone(),
+					orientation: segment.begin_orientation,
+				}),
+			_ => None,
+		};
 		let transition = self.tasks.task_completed(game_time, completion)?;
-		self.handle_task_transition(transition.unit_id, &transition, None)?;
+		self.handle_task_transition(transition.unit_id, &transition, arrival)?;
 
 		Ok(())
 	}
@@ -530,6 +1127,13 @@ impl GameState {
 		} else {
 			self.clear_upcoming_by_unit(unit_id);
 		}
+
+		self.schedule_collisions(unit_id, transition.game_time)?;
+		// a task that finished as planned leaves the rest of the queue, and
+		// so who sees it, as it was
+		if !matches!(transition.from, Some((_, _, true))) {
+			self.schedule_sightings(unit_id, transition.game_time)?;
+		}
 		Ok(())
 	}
 
@@ -558,13 +1162,19 @@ impl GameState {
 		} else {
 			// Shouldn't have to recalculate where it is...
 			let current_location = match &transition.from {
-				Some((model::Task::MoveTo(destination), _, true)) => {
-					OrientedPoint {
+				Some((model::Task::MoveTo(destination), _, true)) => location
+					.unwrap_or(OrientedPoint {
 						point: destination.clone(),
-						orientation: 0.0, // TODO: set proper orientation
-					}
-				}
+						orientation: 0.0,
+					}),
+				// anything else happens in place
 				_ => location
+					.or_else(|| match self.locations.get(&unit_id) {
+						Some(UnitLocation::Fixed(position)) => {
+							Some(position.clone())
+						}
+						_ => None,
+					})
 					.ok_or(engine_error!(EngineErrorKind::InternalError))?,
 			};
 
@@ -584,11 +1194,11 @@ impl PartialOrd for TaskProgress {
 }
 impl Ord for TaskProgress {
 	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		// BinaryHeap is a max-heap, so the earliest event compares greatest
 		other
 			.finish_time
 			.cmp(&self.finish_time)
-			.reverse()
-			.then_with(|| self.completion.cmp(&other.completion))
+			.then_with(|| other.completion.cmp(&self.completion))
 	}
 }
 
@@ -624,8 +1234,17 @@ impl Default for UnitTemplate {
 				current: 100,
 				max: 100,
 			}),
-			speed: Some(1.0 * model::METERS / model::SECONDS as model::Speed),
-			display_type: Some(model::UnitDisplayType::SimpleUnit),
+			// one meter per second
+			speed: Some(model::METERS),
+			// up to speed in half a second
+			acceleration: Some(2 * model::METERS),
+			// half a turn per second
+			turn_rate: Some(std::f32::consts::PI),
+			shape: Some(model::Shape::Circle(model::METERS / 2)),
+			collision_policy: Some(CollisionPolicy::Stop),
+			inventory_capacity: Some(20),
+			stock: BTreeMap::from([("wood".to_string(), 10)]),
+			vision: Some(10 * model::METERS),
 		}
 	}
 }
@@ -637,6 +1256,8 @@ impl From<&SimulatedTask> for TaskProgress {
 			completion: SimulationEvent::TaskCompleted(CompletedTask {
 				unit_id: match &task.progress.completion {
 					SimulationEvent::TaskCompleted(ct) => ct.unit_id,
+					SimulationEvent::Collision(c) => c.unit_id,
+					SimulationEvent::Sighting(s) => s.unit_id,
 				},
 				simulation_id: task.id,
 				task: task.task.clone(),
@@ -0,0 +1,155 @@
+use common::model;
+use common::model::ResourceId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::engine::EngineError;
+use crate::engine::EngineErrorKind;
+use crate::engine_error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceType {
+	pub name: String,
+	// how much inventory capacity one unit of this resource takes up
+	pub size: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceRegistry {
+	next_id: ResourceId,
+	types: HashMap<ResourceId, ResourceType>,
+}
+
+impl Default for ResourceRegistry {
+	fn default() -> Self {
+		// 0 is what an unset resource id looks like on the wire
+		let mut registry = Self {
+			next_id: 1,
+			types: HashMap::new(),
+		};
+		registry.register("wood", 1);
+		registry.register("stone", 2);
+		registry.register("gold", 1);
+		registry
+	}
+}
+
+impl ResourceRegistry {
+	pub fn register(&mut self, name: &str, size: i32) -> ResourceId {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.types.insert(
+			id,
+			ResourceType {
+				name: name.to_string(),
+				size: size.max(1),
+			},
+		);
+		id
+	}
+
+	pub fn get(&self, resource_id: ResourceId) -> Option<&ResourceType> {
+		self.types.get(&resource_id)
+	}
+
+	pub fn find(&self, name: &str) -> Option<ResourceId> {
+		self.types
+			.iter()
+			.find(|(_, resource)| resource.name == name)
+			.map(|(id, _)| *id)
+	}
+
+	fn size_of(&self, resource_id: ResourceId) -> Result<i32, EngineError> {
+		self.get(resource_id)
+			.map(|resource| resource.size)
+			.ok_or(engine_error!(EngineErrorKind::MalformedRequest))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inventory {
+	capacity: i32,
+	// ordered so the details come out the same every time
+	contents: BTreeMap<ResourceId, i32>,
+}
+
+impl Inventory {
+	pub fn new(capacity: i32) -> Self {
+		Self {
+			capacity,
+			contents: BTreeMap::new(),
+		}
+	}
+
+	pub fn amount(&self, resource_id: ResourceId) -> i32 {
+		self.contents.get(&resource_id).copied().unwrap_or(0)
+	}
+
+	pub fn used(&self, registry: &ResourceRegistry) -> i32 {
+		self.contents
+			.iter()
+			.map(|(id, amount)| {
+				amount * registry.get(*id).map_or(1, |resource| resource.size)
+			})
+			.sum()
+	}
+
+	// How many more of this resource fit
+	pub fn room_for(
+		&self,
+		registry: &ResourceRegistry,
+		resource_id: ResourceId,
+	) -> Result<i32, EngineError> {
+		let size = registry.size_of(resource_id)?;
+		Ok((self.capacity - self.used(registry)).max(0) / size)
+	}
+
+	pub fn add(
+		&mut self,
+		registry: &ResourceRegistry,
+		resource_id: ResourceId,
+		amount: i32,
+	) -> Result<(), EngineError> {
+		if amount < 0 || amount > self.room_for(registry, resource_id)? {
+			return Err(engine_error!(EngineErrorKind::MalformedRequest));
+		}
+		*self.contents.entry(resource_id).or_insert(0) += amount;
+		Ok(())
+	}
+
+	pub fn remove(
+		&mut self,
+		resource_id: ResourceId,
+		amount: i32,
+	) -> Result<(), EngineError> {
+		let held = self.amount(resource_id);
+		if amount < 0 || amount > held {
+			return Err(engine_error!(EngineErrorKind::MalformedRequest));
+		}
+		if held == amount {
+			self.contents.remove(&resource_id);
+		} else {
+			self.contents.insert(resource_id, held - amount);
+		}
+		Ok(())
+	}
+
+	pub fn details(&self, registry: &ResourceRegistry) -> model::UnitDetails {
+		model::UnitDetails {
+			inventory_capacity: self.capacity,
+			inventory: self
+				.contents
+				.iter()
+				.map(|(id, amount)| model::ResourceAmount {
+					resource_id: *id,
+					name: registry
+						.get(*id)
+						.map(|resource| resource.name.clone())
+						.unwrap_or_default(),
+					amount: *amount,
+				})
+				.collect(),
+		}
+	}
+}
@@ -1,4 +1,7 @@
 pub mod game;
+pub mod inventory;
+pub mod motion;
+pub mod navigation;
 pub mod perspective;
 pub mod tasks;
 pub mod templates;
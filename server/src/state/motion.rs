@@ -0,0 +1,479 @@
+use common::model::{
+	self, AnimationSegment, CircularArc, Coord, Delta, Orientation,
+	OrientedPoint, Point, Speed, TimeStamp,
+};
+
+// How a unit gets around, from its template
+pub struct Mobility {
+	pub speed: Speed,
+	// Coord per second per second, units start and stop instantly without
+	pub acceleration: Option<Speed>,
+	// radians per second, units face any way instantly without
+	pub turn_rate: Option<Orientation>,
+}
+
+// The animation for following a path and how the unit ends up
+pub struct PlannedMove {
+	pub animation: Vec<AnimationSegment>,
+	pub finish_time: TimeStamp,
+	pub orientation: Orientation,
+}
+
+// Rounding a corner at full speed instead of stopping at it, on the circle
+// that touches both legs
+struct Fillet {
+	// where the circle leaves the incoming leg and meets the outgoing one
+	enter: Point,
+	exit: Point,
+	center: Point,
+	// how far `enter` and `exit` are from the corner
+	cut: Coord,
+	turn: Orientation,
+}
+
+impl Mobility {
+	fn acceleration(&self) -> Option<Speed> {
+		self.acceleration.filter(|a| *a > 0)
+	}
+
+	// How far it takes to get up to full speed, or to stop from it
+	fn ramp(&self, acceleration: Speed) -> Coord {
+		(self.speed as i128 * self.speed as i128 / (2 * acceleration as i128))
+			as Coord
+	}
+
+	// One per point, for the corners that can be rounded. Every straight
+	// line that starts or ends at rest leaves room to speed up or slow
+	// down, and the shortcut across the corner has to be clear.
+	fn fillets(
+		&self,
+		points: &[Point],
+		is_clear: impl Fn(&Point, &Point) -> bool,
+	) -> Vec<Option<Fillet>> {
+		let mut fillets: Vec<Option<Fillet>> =
+			points.iter().map(|_| None).collect();
+		let Some(acceleration) = self.acceleration() else {
+			return fillets;
+		};
+		// the tightest circle there is at full speed
+		let radius = (self.speed as i128 * self.speed as i128
+			/ acceleration as i128) as Coord;
+		if radius <= 0 {
+			return fillets;
+		}
+		let ramp = self.ramp(acceleration);
+		for corner in 1..points.len().saturating_sub(1) {
+			let at = &points[corner];
+			let incoming = Delta::between(&points[corner - 1], at);
+			let outgoing = Delta::between(at, &points[corner + 1]);
+			let turn =
+				model::turn_between(incoming.heading(), outgoing.heading());
+			if turn == 0.0 {
+				continue;
+			}
+			let cut = (radius as f64 * (turn.abs() as f64 / 2.0).tan()).round()
+				as Coord;
+			let (left, needed) = match &fillets[corner - 1] {
+				Some(previous) => (incoming.length() - previous.cut, 0),
+				None => (incoming.length(), ramp),
+			};
+			if left - cut < needed || outgoing.length() - cut < ramp {
+				continue;
+			}
+			let enter = at.moved_by(&incoming.normalize(-cut));
+			let exit = at.moved_by(&outgoing.normalize(cut));
+			if !is_clear(&enter, &exit) {
+				continue;
+			}
+			// on the side the unit turns towards
+			let normal = if turn > 0.0 {
+				Delta {
+					dx: -incoming.dy,
+					dy: incoming.dx,
+				}
+			} else {
+				Delta {
+					dx: incoming.dy,
+					dy: -incoming.dx,
+				}
+			};
+			fillets[corner] = Some(Fillet {
+				center: enter.moved_by(&normal.normalize(radius)),
+				enter,
+				exit,
+				cut,
+				turn,
+			});
+		}
+		fillets
+	}
+}
+
+// Follows `waypoints` from `start`, turning on the spot to face each leg.
+// Units with an acceleration speed up and slow down at the ends and round
+// the corners they can, when `is_clear` lets them cut across.
+pub fn plan_move(
+	mobility: &Mobility,
+	begin_time: TimeStamp,
+	start: &OrientedPoint,
+	waypoints: &[Point],
+	is_clear: impl Fn(&Point, &Point) -> bool,
+) -> PlannedMove {
+	let mut points = vec![start.point.clone()];
+	for waypoint in waypoints {
+		if points.last() != Some(waypoint) {
+			points.push(waypoint.clone());
+		}
+	}
+	let fillets = mobility.fillets(&points, is_clear);
+
+	let mut planner = Planner {
+		mobility,
+		animation: Vec::with_capacity(3 * points.len()),
+		time: begin_time,
+		orientation: start.orientation,
+	};
+	for (leg, ends) in points.windows(2).enumerate() {
+		let entry = fillets[leg].as_ref();
+		let exit = fillets[leg + 1].as_ref();
+		let heading = Delta::between(&ends[0], &ends[1]).heading();
+		let from = entry.map_or(&ends[0], |f| &f.exit);
+		let to = exit.map_or(&ends[1], |f| &f.enter);
+		if entry.is_none() {
+			planner.turn_to(from, heading);
+		}
+		planner.straight(from, to, entry.is_none(), exit.is_none());
+		if let Some(fillet) = exit {
+			planner.round(fillet);
+		}
+	}
+	PlannedMove {
+		animation: planner.animation,
+		finish_time: planner.time,
+		orientation: planner.orientation,
+	}
+}
+
+struct Planner<'a> {
+	mobility: &'a Mobility,
+	animation: Vec<AnimationSegment>,
+	// when the next segment begins
+	time: TimeStamp,
+	orientation: Orientation,
+}
+
+impl Planner<'_> {
+	// Standing still at `at`, facing the way the unit does now
+	fn segment(&self, at: &Point) -> AnimationSegment {
+		AnimationSegment {
+			begin_time: self.time,
+			begin_location: at.clone(),
+			delta: None,
+			acceleration: None,
+			arc: None,
+			begin_orientation: self.orientation,
+			d_orientation: None,
+		}
+	}
+
+	fn turn_to(&mut self, at: &Point, heading: Orientation) {
+		let turn = model::turn_between(self.orientation, heading);
+		if let Some(turn_rate) =
+			self.mobility.turn_rate.filter(|rate| *rate > 0.0)
+			&& turn != 0.0
+		{
+			self.animation.push(AnimationSegment {
+				d_orientation: Some(turn_rate.copysign(turn)),
+				..self.segment(at)
+			});
+			let turn_time = turn.abs() / turn_rate * model::SECONDS as f32;
+			self.time += turn_time.ceil() as TimeStamp;
+		}
+		self.orientation = heading;
+	}
+
+	// From `from` to `to`, speeding up first and slowing down at the end
+	// when the unit is at rest there
+	fn straight(
+		&mut self,
+		from: &Point,
+		to: &Point,
+		starts: bool,
+		stops: bool,
+	) {
+		let direction = Delta::between(from, to);
+		let dist = direction.length();
+		if dist == 0 {
+			return;
+		}
+		let speed = self.mobility.speed;
+		let Some(acceleration) = self.mobility.acceleration() else {
+			self.cruise(from, &direction, dist);
+			return;
+		};
+		let ramp = self.mobility.ramp(acceleration);
+		if starts && stops && dist < 2 * ramp {
+			// never gets up to full speed
+			let top = (acceleration as i128 * dist as i128).isqrt() as Speed;
+			let middle = from.moved_by(&direction.normalize(dist / 2));
+			self.speed_up(from, &direction, top, acceleration);
+			self.slow_down(&middle, &direction, top, acceleration);
+			return;
+		}
+		let (begin, end) = (
+			if starts { ramp } else { 0 },
+			if stops { dist - ramp } else { dist },
+		);
+		if starts {
+			self.speed_up(from, &direction, speed, acceleration);
+		}
+		self.cruise(
+			&from.moved_by(&direction.normalize(begin)),
+			&direction,
+			end - begin,
+		);
+		if stops {
+			self.slow_down(
+				&from.moved_by(&direction.normalize(end)),
+				&direction,
+				speed,
+				acceleration,
+			);
+		}
+	}
+
+	// At full speed for `dist`
+	fn cruise(&mut self, at: &Point, direction: &Delta, dist: Coord) {
+		if dist <= 0 {
+			return;
+		}
+		let speed = self.mobility.speed;
+		self.animation.push(AnimationSegment {
+			delta: Some(direction.normalize(speed)),
+			..self.segment(at)
+		});
+		// Round up so the unit is never reported done before it arrives
+		self.time +=
+			(dist as TimeStamp * model::SECONDS).div_ceil(speed as TimeStamp);
+	}
+
+	// From rest up to `top`
+	fn speed_up(
+		&mut self,
+		at: &Point,
+		direction: &Delta,
+		top: Speed,
+		acceleration: Speed,
+	) {
+		self.animation.push(AnimationSegment {
+			delta: Some(Delta { dx: 0, dy: 0 }),
+			acceleration: Some(direction.normalize(acceleration)),
+			..self.segment(at)
+		});
+		self.time += ramp_time(top, acceleration);
+	}
+
+	// From `top` down to rest
+	fn slow_down(
+		&mut self,
+		at: &Point,
+		direction: &Delta,
+		top: Speed,
+		acceleration: Speed,
+	) {
+		self.animation.push(AnimationSegment {
+			delta: Some(direction.normalize(top)),
+			acceleration: Some(direction.normalize(-acceleration)),
+			..self.segment(at)
+		});
+		self.time += ramp_time(top, acceleration);
+	}
+
+	// Around the corner at full speed, facing along the circle
+	fn round(&mut self, fillet: &Fillet) {
+		let radius = fillet.enter.distance_to(&fillet.center);
+		let angular_velocity =
+			(self.mobility.speed as f64 / radius as f64) as Orientation;
+		let angular_velocity = angular_velocity.copysign(fillet.turn);
+		self.animation.push(AnimationSegment {
+			arc: Some(CircularArc {
+				center: fillet.center.clone(),
+				angular_velocity,
+			}),
+			d_orientation: Some(angular_velocity),
+			..self.segment(&fillet.enter)
+		});
+		let turn_time = fillet.turn / angular_velocity * model::SECONDS as f32;
+		self.time += turn_time.ceil() as TimeStamp;
+		self.orientation += fillet.turn;
+	}
+}
+
+// Milliseconds to change speed by `top`, rounded up
+fn ramp_time(top: Speed, acceleration: Speed) -> TimeStamp {
+	(top as TimeStamp * model::SECONDS).div_ceil(acceleration as TimeStamp)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use model::{METERS, SECONDS};
+	use std::f32::consts::{FRAC_PI_2, PI};
+
+	// Like the default template
+	fn mobility() -> Mobility {
+		Mobility {
+			speed: METERS,
+			acceleration: Some(2 * METERS),
+			turn_rate: Some(PI),
+		}
+	}
+
+	fn at_origin(orientation: Orientation) -> OrientedPoint {
+		OrientedPoint {
+			point: Point { x: 0, y: 0 },
+			orientation,
+		}
+	}
+
+	fn close(a: Orientation, b: Orientation) -> bool {
+		model::turn_between(a, b).abs() < 1e-4
+	}
+
+	#[test]
+	fn turns_on_the_spot_before_speeding_up() {
+		let north = Point {
+			x: 0,
+			y: 2 * METERS,
+		};
+		let planned =
+			plan_move(&mobility(), 0, &at_origin(0.0), &[north], |_, _| true);
+
+		let turn = &planned.animation[0];
+		assert_eq!(turn.begin_location, Point { x: 0, y: 0 });
+		assert_eq!(turn.delta, None);
+		assert_eq!(turn.d_orientation, Some(PI));
+		// a quarter turn at half a turn per second
+		let off = &planned.animation[1];
+		assert_eq!(off.begin_time, SECONDS / 2);
+		assert_eq!(off.begin_location, Point { x: 0, y: 0 });
+		assert_eq!(off.delta, Some(Delta { dx: 0, dy: 0 }));
+		assert!(off.acceleration.is_some());
+		assert!(close(off.begin_orientation, FRAC_PI_2));
+		// half a second each way to get up to speed and stop, a meter and a
+		// half in between
+		assert_eq!(planned.animation.len(), 4);
+		assert_eq!(planned.finish_time, 3 * SECONDS);
+		assert!(close(planned.orientation, FRAC_PI_2));
+	}
+
+	#[test]
+	fn turns_the_short_way() {
+		let south_east = Point {
+			x: METERS,
+			y: -METERS,
+		};
+		let planned = plan_move(
+			&mobility(),
+			0,
+			&at_origin(FRAC_PI_2),
+			&[south_east],
+			|_, _| true,
+		);
+		assert_eq!(planned.animation[0].d_orientation, Some(-PI));
+		// three eighths of a turn
+		assert_eq!(planned.animation[1].begin_time, 750);
+		assert!(close(planned.orientation, -PI / 4.0));
+	}
+
+	#[test]
+	fn faces_the_last_leg_on_arrival() {
+		let waypoints = [
+			Point {
+				x: 4 * METERS,
+				y: 0,
+			},
+			Point {
+				x: 4 * METERS,
+				y: 4 * METERS,
+			},
+		];
+		// rounding the corner
+		let rounded =
+			plan_move(&mobility(), 0, &at_origin(0.0), &waypoints, |_, _| true);
+		let arc = rounded
+			.animation
+			.iter()
+			.find(|segment| segment.arc.is_some())
+			.unwrap();
+		assert!(close(arc.begin_orientation, 0.0));
+		assert!(arc.d_orientation.is_some_and(|turn| turn > 0.0));
+		assert!(close(rounded.orientation, FRAC_PI_2));
+		let last = rounded.animation.last().unwrap();
+		assert!(close(last.begin_orientation, FRAC_PI_2));
+
+		// stopping and turning at it instead
+		let stopped =
+			plan_move(&mobility(), 0, &at_origin(0.0), &waypoints, |_, _| {
+				false
+			});
+		assert!(
+			stopped
+				.animation
+				.iter()
+				.all(|segment| segment.arc.is_none())
+		);
+		let turn = stopped
+			.animation
+			.iter()
+			.find(|segment| segment.d_orientation.is_some())
+			.unwrap();
+		assert_eq!(turn.begin_location, waypoints[0]);
+		assert_eq!(turn.delta, None);
+		assert!(close(stopped.orientation, FRAC_PI_2));
+		assert!(rounded.finish_time < stopped.finish_time);
+	}
+
+	#[test]
+	fn short_moves_never_get_up_to_speed() {
+		let near = Point {
+			x: 2 * METERS / 5,
+			y: 0,
+		};
+		let planned =
+			plan_move(&mobility(), 0, &at_origin(0.0), &[near], |_, _| true);
+		// sqrt(2 m/s² * 0.4 m), rounded down
+		let top = 894;
+		assert_eq!(planned.animation.len(), 2);
+		assert_eq!(planned.animation[0].delta, Some(Delta { dx: 0, dy: 0 }));
+		assert_eq!(planned.animation[1].delta, Some(Delta { dx: top, dy: 0 }));
+		assert_eq!(
+			planned.animation[1].begin_location,
+			Point {
+				x: METERS / 5,
+				y: 0
+			}
+		);
+		assert_eq!(planned.finish_time, 2 * ramp_time(top, 2 * METERS));
+	}
+
+	#[test]
+	fn without_turn_rate_or_acceleration() {
+		let instant = Mobility {
+			speed: METERS,
+			acceleration: None,
+			turn_rate: None,
+		};
+		let north = Point {
+			x: 0,
+			y: 3 * METERS,
+		};
+		let planned =
+			plan_move(&instant, 0, &at_origin(0.0), &[north], |_, _| true);
+		assert_eq!(planned.animation.len(), 1);
+		let only = &planned.animation[0];
+		assert_eq!(only.delta, Some(Delta { dx: 0, dy: METERS }));
+		assert!(close(only.begin_orientation, FRAC_PI_2));
+		assert_eq!(planned.finish_time, 3 * SECONDS);
+	}
+}
@@ -0,0 +1,551 @@
+use common::model::{self, Coord, Point, Rec, Shape};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Grid cells start at half a meter and get coarser for big areas, so a
+// search never looks at more than MAX_CELLS of them
+const CELL: Coord = model::METERS / 2;
+const MAX_CELLS: i64 = 256 * 256;
+// Room around everything of interest for paths that go around the outside
+const MARGIN_CELLS: i64 = 4;
+
+// Octile distance costs
+const STRAIGHT: u64 = 10;
+const DIAGONAL: u64 = 14;
+
+// Something nothing can walk through
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Obstacle {
+	pub shape: Shape,
+	pub center: Point,
+}
+
+type Vector = (f64, f64);
+
+fn vector(p: &Point) -> Vector {
+	(p.x as f64, p.y as f64)
+}
+
+fn length((x, y): Vector) -> f64 {
+	x.hypot(y)
+}
+
+// How close `p` comes to the segment from `a` to `b`
+fn point_to_segment(p: Vector, a: Vector, b: Vector) -> f64 {
+	let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+	let len2 = dx * dx + dy * dy;
+	let t = if len2 == 0.0 {
+		0.0
+	} else {
+		(((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+	};
+	length((p.0 - a.0 - t * dx, p.1 - a.1 - t * dy))
+}
+
+impl Obstacle {
+	// Half its width and height
+	fn extent(&self) -> (Coord, Coord) {
+		match self.shape {
+			Shape::Circle(r) => (r, r),
+			Shape::Rectangle(w, h) => (w / 2, h / 2),
+		}
+	}
+
+	// The point of the obstacle closest to `p`, `p` itself inside it
+	fn nearest(&self, p: Vector) -> Vector {
+		let c = vector(&self.center);
+		match self.shape {
+			Shape::Circle(r) => {
+				let (dx, dy) = (p.0 - c.0, p.1 - c.1);
+				let len = length((dx, dy));
+				if len <= r as f64 {
+					p
+				} else {
+					(c.0 + dx / len * r as f64, c.1 + dy / len * r as f64)
+				}
+			}
+			Shape::Rectangle(w, h) => {
+				let (hw, hh) = (w as f64 / 2.0, h as f64 / 2.0);
+				(p.0.clamp(c.0 - hw, c.0 + hw), p.1.clamp(c.1 - hh, c.1 + hh))
+			}
+		}
+	}
+
+	pub fn covers(&self, p: &Point) -> bool {
+		self.distance_to(vector(p)) == 0.0
+	}
+
+	// How far `p` is from the obstacle, 0 inside it
+	fn distance_to(&self, p: Vector) -> f64 {
+		let c = vector(&self.center);
+		match self.shape {
+			Shape::Circle(r) => {
+				(length((p.0 - c.0, p.1 - c.1)) - r as f64).max(0.0)
+			}
+			Shape::Rectangle(w, h) => length((
+				((p.0 - c.0).abs() - w as f64 / 2.0).max(0.0),
+				((p.1 - c.1).abs() - h as f64 / 2.0).max(0.0),
+			)),
+		}
+	}
+
+	// How close the segment from `a` to `b` comes to the obstacle
+	fn distance_to_segment(&self, a: Vector, b: Vector) -> f64 {
+		let c = vector(&self.center);
+		match self.shape {
+			Shape::Circle(r) => (point_to_segment(c, a, b) - r as f64).max(0.0),
+			Shape::Rectangle(w, h) => {
+				let (hw, hh) = (w as f64 / 2.0, h as f64 / 2.0);
+				let min = (c.0 - hw, c.1 - hh);
+				let max = (c.0 + hw, c.1 + hh);
+				if segment_hits_rect(a, b, min, max) {
+					return 0.0;
+				}
+				// Apart, so the closest points include an end of the
+				// segment or a corner of the rectangle
+				[
+					(min.0, min.1),
+					(min.0, max.1),
+					(max.0, min.1),
+					(max.0, max.1),
+				]
+				.into_iter()
+				.map(|corner| point_to_segment(corner, a, b))
+				.chain([self.distance_to(a), self.distance_to(b)])
+				.fold(f64::INFINITY, f64::min)
+			}
+		}
+	}
+}
+
+// Liang-Barsky clipping of the segment against the rectangle
+fn segment_hits_rect(a: Vector, b: Vector, min: Vector, max: Vector) -> bool {
+	let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+	let (mut t0, mut t1) = (0.0f64, 1.0f64);
+	for (p, q) in [
+		(-dx, a.0 - min.0),
+		(dx, max.0 - a.0),
+		(-dy, a.1 - min.1),
+		(dy, max.1 - a.1),
+	] {
+		if p == 0.0 {
+			if q < 0.0 {
+				return false;
+			}
+			continue;
+		}
+		let t = q / p;
+		if p < 0.0 {
+			t0 = t0.max(t);
+		} else {
+			t1 = t1.min(t);
+		}
+		if t0 > t1 {
+			return false;
+		}
+	}
+	true
+}
+
+// The impassable parts of the map and how to get around them
+#[derive(Default, Serialize, Deserialize)]
+pub struct Navigation {
+	obstacles: Vec<Obstacle>,
+	// paths around obstacles don't leave these
+	bounds: Option<Rec>,
+	// units in the way of the path being planned, see
+	// GameState::plan_around
+	#[serde(skip)]
+	blockers: Vec<Obstacle>,
+}
+
+impl Navigation {
+	pub fn set_bounds(&mut self, bounds: Rec) {
+		self.bounds = Some(bounds);
+	}
+
+	pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+		self.obstacles.push(obstacle);
+	}
+
+	pub fn obstacles(&self) -> &[Obstacle] {
+		&self.obstacles
+	}
+
+	pub fn set_blockers(&mut self, blockers: Vec<Obstacle>) {
+		self.blockers = blockers;
+	}
+
+	// What paths have to go around right now
+	fn impassable(&self) -> impl Iterator<Item = &Obstacle> {
+		self.obstacles.iter().chain(&self.blockers)
+	}
+
+	// Whether something `clearance` wide can go straight from `a` to `b`
+	pub fn is_clear(&self, a: &Point, b: &Point, clearance: Coord) -> bool {
+		let (a, b) = (vector(a), vector(b));
+		self.impassable()
+			.all(|o| o.distance_to_segment(a, b) >= clearance as f64)
+	}
+
+	// The waypoints after `from` that lead to `to` without touching an
+	// obstacle, ending with `to`. None when `to` can't be reached.
+	pub fn find_path(
+		&self,
+		from: &Point,
+		to: &Point,
+		clearance: Coord,
+	) -> Option<Vec<Point>> {
+		// a unit pushed up against something, say by running into it, first
+		// backs straight off it
+		if let Some(off) = self.step_away(from, clearance) {
+			let mut path = vec![off.clone()];
+			path.extend(self.route(&off, to, clearance)?);
+			return Some(path);
+		}
+		self.route(from, to, clearance)
+	}
+
+	// Where `p` ends up backing straight off the closest thing it is
+	// touching or too close to, with half a meter to spare. None when it is
+	// clear of everything already.
+	fn step_away(&self, p: &Point, clearance: Coord) -> Option<Point> {
+		let at = vector(p);
+		let (obstacle, distance) = self
+			.impassable()
+			.map(|o| (o, o.distance_to(at)))
+			.min_by(|a, b| a.1.total_cmp(&b.1))?;
+		if distance > clearance as f64 {
+			return None;
+		}
+		let nearest = obstacle.nearest(at);
+		let mut away = (at.0 - nearest.0, at.1 - nearest.1);
+		if length(away) == 0.0 {
+			// inside it, out the way it is closest to the center
+			let center = vector(&obstacle.center);
+			away = (at.0 - center.0, at.1 - center.1);
+		}
+		let len = length(away);
+		if len == 0.0 {
+			return None;
+		}
+		let step = (clearance + CELL) as f64 - distance;
+		Some(Point {
+			x: p.x + (away.0 / len * step).round() as Coord,
+			y: p.y + (away.1 / len * step).round() as Coord,
+		})
+	}
+
+	fn route(
+		&self,
+		from: &Point,
+		to: &Point,
+		clearance: Coord,
+	) -> Option<Vec<Point>> {
+		if self.is_clear(from, to, clearance) {
+			return Some(vec![to.clone()]);
+		}
+		if self
+			.impassable()
+			.any(|o| o.distance_to(vector(to)) < clearance as f64)
+		{
+			return None;
+		}
+		let grid = Grid::covering(self, from, to, clearance);
+		let cells = grid.search(from, to)?;
+
+		let mut waypoints = vec![from.clone()];
+		// the end cells are where `from` and `to` already are
+		waypoints.extend(
+			cells
+				.iter()
+				.skip(1)
+				.take(cells.len().saturating_sub(2))
+				.map(|cell| grid.center(*cell)),
+		);
+		waypoints.push(to.clone());
+		Some(self.shortcut(&waypoints, clearance))
+	}
+
+	// Drops every waypoint that can be skipped by walking straight past it
+	fn shortcut(&self, waypoints: &[Point], clearance: Coord) -> Vec<Point> {
+		let mut path = Vec::new();
+		let mut at = 0;
+		while at < waypoints.len() - 1 {
+			let next = (at + 2..waypoints.len())
+				.rev()
+				.find(|next| {
+					self.is_clear(&waypoints[at], &waypoints[*next], clearance)
+				})
+				.unwrap_or(at + 1);
+			path.push(waypoints[next].clone());
+			at = next;
+		}
+		path
+	}
+}
+
+// Cells are indexed row by row from `origin`, the corner with the smallest
+// coordinates
+struct Grid {
+	origin: Point,
+	cell: Coord,
+	columns: i64,
+	rows: i64,
+	// how far each cell's center is from the nearest obstacle or edge
+	room: Vec<f64>,
+	// cells with less room than this are blocked
+	margin: f64,
+}
+
+impl Grid {
+	fn covering(
+		navigation: &Navigation,
+		from: &Point,
+		to: &Point,
+		clearance: Coord,
+	) -> Self {
+		let (mut min, mut max) = (from.clone(), from.clone());
+		let mut include = |x: Coord, y: Coord, (ex, ey): (Coord, Coord)| {
+			min.x = min.x.min(x - ex);
+			min.y = min.y.min(y - ey);
+			max.x = max.x.max(x + ex);
+			max.y = max.y.max(y + ey);
+		};
+		include(to.x, to.y, (0, 0));
+		for obstacle in navigation.impassable() {
+			let (ex, ey) = obstacle.extent();
+			include(
+				obstacle.center.x,
+				obstacle.center.y,
+				(ex + clearance, ey + clearance),
+			);
+		}
+
+		let mut cell = CELL;
+		let size = |cell: Coord| {
+			let span =
+				|lo: Coord, hi: Coord| (hi - lo) / cell + 1 + 2 * MARGIN_CELLS;
+			(span(min.x, max.x), span(min.y, max.y))
+		};
+		while size(cell).0 * size(cell).1 > MAX_CELLS {
+			cell *= 2;
+		}
+		let (columns, rows) = size(cell);
+		let origin = Point {
+			x: min.x - MARGIN_CELLS * cell,
+			y: min.y - MARGIN_CELLS * cell,
+		};
+
+		let mut grid = Self {
+			origin,
+			cell,
+			columns,
+			rows,
+			room: Vec::new(),
+			// Wide enough that walking between the centers of two free
+			// neighbours never comes closer than `clearance`
+			margin: (clearance + cell * 3 / 4) as f64,
+		};
+		grid.room = (0..columns * rows)
+			.map(|index| {
+				let point = grid.center(index as usize);
+				let to_edge =
+					navigation.bounds.as_ref().map_or(f64::INFINITY, |b| {
+						(point.x - b.min.x)
+							.min(b.max.x - point.x)
+							.min(point.y - b.min.y)
+							.min(b.max.y - point.y) as f64
+					});
+				let center = vector(&point);
+				navigation
+					.impassable()
+					.map(|o| o.distance_to(center))
+					.fold(to_edge, f64::min)
+			})
+			.collect();
+		grid
+	}
+
+	fn blocked(&self, index: usize) -> bool {
+		self.room[index] < self.margin
+	}
+
+	fn cell_of(&self, p: &Point) -> usize {
+		let column =
+			((p.x - self.origin.x) / self.cell).clamp(0, self.columns - 1);
+		let row = ((p.y - self.origin.y) / self.cell).clamp(0, self.rows - 1);
+		(row * self.columns + column) as usize
+	}
+
+	fn center(&self, index: usize) -> Point {
+		let (row, column) =
+			(index as i64 / self.columns, index as i64 % self.columns);
+		Point {
+			x: self.origin.x + column * self.cell + self.cell / 2,
+			y: self.origin.y + row * self.cell + self.cell / 2,
+		}
+	}
+
+	fn estimate(&self, a: usize, b: usize) -> u64 {
+		let (ar, ac) = (a as i64 / self.columns, a as i64 % self.columns);
+		let (br, bc) = (b as i64 / self.columns, b as i64 % self.columns);
+		let (dr, dc) = ((ar - br).unsigned_abs(), (ac - bc).unsigned_abs());
+		STRAIGHT * dr.max(dc) + (DIAGONAL - STRAIGHT) * dr.min(dc)
+	}
+
+	// A* over the free cells, from the cell of `from` to the cell of `to`.
+	// The goal counts as free, it is checked against the real shapes. A
+	// start that is blocked, like a unit stopped against something, may
+	// move through blocked cells as long as each one has more room.
+	fn search(&self, from: &Point, to: &Point) -> Option<Vec<usize>> {
+		let (start, goal) = (self.cell_of(from), self.cell_of(to));
+		let mut cost = vec![u64::MAX; self.room.len()];
+		let mut came_from = vec![usize::MAX; self.room.len()];
+		let mut open = BinaryHeap::new();
+		cost[start] = 0;
+		open.push(Reverse((self.estimate(start, goal), start)));
+
+		while let Some(Reverse((_, current))) = open.pop() {
+			if current == goal {
+				let mut cells = vec![goal];
+				while let Some(&cell) = cells.last() {
+					if cell == start {
+						break;
+					}
+					cells.push(came_from[cell]);
+				}
+				cells.reverse();
+				return Some(cells);
+			}
+			let (row, column) =
+				(current as i64 / self.columns, current as i64 % self.columns);
+			for (dr, dc) in [
+				(-1, -1),
+				(-1, 0),
+				(-1, 1),
+				(0, -1),
+				(0, 1),
+				(1, -1),
+				(1, 0),
+				(1, 1),
+			] {
+				let (r, c) = (row + dr, column + dc);
+				if r < 0 || c < 0 || r >= self.rows || c >= self.columns {
+					continue;
+				}
+				let next = (r * self.columns + c) as usize;
+				let escaping = self.blocked(current)
+					&& self.room[next] > self.room[current];
+				if self.blocked(next) && next != goal && !escaping {
+					continue;
+				}
+				// no cutting corners between two blocked cells
+				if dr != 0
+					&& dc != 0 && !escaping
+					&& self.blocked((row * self.columns + c) as usize)
+					&& self.blocked((r * self.columns + column) as usize)
+				{
+					continue;
+				}
+				let step = if dr != 0 && dc != 0 {
+					DIAGONAL
+				} else {
+					STRAIGHT
+				};
+				let next_cost = cost[current] + step;
+				if next_cost < cost[next] {
+					cost[next] = next_cost;
+					came_from[next] = current;
+					open.push(Reverse((
+						next_cost + self.estimate(next, goal),
+						next,
+					)));
+				}
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use common::model::METERS;
+
+	const CLEARANCE: Coord = METERS / 2;
+
+	// A wall one meter thick and ten long across the x axis at the origin
+	fn walled() -> Navigation {
+		let mut navigation = Navigation::default();
+		navigation.add_obstacle(Obstacle {
+			shape: Shape::Rectangle(METERS, 10 * METERS),
+			center: Point { x: 0, y: 0 },
+		});
+		navigation
+	}
+
+	fn point(x: Coord, y: Coord) -> Point {
+		Point { x, y }
+	}
+
+	// Ends at `to`, and every leg after the first keeps clear of the wall.
+	// The first may start right against it.
+	fn assert_walkable(navigation: &Navigation, path: &[Point], to: &Point) {
+		assert_eq!(path.last(), Some(to));
+		for leg in path.windows(2) {
+			assert!(
+				navigation.is_clear(&leg[0], &leg[1], CLEARANCE),
+				"{:?} to {:?} touches the wall",
+				leg[0],
+				leg[1]
+			);
+		}
+	}
+
+	#[test]
+	fn straight_when_clear() {
+		let navigation = walled();
+		let (from, to) = (
+			point(-5 * METERS, 8 * METERS),
+			point(5 * METERS, 8 * METERS),
+		);
+		assert_eq!(navigation.find_path(&from, &to, CLEARANCE), Some(vec![to]));
+	}
+
+	#[test]
+	fn around_a_wall() {
+		let navigation = walled();
+		let (from, to) = (point(-3 * METERS, 0), point(3 * METERS, 0));
+		let path = navigation.find_path(&from, &to, CLEARANCE).unwrap();
+		assert!(path.len() > 1);
+		assert!(navigation.is_clear(&from, &path[0], CLEARANCE));
+		assert_walkable(&navigation, &path, &to);
+		// over either end of the wall
+		assert!(path.iter().any(|p| p.y.abs() >= 5 * METERS + CLEARANCE));
+	}
+
+	#[test]
+	fn destination_inside_an_obstacle() {
+		let navigation = walled();
+		let from = point(-3 * METERS, 0);
+		assert_eq!(navigation.find_path(&from, &point(0, 0), CLEARANCE), None);
+		// or too close to it to fit
+		assert_eq!(
+			navigation.find_path(&from, &point(METERS * 3 / 4, 0), CLEARANCE),
+			None
+		);
+	}
+
+	#[test]
+	fn start_inside_a_blocked_cell() {
+		let navigation = walled();
+		// touching the wall, as a unit stopped by running into it would be
+		let from = point(-METERS / 2 - CLEARANCE, METERS);
+		let to = point(3 * METERS, 0);
+		let path = navigation.find_path(&from, &to, CLEARANCE).unwrap();
+		assert_walkable(&navigation, &path, &to);
+		assert!(path.iter().all(|p| !navigation.obstacles()[0].covers(p)));
+	}
+}
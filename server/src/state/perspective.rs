@@ -1,16 +1,20 @@
 use crate::event;
 use crate::state::types::SequenceNumber;
 use common::model::{PlayerId, UnitId};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::collections::HashSet;
 use tokio::sync::broadcast;
 
 use crate::engine::EngineError;
 use crate::engine::EngineErrorKind;
 use crate::engine_error;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct PlayersGamePerspective {
 	pub last_update: HashMap<UnitId, SequenceNumber>,
+	// sent again even though their queue didn't change
+	stale: HashSet<UnitId>,
 }
 
 // TODO: this is not efficient
@@ -25,11 +29,31 @@ impl PlayersGamePerspective {
 	pub fn apply_changes(&mut self, updates: &PerspectiveUpdates) {
 		for (unit_id, sequence_number, _) in &updates.units_to_upsert {
 			self.last_update.insert(*unit_id, *sequence_number);
+			self.stale.remove(unit_id);
 		}
 		for unit_id in &updates.units_to_remove {
 			self.last_update.remove(unit_id);
 		}
 	}
+	// Anything the player was shown that they can't see anymore
+	pub fn units_hidden(
+		&self,
+		visible: &HashSet<UnitId>,
+		perspective_updates: &mut PerspectiveUpdates,
+	) {
+		for unit_id in self.last_update.keys() {
+			if !visible.contains(unit_id) {
+				perspective_updates.units_to_remove.push(*unit_id);
+			}
+		}
+	}
+	// What the player may know of the unit changed, e.g. how far along its
+	// queue they get to see it
+	pub fn resend(&mut self, unit_id: UnitId) {
+		if self.last_update.contains_key(&unit_id) {
+			self.stale.insert(unit_id);
+		}
+	}
 	pub fn unit_exists(
 		&self,
 		unit_id: &UnitId,
@@ -37,7 +61,7 @@ impl PlayersGamePerspective {
 		perspective_updates: &mut PerspectiveUpdates,
 	) {
 		if let Some(last_seq) = self.last_update.get(unit_id) {
-			if *last_seq < sequence_number {
+			if *last_seq < sequence_number || self.stale.contains(unit_id) {
 				perspective_updates.units_to_upsert.push((
 					*unit_id,
 					sequence_number,
@@ -78,7 +102,11 @@ impl PerspectiveUpdates {
 				if let Some(animatable) = animatable {
 					tick_completion_sender
 						.send(crate::event::PublishEvent::UnitCreated(
-							animatable,
+							event::UnitCreatedEvent {
+								player_id: self.player_id,
+								details: game.details(self.player_id, *unit_id),
+								anim: animatable,
+							},
 						))
 						.map_err(|_e| {
 							engine_error!(EngineErrorKind::UnableToSend)
@@ -99,12 +127,14 @@ impl PerspectiveUpdates {
 					tick_completion_sender
 						.send(crate::event::PublishEvent::TasksUpdated(
 							event::TasksUpdatedEvent {
+								player_id: self.player_id,
 								unit_id: *unit_id,
 								tasks: animatable
 									.queue
 									.into_iter()
 									.map(Into::into)
 									.collect(),
+								details: game.details(self.player_id, *unit_id),
 							},
 						))
 						.map_err(|_e| {
@@ -118,6 +148,18 @@ impl PerspectiveUpdates {
 				}
 			}
 		}
+		for unit_id in self.units_to_remove.iter() {
+			println!(
+				"Player {}: sending hide for unit {}",
+				self.player_id, unit_id
+			);
+			tick_completion_sender
+				.send(crate::event::PublishEvent::UnitHidden(
+					self.player_id,
+					*unit_id,
+				))
+				.map_err(|_e| engine_error!(EngineErrorKind::UnableToSend))?;
+		}
 		Ok(())
 	}
 }
@@ -3,9 +3,13 @@ use crate::engine::EngineErrorKind;
 use crate::engine_error;
 use crate::state::game::TaskProgress;
 use crate::state::types::{SequenceNumber, SimulatedId};
+use common::model::PlayerId;
 use common::model::TimeStamp;
 use common::model::UnitId;
+use common::model::{Shape, ShapeVolume};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use crate::state::perspective::PerspectiveUpdates;
 use crate::state::perspective::PlayersGamePerspective;
@@ -19,14 +23,15 @@ pub struct TaskTransition {
 }
 
 // This could go in the engine as well
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SimulatedTask {
 	pub id: SimulatedId,
 	pub task: common::model::Task,
 	// animation class? we need other things like player, health, image...
 	// this should be just the info abot tasks: task animation...
 	// break them up? no.
-	pub animation: common::model::AnimationSegment,
+	// one segment per leg, a move around obstacles has several
+	pub animation: Vec<common::model::AnimationSegment>,
 	pub progress: TaskProgress,
 	// move the task id here?
 	//     yes, and then make this be by_id, and keep the sorted heap separate
@@ -35,7 +40,38 @@ pub struct SimulatedTask {
 	// todo: put the shape (volume) for collisions here
 }
 
-#[derive(Default, Debug)]
+impl SimulatedTask {
+	// The leg the unit is on at `time`
+	pub fn segment_at(
+		&self,
+		time: TimeStamp,
+	) -> Option<&common::model::AnimationSegment> {
+		self.animation
+			.iter()
+			.rev()
+			.find(|segment| segment.begin_time <= time)
+			.or(self.animation.first())
+	}
+
+	// The space swept by each segment, each one ending when the next begins
+	pub fn volumes(&self, shape: &Shape) -> Vec<ShapeVolume> {
+		let ends = self
+			.animation
+			.iter()
+			.skip(1)
+			.map(|segment| segment.begin_time)
+			.chain([self.progress.finish_time]);
+		self.animation
+			.iter()
+			.zip(ends)
+			.flat_map(|(segment, end)| {
+				ShapeVolume::along_segment(shape, segment, end)
+			})
+			.collect()
+	}
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct UnitTasks {
 	pub current_simulation_id: Option<SimulatedId>,
 	pub sequence_number: SequenceNumber,
@@ -54,21 +90,55 @@ pub enum TaskCompletion {
 	// TransferCompleted,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompletedTask {
 	pub unit_id: UnitId,
 	pub simulation_id: SimulatedId,
 	pub task: common::model::Task,
 }
 
+// Two units start to overlap. Always stored with unit_id < other_id.
+#[derive(
+	Debug, Clone, Ord, PartialEq, PartialOrd, Eq, Serialize, Deserialize,
+)]
+pub struct Collision {
+	pub unit_id: UnitId,
+	pub other_id: UnitId,
+}
+
+impl Collision {
+	pub fn between(a: UnitId, b: UnitId) -> Self {
+		Self {
+			unit_id: a.min(b),
+			other_id: a.max(b),
+		}
+	}
+
+	pub fn involves(&self, unit_id: UnitId) -> bool {
+		self.unit_id == unit_id || self.other_id == unit_id
+	}
+}
+
+// The player may start or stop seeing someone else's unit
+#[derive(
+	Debug, Clone, Ord, PartialEq, PartialOrd, Eq, Serialize, Deserialize,
+)]
+pub struct Sighting {
+	pub player_id: PlayerId,
+	pub unit_id: UnitId,
+}
+
 // todo move
-#[derive(Debug, Clone, Ord, PartialEq, PartialOrd, Eq)]
+#[derive(
+	Debug, Clone, Ord, PartialEq, PartialOrd, Eq, Serialize, Deserialize,
+)]
 pub enum SimulationEvent {
 	TaskCompleted(CompletedTask),
-	// Collision,
+	Collision(Collision),
+	Sighting(Sighting),
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TaskManager {
 	pub unit_tasks: HashMap<UnitId, UnitTasks>,
 	pub simulated_tasks: HashMap<SimulatedId, SimulatedTask>,
@@ -252,6 +322,14 @@ impl TaskManager {
 		})
 	}
 
+	// Something other than the queue changed, make perspectives send the
+	// unit again
+	pub fn touch(&mut self, unit_id: UnitId) {
+		if let Some(unit_tasks) = self.unit_tasks.get_mut(&unit_id) {
+			unit_tasks.sequence_number += 1;
+		}
+	}
+
 	pub fn get_sequence_number(
 		&self,
 		unit_id: UnitId,
@@ -266,9 +344,13 @@ impl TaskManager {
 	pub fn show_perspective(
 		&self,
 		perspective: &PlayersGamePerspective,
+		visible: &HashSet<UnitId>,
 		updates: &mut PerspectiveUpdates,
 	) {
 		for (unit_id, unit_tasks) in &self.unit_tasks {
+			if !visible.contains(unit_id) {
+				continue;
+			}
 			perspective.unit_exists(
 				unit_id,
 				unit_tasks.sequence_number,
@@ -1,12 +1,41 @@
-use common::model::{Health, Speed};
+use common::model::{Coord, Health, Speed};
 
 use common::model;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-
+// What a unit is made of when it is created. Catalog files only need to give
+// what differs from the default.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UnitTemplate {
 	pub health: Option<Health>,
 	pub speed: Option<Speed>,
+	// Coord per second per second, both for getting up to speed and for
+	// turning at it. Units without one start and stop instantly.
+	pub acceleration: Option<Speed>,
+	// radians per second, units without one face any way instantly
+	pub turn_rate: Option<model::Orientation>,
 	// keep this separate from the view shape?
-	// pub model_shape: Option<model::Shape>,
-	pub display_type: Option<model::UnitDisplayType>,
+	pub shape: Option<model::Shape>,
+	pub collision_policy: Option<CollisionPolicy>,
+	// units without one can't hold resources
+	pub inventory_capacity: Option<i32>,
+	// what the inventory starts out with, by resource name
+	pub stock: BTreeMap<String, i32>,
+	// how far the unit lets its owner see, from its center
+	pub vision: Option<Coord>,
+}
+
+// What a unit does with its queue after bumping into another unit
+#[derive(
+	Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum CollisionPolicy {
+	// stand still at the contact point and forget the queue
+	#[default]
+	Stop,
+	// go around the unit it bumped into and carry on with the queue, the
+	// blocked task included, from the contact point
+	Replan,
 }
@@ -1,15 +1,20 @@
-use crate::event::PlayerRequest;
+use crate::auth::{
+	AuthenticatedPlayer, JoinTickets, Secrets, authenticated_lobby,
+	authenticated_player,
+};
+use crate::engine::{EngineError, EngineErrorKind};
+use crate::event::{PendingRequest, PlayerRequest};
+use crate::matches::{MatchHandle, MatchId, Matches};
 use crate::viewer::GameViewer;
 use common::grpc::{
 	CreateShapeRequest, CreateShapeResponse, Event, SubscribeRequest,
 	shape_events_server::ShapeEvents,
 };
 use common::model::PlayerId;
-use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use tokio::sync::broadcast;
+use tokio::sync::oneshot;
 use tokio_stream::Stream;
 use tonic::{Request, Response, Status};
 
@@ -19,24 +24,125 @@ use tonic::{Request, Response, Status};
 pub struct ShapeSvc {
 	// to remove
 	next_id: Arc<AtomicU64>,
-	player_requests_tx: tokio::sync::mpsc::Sender<crate::event::PlayerRequest>,
-	tick_tx: broadcast::Sender<crate::event::PublishEvent>,
+	matches: Matches,
+	// where players go when they don't ask for a match
+	default_match: MatchId,
 
-	secrets: HashMap<PlayerId, String>,
+	secrets: Secrets,
+	tickets: JoinTickets,
 }
 
 impl ShapeSvc {
 	pub fn new(
-		user_requests_tx: tokio::sync::mpsc::Sender<
-			crate::event::PlayerRequest,
-		>,
-		tick_tx: broadcast::Sender<crate::event::PublishEvent>,
+		matches: Matches,
+		default_match: MatchId,
+		secrets: Secrets,
 	) -> Self {
 		Self {
 			next_id: Arc::new(AtomicU64::new(1)),
-			player_requests_tx: user_requests_tx,
-			tick_tx,
-			secrets: HashMap::new(),
+			matches,
+			default_match,
+			secrets,
+			tickets: JoinTickets::default(),
+		}
+	}
+
+	fn find_match(&self, match_id: MatchId) -> Result<MatchHandle, Status> {
+		self.matches
+			.get(match_id)
+			.ok_or_else(|| Status::not_found("no such match"))
+	}
+
+	// Hands the request to the match's engine without waiting for it to be
+	// handled
+	async fn send(
+		&self,
+		match_id: MatchId,
+		request: PlayerRequest,
+		failure: &'static str,
+	) -> Result<(), Status> {
+		self.find_match(match_id)?
+			.requests_tx
+			.send(PendingRequest {
+				request,
+				reply: None,
+			})
+			.await
+			.map_err(|_e| Status::internal(failure))
+	}
+
+	// Hands the request to the match's engine and waits to hear if it was
+	// accepted
+	async fn submit(
+		&self,
+		match_id: MatchId,
+		request: PlayerRequest,
+		failure: &'static str,
+	) -> Result<(), Status> {
+		let (reply_tx, reply_rx) = oneshot::channel();
+		self.find_match(match_id)?
+			.requests_tx
+			.send(PendingRequest {
+				request,
+				reply: Some(reply_tx),
+			})
+			.await
+			.map_err(|_e| Status::internal(failure))?;
+		reply_rx
+			.await
+			.map_err(|_e| Status::internal(failure))?
+			.map_err(Into::into)
+	}
+
+	async fn change_clock(
+		&self,
+		match_id: MatchId,
+		change: crate::event::ClockChange,
+	) -> Result<(), Status> {
+		self.send(
+			match_id,
+			PlayerRequest::ChangeClock(change),
+			"failed to send clock request",
+		)
+		.await
+	}
+
+	// The clock is the same for everyone, so only the host gets to touch it
+	fn check_host(&self, player: &AuthenticatedPlayer) -> Result<(), Status> {
+		if self.matches.is_host(player.match_id, player.player_id) {
+			Ok(())
+		} else {
+			Err(Status::permission_denied("only the host changes the clock"))
+		}
+	}
+
+	// Ids after a restore have to skip everything the snapshot already uses
+	pub fn reserve_ids_through(&self, highest: u64) {
+		self.next_id.fetch_max(highest + 1, Ordering::Relaxed);
+	}
+
+	// A new player in the match, taken up by subscribing with the ticket
+	fn reserve_player(&self, match_id: MatchId) -> (PlayerId, String) {
+		let player_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		(player_id, self.tickets.issue(player_id, match_id))
+	}
+}
+
+impl From<EngineError> for Status {
+	fn from(e: EngineError) -> Self {
+		match e.kind() {
+			EngineErrorKind::MalformedRequest => {
+				Status::invalid_argument(e.to_string())
+			}
+			EngineErrorKind::InvalidUnitId => Status::not_found(e.to_string()),
+			EngineErrorKind::NotOwner => {
+				Status::permission_denied(e.to_string())
+			}
+			EngineErrorKind::UnableToSend
+			| EngineErrorKind::InternalError
+			| EngineErrorKind::SnapshotFailed
+			| EngineErrorKind::InvalidMap
+			| EngineErrorKind::InvalidCatalog => Status::internal(e.to_string()),
 		}
 	}
 }
@@ -50,20 +156,73 @@ impl ShapeEvents for ShapeSvc {
 
 	async fn subscribe(
 		&self,
-		_req: Request<SubscribeRequest>,
+		req: Request<SubscribeRequest>,
 	) -> Result<Response<Self::SubscribeStream>, Status> {
-		let player_id = self.next_id.fetch_add(1, Ordering::Relaxed);
-		let rx = self.tick_tx.subscribe();
+		let SubscribeRequest {
+			resume_token,
+			join_ticket,
+			match_id,
+		} = req.into_inner();
+		// the match is checked before anything is handed out for it
+		let (game, player_id, resumed) = if !resume_token.is_empty() {
+			let (player_id, match_id) =
+				self.secrets.resume(&resume_token).ok_or_else(|| {
+					Status::unauthenticated("unknown resume token")
+				})?;
+			(self.find_match(match_id)?, player_id, true)
+		} else if join_ticket.is_empty() {
+			// every other match is joined with a ticket
+			if match_id != 0 && match_id != self.default_match {
+				return Err(Status::permission_denied(
+					"joining this match takes a ticket",
+				));
+			}
+			let game = self.find_match(self.default_match)?;
+			(game, self.next_id.fetch_add(1, Ordering::Relaxed), false)
+		} else {
+			let (player_id, match_id) =
+				self.tickets.redeem(&join_ticket).ok_or_else(|| {
+					Status::unauthenticated("unknown or used join ticket")
+				})?;
+			(self.find_match(match_id)?, player_id, false)
+		};
+		let match_id = game.id;
+		// subscribe before the engine hears about it so the snapshot of a
+		// resumed player isn't missed
+		let rx = game.publish_tx.subscribe();
+		let request = if resumed {
+			PlayerRequest::PlayerResumed(player_id)
+		} else {
+			PlayerRequest::PlayerJoined(player_id)
+		};
+		// registered first so a stream of theirs ending meanwhile doesn't
+		// have the engine drop them after it took them in
+		self.matches.add_player(match_id, player_id);
+		if let Err(status) = self
+			.send(match_id, request, "failed to send join request")
+			.await
+		{
+			self.matches.remove_player(match_id, player_id);
+			return Err(status);
+		}
 
+		// only once the engine has them
+		let secret = if resumed {
+			resume_token
+		} else {
+			self.secrets.issue(player_id, match_id)
+		};
 		let (grpc_tx, grpc_rx) =
 			tokio::sync::mpsc::channel::<Result<Event, Status>>(100);
-		let mut viewer = GameViewer::new(player_id as PlayerId, grpc_tx, rx);
-
-		self.player_requests_tx
-			.send(crate::event::PlayerRequest::PlayerJoined(player_id))
-			.await
-			.map_err(|_e| Status::internal("failed to send join request"))?;
+		let mut viewer = GameViewer::new(
+			player_id as PlayerId,
+			secret,
+			grpc_tx,
+			rx,
+			game.requests_tx.downgrade(),
+		);
 
+		let matches = self.matches.clone();
 		tokio::spawn(async move {
 			match viewer.handle_events().await {
 				Ok(_) => {}
@@ -71,6 +230,7 @@ impl ShapeEvents for ShapeSvc {
 					eprintln!("Error in viewer event handling: {:?}", e);
 				}
 			}
+			matches.remove_player(match_id, player_id);
 		});
 
 		let stream = tokio_stream::wrappers::ReceiverStream::new(grpc_rx);
@@ -81,23 +241,26 @@ impl ShapeEvents for ShapeSvc {
 		&self,
 		req: Request<CreateShapeRequest>,
 	) -> Result<Response<CreateShapeResponse>, Status> {
-		// Extract player ID from metadata
-		let player_id = req
-			.metadata()
-			.get("player-id")
-			.and_then(|v| v.to_str().ok())
-			.and_then(|s| s.parse::<u64>().ok())
-			.ok_or_else(|| {
-				Status::unauthenticated("missing or invalid player-id header")
-			})?;
+		let player = authenticated_player(&req)?;
+		let game = self.find_match(player.match_id)?;
+		let template_id = req
+			.into_inner()
+			.template_id
+			.unwrap_or_else(|| game.catalog.default_id());
+		if game.catalog.get(template_id).is_none() {
+			return Err(Status::invalid_argument(format!(
+				"unknown template {}",
+				template_id
+			)));
+		}
 
 		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
-		self.player_requests_tx
-			.send(PlayerRequest::CreateUnit(player_id, id))
-			.await
-			.map_err(|_e| {
-				Status::internal("failed to send create unit request")
-			})?;
+		self.send(
+			player.match_id,
+			PlayerRequest::CreateUnit(player.player_id, id, template_id),
+			"failed to send create unit request",
+		)
+		.await?;
 
 		Ok(Response::new(CreateShapeResponse { id }))
 	}
@@ -106,24 +269,14 @@ impl ShapeEvents for ShapeSvc {
 		&self,
 		req: Request<common::grpc::SetQueueRequest>,
 	) -> Result<Response<common::grpc::SetQueueResponse>, Status> {
-		// Extract player ID from metadata
-		let _player_id = req
-			.metadata()
-			.get("player-id")
-			.and_then(|v| v.to_str().ok())
-			.and_then(|s| s.parse::<u64>().ok())
-			.ok_or_else(|| {
-				Status::unauthenticated("missing or invalid player-id header")
-			})?;
-
-		self.player_requests_tx
-			.send(crate::event::PlayerRequest::UpdateIntentions(
-				req.into_inner(),
-			))
-			.await
-			.map_err(|_e| {
-				Status::internal("failed to send update intentions request")
-			})?;
+		let player = authenticated_player(&req)?;
+
+		self.submit(
+			player.match_id,
+			PlayerRequest::UpdateIntentions(player.player_id, req.into_inner()),
+			"failed to send update intentions request",
+		)
+		.await?;
 		Ok(Response::new(common::grpc::SetQueueResponse {
 			valid: true,
 		}))
@@ -133,25 +286,208 @@ impl ShapeEvents for ShapeSvc {
 		&self,
 		req: Request<common::grpc::ClearQueueRequest>,
 	) -> Result<Response<common::grpc::ClearQueueResponse>, Status> {
-		// TODO: send an empty queue
-		// Extract player ID from metadata
-		let _player_id = req
-			.metadata()
-			.get("player-id")
-			.and_then(|v| v.to_str().ok())
-			.and_then(|s| s.parse::<u64>().ok())
-			.ok_or_else(|| {
-				Status::unauthenticated("missing or invalid player-id header")
-			})?;
-
-		self.player_requests_tx
-			.send(crate::event::PlayerRequest::ClearQueue(
+		let player = authenticated_player(&req)?;
+
+		self.submit(
+			player.match_id,
+			PlayerRequest::ClearQueue(
+				player.player_id,
 				req.into_inner().unit_id,
-			))
-			.await
-			.map_err(|_e| {
-				Status::internal("failed to send update intentions request")
-			})?;
+			),
+			"failed to send clear queue request",
+		)
+		.await?;
 		Ok(Response::new(common::grpc::ClearQueueResponse {}))
 	}
+
+	async fn pause(
+		&self,
+		req: Request<common::grpc::PauseRequest>,
+	) -> Result<Response<common::grpc::PauseResponse>, Status> {
+		let player = authenticated_player(&req)?;
+		self.check_host(&player)?;
+
+		self.change_clock(player.match_id, crate::event::ClockChange::Pause)
+			.await?;
+		Ok(Response::new(common::grpc::PauseResponse {}))
+	}
+
+	async fn resume(
+		&self,
+		req: Request<common::grpc::ResumeRequest>,
+	) -> Result<Response<common::grpc::ResumeResponse>, Status> {
+		let player = authenticated_player(&req)?;
+		self.check_host(&player)?;
+
+		self.change_clock(player.match_id, crate::event::ClockChange::Resume)
+			.await?;
+		Ok(Response::new(common::grpc::ResumeResponse {}))
+	}
+
+	async fn set_speed(
+		&self,
+		req: Request<common::grpc::SetSpeedRequest>,
+	) -> Result<Response<common::grpc::SetSpeedResponse>, Status> {
+		let player = authenticated_player(&req)?;
+		self.check_host(&player)?;
+
+		let speed = req.into_inner().speed;
+		if !speed.is_finite() || speed <= 0.0 {
+			return Err(Status::invalid_argument(
+				"speed must be a positive number",
+			));
+		}
+
+		self.change_clock(
+			player.match_id,
+			crate::event::ClockChange::SetSpeed(speed),
+		)
+		.await?;
+		Ok(Response::new(common::grpc::SetSpeedResponse {}))
+	}
+
+	// Called by the lobby once everyone is ready. The lobby gets a match of
+	// its own and each lobby player gets a game PlayerId and a ticket to
+	// subscribe with.
+	async fn start_match(
+		&self,
+		req: Request<common::grpc::StartMatchRequest>,
+	) -> Result<Response<common::grpc::StartMatchResponse>, Status> {
+		authenticated_lobby(&req)?;
+
+		let common::grpc::StartMatchRequest {
+			players: roster,
+			name,
+			speed,
+		} = req.into_inner();
+		if roster.is_empty() {
+			return Err(Status::invalid_argument("the roster is empty"));
+		}
+		if !speed.is_finite() || speed < 0.0 {
+			return Err(Status::invalid_argument(
+				"speed must be a positive number",
+			));
+		}
+		let game = self.matches.create(name);
+		if speed > 0.0 {
+			self.change_clock(
+				game.id,
+				crate::event::ClockChange::SetSpeed(speed),
+			)
+			.await?;
+		}
+		// the lobby lists the room's host first
+		let tickets: Vec<_> = roster
+			.into_iter()
+			.map(|entry| {
+				let (player_id, ticket) = self.reserve_player(game.id);
+				println!(
+					"Lobby player {} ({}) will be player {} in match {}",
+					entry.lobby_player_id, entry.name, player_id, game.id
+				);
+				common::grpc::JoinTicket {
+					lobby_player_id: entry.lobby_player_id,
+					player_id,
+					ticket,
+				}
+			})
+			.collect();
+		self.matches.set_host(game.id, tickets[0].player_id);
+		Ok(Response::new(common::grpc::StartMatchResponse {
+			tickets,
+			match_id: game.id,
+		}))
+	}
+
+	// Like the tickets of join_match, only the lobby gets to hand out matches
+	async fn create_match(
+		&self,
+		req: Request<common::grpc::CreateMatchRequest>,
+	) -> Result<Response<common::grpc::CreateMatchResponse>, Status> {
+		authenticated_lobby(&req)?;
+
+		let game = self.matches.create(req.into_inner().name);
+		Ok(Response::new(common::grpc::CreateMatchResponse {
+			match_id: game.id,
+		}))
+	}
+
+	async fn list_matches(
+		&self,
+		_req: Request<common::grpc::ListMatchesRequest>,
+	) -> Result<Response<common::grpc::ListMatchesResponse>, Status> {
+		let matches = self
+			.matches
+			.list()
+			.into_iter()
+			.map(|(game, players)| common::grpc::MatchInfo {
+				match_id: game.id,
+				name: game.name,
+				players: players as u32,
+			})
+			.collect();
+		Ok(Response::new(common::grpc::ListMatchesResponse { matches }))
+	}
+
+	// Writes the caller's match to the server's snapshot directory
+	async fn save_snapshot(
+		&self,
+		req: Request<common::grpc::SaveSnapshotRequest>,
+	) -> Result<Response<common::grpc::SaveSnapshotResponse>, Status> {
+		let player = authenticated_player(&req)?;
+
+		let path = self.matches.snapshot_path(player.match_id);
+		self.submit(
+			player.match_id,
+			PlayerRequest::SaveSnapshot(
+				path.clone(),
+				self.secrets.saved(player.match_id),
+			),
+			"failed to send snapshot request",
+		)
+		.await?;
+		Ok(Response::new(common::grpc::SaveSnapshotResponse {
+			path: path.display().to_string(),
+		}))
+	}
+
+	// The ground under the caller's match, it doesn't change while it runs
+	async fn get_map(
+		&self,
+		req: Request<common::grpc::GetMapRequest>,
+	) -> Result<Response<common::grpc::GetMapResponse>, Status> {
+		let player = authenticated_player(&req)?;
+
+		let game = self.find_match(player.match_id)?;
+		Ok(Response::new(common::grpc::GetMapResponse {
+			map: Some(game.map.as_ref().into()),
+		}))
+	}
+
+	async fn get_templates(
+		&self,
+		req: Request<common::grpc::GetTemplatesRequest>,
+	) -> Result<Response<common::grpc::GetTemplatesResponse>, Status> {
+		let player = authenticated_player(&req)?;
+
+		let game = self.find_match(player.match_id)?;
+		Ok(Response::new(common::grpc::GetTemplatesResponse {
+			templates: game.catalog.templates.iter().map(Into::into).collect(),
+		}))
+	}
+
+	// A ticket into the match, for the lobby to pass on to a player
+	async fn join_match(
+		&self,
+		req: Request<common::grpc::JoinMatchRequest>,
+	) -> Result<Response<common::grpc::JoinMatchResponse>, Status> {
+		authenticated_lobby(&req)?;
+
+		let game = self.find_match(req.into_inner().match_id)?;
+		let (player_id, ticket) = self.reserve_player(game.id);
+		Ok(Response::new(common::grpc::JoinMatchResponse {
+			player_id,
+			ticket,
+		}))
+	}
 }
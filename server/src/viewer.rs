@@ -1,28 +1,57 @@
-use crate::event::PublishEvent;
+use crate::event::{PendingRequest, PlayerRequest, PublishEvent};
 use common::model::PlayerId;
+use tokio::sync::broadcast::error::RecvError;
 
 pub struct GameViewer {
 	player_id: PlayerId,
+	secret: String,
 	grpc_tx:
 		tokio::sync::mpsc::Sender<Result<common::grpc::Event, tonic::Status>>,
 	rx: tokio::sync::broadcast::Receiver<PublishEvent>,
+	// for catching up after falling behind, weak so it doesn't keep the
+	// engine running once the match closes
+	requests_tx: tokio::sync::mpsc::WeakSender<PendingRequest>,
 }
 
 impl GameViewer {
 	pub fn new(
 		player_id: PlayerId,
+		secret: String,
 		grpc_tx: tokio::sync::mpsc::Sender<
 			Result<common::grpc::Event, tonic::Status>,
 		>,
 		rx: tokio::sync::broadcast::Receiver<PublishEvent>,
+		requests_tx: tokio::sync::mpsc::WeakSender<PendingRequest>,
 	) -> Self {
 		Self {
 			player_id,
+			secret,
 			grpc_tx,
 			rx,
+			requests_tx,
 		}
 	}
 
+	// Events were dropped before this viewer got to them, so whatever the
+	// client has may be wrong. Resuming starts it over with a Begin and the
+	// whole perspective.
+	async fn resync(&self, skipped: u64) -> Result<(), tonic::Status> {
+		eprintln!(
+			"Player {} fell {} events behind, starting over",
+			self.player_id, skipped
+		);
+		let Some(requests_tx) = self.requests_tx.upgrade() else {
+			return Err(tonic::Status::unavailable("the match is over"));
+		};
+		requests_tx
+			.send(PendingRequest {
+				request: PlayerRequest::PlayerResumed(self.player_id),
+				reply: None,
+			})
+			.await
+			.map_err(|_e| tonic::Status::unavailable("the match is over"))
+	}
+
 	pub async fn handle_events(&mut self) -> Result<(), tonic::Status> {
 		// Send player identity as the first event
 		self.grpc_tx
@@ -30,6 +59,7 @@ impl GameViewer {
 				kind: Some(common::grpc::event::Kind::PlayerIdentity(
 					common::grpc::PlayerIdentity {
 						player_id: self.player_id,
+						secret: self.secret.clone(),
 					},
 				)),
 			}))
@@ -38,7 +68,22 @@ impl GameViewer {
 				tonic::Status::internal("failed to send player identity")
 			})?;
 
-		while let Ok(publish_event) = self.rx.recv().await {
+		loop {
+			let publish_event = match self.rx.recv().await {
+				Ok(publish_event) => publish_event,
+				Err(RecvError::Lagged(skipped)) => {
+					self.resync(skipped).await?;
+					continue;
+				}
+				Err(RecvError::Closed) => break,
+			};
+			// everyone shares the channel, skip what is meant for others
+			if publish_event
+				.recipient()
+				.is_some_and(|player_id| player_id != self.player_id)
+			{
+				continue;
+			}
 			match publish_event {
 				PublishEvent::TickCompleted(event) => {
 					self.grpc_tx
@@ -47,6 +92,8 @@ impl GameViewer {
 								common::grpc::Synchronize {
 									wall_time: event.wall_ms,
 									game_time: event.game_time,
+									speed: event.speed,
+									paused: event.paused,
 								},
 							)),
 						}))
@@ -55,12 +102,12 @@ impl GameViewer {
 							tonic::Status::internal("failed to send event")
 						})?;
 				}
-				PublishEvent::UnitCreated(anim) => {
+				PublishEvent::UnitCreated(created) => {
+					let mut show: common::grpc::Show = created.anim.into();
+					show.details = created.details.map(Into::into);
 					self.grpc_tx
 						.send(Ok(common::grpc::Event {
-							kind: Some(common::grpc::event::Kind::Show(
-								anim.into(),
-							)),
+							kind: Some(common::grpc::event::Kind::Show(show)),
 						}))
 						.await
 						.map_err(|_e| {
@@ -93,7 +140,21 @@ impl GameViewer {
 										.iter()
 										.map(|t| t.clone())
 										.collect(),
-									details: None,
+									details: updates.details.map(Into::into),
+								},
+							)),
+						}))
+						.await
+						.map_err(|_e| {
+							tonic::Status::internal("failed to send event")
+						})?;
+				}
+				PublishEvent::Begin(_, game_time) => {
+					self.grpc_tx
+						.send(Ok(common::grpc::Event {
+							kind: Some(common::grpc::event::Kind::Begin(
+								common::grpc::Begin {
+									timestamp: game_time,
 								},
 							)),
 						}))
@@ -102,12 +163,24 @@ impl GameViewer {
 							tonic::Status::internal("failed to send event")
 						})?;
 				}
-				PublishEvent::TasksCompleted(completions) => {
+				PublishEvent::UnitHidden(_, unit_id) => {
+					self.grpc_tx
+						.send(Ok(common::grpc::Event {
+							kind: Some(common::grpc::event::Kind::Hide(
+								common::grpc::Hide { id: unit_id },
+							)),
+						}))
+						.await
+						.map_err(|_e| {
+							tonic::Status::internal("failed to send event")
+						})?;
+				}
+				PublishEvent::TasksCompleted(_, unit_id) => {
 					self.grpc_tx
 						.send(Ok(common::grpc::Event {
 							kind: Some(common::grpc::event::Kind::Update(
 								common::grpc::Update {
-									unit_id: completions,
+									unit_id,
 									queue: vec![],
 									details: None,
 								},
@@ -2,8 +2,11 @@ use common::grpc::shape_events_client::ShapeEventsClient;
 use common::grpc::{CreateShapeRequest, Event, SubscribeRequest};
 
 use common::grpc;
+use common::lobby::{Lobby, LobbyEvent};
 use common::model;
-use common::model::{Coord, OrientedPoint, Point, PositionedShape, UnitId};
+use common::model::{
+	OrientedPoint, Point, PositionedShape, UnitId, from_meters, to_meters,
+};
 use futures::StreamExt;
 use leptos::mount::mount_to_body;
 use leptos::prelude::*;
@@ -24,6 +27,7 @@ enum RenderError {
 	MissingTasks,
 	MissingStartTime,
 	InvalidTime,
+	MissingShape,
 	ErrorParsing(common::convert::ParseError),
 }
 
@@ -39,6 +43,9 @@ impl fmt::Display for RenderError {
 			RenderError::InvalidTime => {
 				write!(f, "Invalid time value encountered")
 			}
+			RenderError::MissingShape => {
+				write!(f, "The unit's template has no shape")
+			}
 			RenderError::ErrorParsing(e) => {
 				write!(f, "Error parsing value: {}", e)
 			}
@@ -52,6 +59,8 @@ impl Error for RenderError {}
 struct TimeSync {
 	wall_time_ms: u64,
 	game_time_ms: u64,
+	// game milliseconds per wall millisecond, 0 while paused
+	rate: f64,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -123,12 +132,12 @@ impl From<DrawingRect> for common::model::Rec {
 	fn from(r: DrawingRect) -> Self {
 		(
 			Point {
-				x: r.start_x as Coord,
-				y: r.start_y as Coord,
+				x: from_meters(r.start_x),
+				y: from_meters(r.start_y),
 			},
 			Point {
-				x: r.current_x as Coord,
-				y: r.current_y as Coord,
+				x: from_meters(r.current_x),
+				y: from_meters(r.current_y),
 			},
 		)
 			.into()
@@ -144,12 +153,51 @@ struct UiState {
 	last_unit_pos: HashMap<UnitId, PositionedShape>,
 	selected_units: BTreeSet<UnitId>,
 	player_id: Option<u64>,
+	secret: Option<String>,
+	// the match's ground, fetched once we know who we are
+	map: Option<grpc::Map>,
+	// how each kind of unit looks, fetched along with the map
+	templates: HashMap<u32, grpc::UnitTemplate>,
 }
 
 fn window() -> Window {
 	web_sys::window().expect("no global `window`")
 }
 
+const RESUME_TOKEN_KEY: &str = "resume-token";
+
+// Kept across page reloads so a refresh carries on as the same player
+fn stored_resume_token() -> String {
+	window()
+		.local_storage()
+		.ok()
+		.flatten()
+		.and_then(|storage| storage.get_item(RESUME_TOKEN_KEY).ok().flatten())
+		.unwrap_or_default()
+}
+
+// The lobby sends players over with ?ticket=...
+fn join_ticket_from_url() -> String {
+	window()
+		.location()
+		.search()
+		.ok()
+		.and_then(|query| {
+			query
+				.trim_start_matches('?')
+				.split('&')
+				.find_map(|pair| pair.strip_prefix("ticket="))
+				.map(str::to_string)
+		})
+		.unwrap_or_default()
+}
+
+fn store_resume_token(token: &str) {
+	if let Some(storage) = window().local_storage().ok().flatten() {
+		let _ = storage.set_item(RESUME_TOKEN_KEY, token);
+	}
+}
+
 fn wall_now_ms() -> u64 {
 	js_sys::Date::now() as u64
 }
@@ -157,10 +205,8 @@ fn wall_now_ms() -> u64 {
 fn game_now_ms(sync: Option<TimeSync>) -> Option<u64> {
 	let s = sync?;
 	let now = wall_now_ms();
-	Some(
-		s.game_time_ms
-			.saturating_add(now.saturating_sub(s.wall_time_ms)),
-	)
+	let elapsed = now.saturating_sub(s.wall_time_ms) as f64 * s.rate;
+	Some(s.game_time_ms.saturating_add(elapsed as u64))
 }
 
 fn get_ctx(canvas_id: &str) -> CanvasRenderingContext2d {
@@ -279,6 +325,98 @@ fn draw_grid(
 	ctx.set_line_width(1.0);
 }
 
+fn resource_color(resource: &str) -> &'static str {
+	match resource {
+		"wood" => "#8b5a2b",
+		"stone" => "#9a9a9a",
+		"gold" => "#e6c229",
+		_ => "#d050d0",
+	}
+}
+
+// Everything the map puts under the units: the outside of the bounds,
+// obstacles, spawns and resource nodes
+fn draw_terrain(
+	ctx: &CanvasRenderingContext2d,
+	canvas: &HtmlCanvasElement,
+	map: &grpc::Map,
+	zoom: &ZoomState,
+) {
+	let to_pixel = |p: &grpc::Point| {
+		zoom.map_to_pixel((to_meters(p.x), to_meters(p.y)), canvas)
+	};
+	let length = |c: model::Coord| to_meters(c) * zoom.pixels_per_meter;
+
+	if let (Some(min), Some(max)) = (&map.min, &map.max) {
+		let (x1, y1) = to_pixel(min);
+		let (x2, y2) = to_pixel(max);
+		let (width, height) = (canvas.width() as f64, canvas.height() as f64);
+		ctx.set_fill_style(&wasm_bindgen::JsValue::from_str(
+			"rgba(0, 0, 0, 0.5)",
+		));
+		ctx.fill_rect(0.0, 0.0, width, y1.max(0.0));
+		ctx.fill_rect(0.0, y2, width, (height - y2).max(0.0));
+		ctx.fill_rect(0.0, y1, x1.max(0.0), y2 - y1);
+		ctx.fill_rect(x2, y1, (width - x2).max(0.0), y2 - y1);
+	}
+
+	ctx.set_fill_style(&wasm_bindgen::JsValue::from_str("#5b5346"));
+	for obstacle in &map.obstacles {
+		let (Some(center), Some(shape)) = (&obstacle.center, &obstacle.shape)
+		else {
+			continue;
+		};
+		let (x, y) = to_pixel(center);
+		match model::Shape::from(shape) {
+			model::Shape::Circle(r) => {
+				ctx.begin_path();
+				let _ = ctx.arc(x, y, length(r), 0.0, std::f64::consts::TAU);
+				ctx.fill();
+			}
+			model::Shape::Rectangle(w, h) => {
+				let (w, h) = (length(w), length(h));
+				ctx.fill_rect(x - w / 2.0, y - h / 2.0, w, h);
+			}
+		}
+	}
+
+	ctx.set_stroke_style(&wasm_bindgen::JsValue::from_str(
+		"rgba(255, 255, 255, 0.6)",
+	));
+	ctx.set_line_width(2.0);
+	for spawn in &map.spawns {
+		let (x, y) = to_pixel(spawn);
+		ctx.begin_path();
+		let _ =
+			ctx.arc(x, y, length(model::METERS), 0.0, std::f64::consts::TAU);
+		ctx.stroke();
+	}
+	ctx.set_line_width(1.0);
+
+	for node in &map.resources {
+		let Some(location) = &node.location else {
+			continue;
+		};
+		let (x, y) = to_pixel(location);
+		let size = length(model::METERS / 2);
+		ctx.set_fill_style(&wasm_bindgen::JsValue::from_str(resource_color(
+			&node.resource,
+		)));
+		ctx.begin_path();
+		ctx.move_to(x, y - size);
+		ctx.line_to(x + size, y);
+		ctx.line_to(x, y + size);
+		ctx.line_to(x - size, y);
+		ctx.close_path();
+		ctx.fill();
+		let _ = ctx.fill_text(
+			&format!("{} {}", node.amount, node.resource),
+			x + size * 1.5,
+			y,
+		);
+	}
+}
+
 fn place_for(
 	path: &[common::grpc::AnimationSegment],
 	t_game: u64,
@@ -301,10 +439,7 @@ fn place_for(
 		Err(0) => first
 			.begin_location
 			.map(|p| OrientedPoint {
-				point: model::Point {
-					x: p.x as Coord,
-					y: p.y as Coord,
-				},
+				point: model::Point { x: p.x, y: p.y },
 				orientation: first.begin_orientation,
 			})
 			.ok_or(RenderError::MissingStartTime),
@@ -327,23 +462,18 @@ fn draw_anim(
 	ctx: &CanvasRenderingContext2d,
 	canvas: &HtmlCanvasElement,
 	anim: &common::grpc::Animatable,
+	template: &grpc::UnitTemplate,
 	t_game: u64,
 	zoom: &ZoomState,
 ) -> Result<PositionedShape, RenderError> {
-	let dt = model::UnitDisplayType::parse(anim.display_type)
-		.map_err(|e| RenderError::ErrorParsing(e))?;
 	// color
-	let rgb = dt
-		.get_color()
-		// .map(|c| (c.r as u8, c.g as u8, c.b as u8))
-		// .unwrap_or((0, 0, 0))
-		;
+	let rgb = template
+		.color
+		.as_ref()
+		.map(|c| (c.r as u8, c.g as u8, c.b as u8))
+		.unwrap_or((0, 0, 0));
 	set_color(ctx, rgb);
-
-	let d: model::UnitDisplayType =
-		model::UnitDisplayType::parse(anim.display_type)
-			.map_err(|e| RenderError::ErrorParsing(e))?;
-	let fill = d.get_fill();
+	let fill = template.fill;
 
 	let oriented_point = place_for(&anim.queue, t_game)?;
 	// web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(&format!(
@@ -353,30 +483,49 @@ fn draw_anim(
 
 	// Convert from meters to pixels using zoom state
 	let (x, y) = zoom.map_to_pixel(
-		(oriented_point.point.x as f64, oriented_point.point.y as f64),
+		(
+			to_meters(oriented_point.point.x),
+			to_meters(oriented_point.point.y),
+		),
 		canvas,
 	);
-	let shape = d.get_shape();
+	let shape = template
+		.shape
+		.as_ref()
+		.map(model::Shape::from)
+		.ok_or(RenderError::MissingShape)?;
 	// let sk = shape.kind.as_ref().ok_or(RenderError::MissingTasks)?;
 
 	let pos = match shape {
 		model::Shape::Circle(r) => {
 			ctx.begin_path();
-			let radius_pixels = r as f64 * zoom.pixels_per_meter;
+			let radius_pixels = to_meters(r) * zoom.pixels_per_meter;
 			let _ = ctx.arc(x, y, radius_pixels, 0.0, std::f64::consts::TAU);
 			if fill {
 				ctx.fill();
 			} else {
 				ctx.stroke();
 			}
+			// which way the unit faces
+			let heading = oriented_point.orientation as f64;
+			ctx.set_stroke_style(&wasm_bindgen::JsValue::from_str(
+				"rgba(0, 0, 0, 0.6)",
+			));
+			ctx.begin_path();
+			ctx.move_to(x, y);
+			ctx.line_to(
+				x + radius_pixels * heading.cos(),
+				y + radius_pixels * heading.sin(),
+			);
+			ctx.stroke();
 			common::model::PositionedShape::Circle(model::CenteredCircle {
 				center: oriented_point.point.clone(),
 				radius: r,
 			})
 		}
 		model::Shape::Rectangle(w, h) => {
-			let w_pixels = w as f64 * zoom.pixels_per_meter;
-			let h_pixels = h as f64 * zoom.pixels_per_meter;
+			let w_pixels = to_meters(w) * zoom.pixels_per_meter;
+			let h_pixels = to_meters(h) * zoom.pixels_per_meter;
 			if fill {
 				ctx.fill_rect(x, y, w_pixels, h_pixels);
 			} else {
@@ -384,12 +533,12 @@ fn draw_anim(
 			}
 			common::model::PositionedShape::Rectangle(model::Rec {
 				min: Point {
-					x: oriented_point.point.x - w / (2 as Coord),
-					y: oriented_point.point.y - h / (2 as Coord),
+					x: oriented_point.point.x - w / 2,
+					y: oriented_point.point.y - h / 2,
 				},
 				max: Point {
-					x: oriented_point.point.x + w / (2 as Coord),
-					y: oriented_point.point.y + h / (2 as Coord),
+					x: oriented_point.point.x + w / 2,
+					y: oriented_point.point.y + h / 2,
 				},
 			})
 		}
@@ -433,14 +582,22 @@ fn apply_event(state: &mut UiState, ev: &Event) {
 	match kind {
 		common::grpc::event::Kind::PlayerIdentity(pi) => {
 			state.player_id = Some(pi.player_id);
+			state.secret = Some(pi.secret.clone());
+			store_resume_token(&pi.secret);
 			web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(
 				&format!("Received player ID: {}", pi.player_id),
 			));
 		}
+		common::grpc::event::Kind::Begin(_) => {
+			// a full snapshot follows
+			state.anims.clear();
+			state.last_unit_pos.clear();
+		}
 		common::grpc::event::Kind::Synchronize(s) => {
 			state.sync = Some(TimeSync {
 				wall_time_ms: s.wall_time,
 				game_time_ms: s.game_time,
+				rate: if s.paused { 0.0 } else { s.speed },
 			});
 		}
 		common::grpc::event::Kind::Show(show) => {
@@ -515,10 +672,18 @@ fn start_animation_loop(
 			let st = shared.borrow();
 			// Draw grid first
 			draw_grid(&ctx, &canvas, &st.zoom);
+			if let Some(map) = &st.map {
+				draw_terrain(&ctx, &canvas, map, &st.zoom);
+			}
 
 			// Then draw animations on top
 			for anim in st.anims.values() {
-				let res = draw_anim(&ctx, &canvas, anim, t_game, &st.zoom);
+				// units show up once we know what their templates look like
+				let Some(template) = st.templates.get(&anim.template_id) else {
+					continue;
+				};
+				let res =
+					draw_anim(&ctx, &canvas, anim, template, t_game, &st.zoom);
 				match res {
 					Ok(pos) => {
 						new_positions.insert(anim.unit_id, pos);
@@ -563,16 +728,136 @@ fn start_animation_loop(
 	// tied to this function’s captured environment. In practice this is fine for app lifetime.
 }
 
+// Every call but Subscribe has to say who is making it
+fn authorize<T>(request: &mut Request<T>, player_id: u64, secret: &str) {
+	request
+		.metadata_mut()
+		.insert("player-id", player_id.to_string().parse().unwrap());
+	request
+		.metadata_mut()
+		.insert("player-secret", secret.parse().unwrap());
+}
+
 async fn grpc_client() -> ShapeEventsClient<Client> {
 	let client = Client::new("http://127.0.0.1:50051".into());
 	ShapeEventsClient::new(client)
 }
 
+// The map doesn't change during a match, so it is fetched once per identity
+async fn load_map(
+	shared: Rc<RefCell<UiState>>,
+	player_id: u64,
+	secret: String,
+) {
+	let mut client = grpc_client().await;
+	let mut request = Request::new(grpc::GetMapRequest {});
+	authorize(&mut request, player_id, &secret);
+	match client.get_map(request).await {
+		Ok(resp) => shared.borrow_mut().map = resp.into_inner().map,
+		Err(e) => {
+			web_sys::console::error_1(&wasm_bindgen::JsValue::from_str(
+				&format!("GetMap failed: {e}"),
+			));
+		}
+	}
+}
+
+// Like the map, the catalog is fixed for the whole match
+async fn load_templates(
+	shared: Rc<RefCell<UiState>>,
+	player_id: u64,
+	secret: String,
+) {
+	let mut client = grpc_client().await;
+	let mut request = Request::new(grpc::GetTemplatesRequest {});
+	authorize(&mut request, player_id, &secret);
+	match client.get_templates(request).await {
+		Ok(resp) => {
+			shared.borrow_mut().templates = resp
+				.into_inner()
+				.templates
+				.into_iter()
+				.map(|t| (t.id, t))
+				.collect();
+		}
+		Err(e) => {
+			web_sys::console::error_1(&wasm_bindgen::JsValue::from_str(
+				&format!("GetTemplates failed: {e}"),
+			));
+		}
+	}
+}
+
+const LOBBY_URL: &str = "http://127.0.0.1:8081";
+
+// Keeps `set_lobby` current from the lobby's event stream. The browser
+// reconnects on its own and the lobby starts every connection with a
+// snapshot, so nothing needs redoing after a drop.
+fn watch_lobby(set_lobby: WriteSignal<Lobby>) {
+	let source =
+		match web_sys::EventSource::new(&format!("{}/events", LOBBY_URL)) {
+			Ok(source) => source,
+			Err(e) => {
+				web_sys::console::error_1(&e);
+				return;
+			}
+		};
+	let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(
+		move |ev: web_sys::MessageEvent| {
+			let Some(data) = ev.data().as_string() else {
+				return;
+			};
+			match serde_json::from_str::<LobbyEvent>(&data) {
+				Ok(event) => set_lobby.update(|lobby| lobby.apply(&event)),
+				Err(e) => {
+					web_sys::console::error_1(
+						&wasm_bindgen::JsValue::from_str(&format!(
+							"Bad lobby event: {e}"
+						)),
+					);
+				}
+			}
+		},
+	);
+	source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+	// both live as long as the page
+	on_message.forget();
+	std::mem::forget(source);
+}
+
+fn lobby_summary(lobby: &Lobby) -> String {
+	let rooms: Vec<String> = lobby
+		.rooms
+		.iter()
+		.map(|room| {
+			let ready = room.players.iter().filter(|p| p.ready).count();
+			format!(
+				"{} ({}/{} ready, {:?})",
+				room.name,
+				ready,
+				room.players.len(),
+				room.status
+			)
+		})
+		.collect();
+	format!(
+		"Lobby: {} players, rooms: {}",
+		lobby.players.len(),
+		if rooms.is_empty() {
+			"none".to_string()
+		} else {
+			rooms.join(", ")
+		}
+	)
+}
+
 #[component]
 fn App() -> impl IntoView {
 	let (status, set_status) = signal::<String>("Starting…".to_string());
 	let (bounds_display, set_bounds_display) = signal::<String>("".to_string());
 	let (bounds_update_trigger, set_bounds_update_trigger) = signal(0u32);
+	let (lobby, set_lobby) = signal(Lobby::default());
+	watch_lobby(set_lobby);
 
 	// Non-reactive shared state (fast updates; avoids rerendering on every event/frame)
 	let shared = Rc::new(RefCell::new(UiState::default()));
@@ -638,10 +923,36 @@ fn App() -> impl IntoView {
 
 					let mut client = grpc_client().await;
 
-					let resp = match client
-						.subscribe(Request::new(SubscribeRequest {}))
-						.await
-					{
+					// A ticket is for a new match so it goes first. A refresh
+					// keeps it in the URL after it's been used though, by then
+					// the stored session is the way back in. If the server
+					// knows neither, start over as someone new.
+					let join_ticket = join_ticket_from_url();
+					let resume_token = stored_resume_token();
+					let mut attempts = Vec::new();
+					if !join_ticket.is_empty() {
+						attempts.push(SubscribeRequest {
+							join_ticket,
+							..Default::default()
+						});
+					}
+					if !resume_token.is_empty() {
+						attempts.push(SubscribeRequest {
+							resume_token,
+							..Default::default()
+						});
+					}
+					attempts.push(SubscribeRequest::default());
+
+					let mut resp =
+						Err(tonic::Status::unknown("not subscribed"));
+					for attempt in attempts {
+						resp = client.subscribe(Request::new(attempt)).await;
+						if resp.is_ok() {
+							break;
+						}
+					}
+					let resp = match resp {
 						Ok(r) => r,
 						Err(e) => {
 							set_status.set(format!("Subscribe failed: {e}"));
@@ -657,6 +968,21 @@ fn App() -> impl IntoView {
 							Ok(ev) => {
 								let mut st = shared_for_task.borrow_mut();
 								apply_event(&mut st, &ev);
+								if let Some(
+									grpc::event::Kind::PlayerIdentity(pi),
+								) = &ev.kind
+								{
+									spawn_local(load_map(
+										shared_for_task.clone(),
+										pi.player_id,
+										pi.secret.clone(),
+									));
+									spawn_local(load_templates(
+										shared_for_task.clone(),
+										pi.player_id,
+										pi.secret.clone(),
+									));
+								}
 							}
 							Err(e) => {
 								set_status.set(format!("Stream error: {e}"));
@@ -676,23 +1002,21 @@ fn App() -> impl IntoView {
 			spawn_local(async move {
 				let mut client = grpc_client().await;
 
-				// Get player ID from state
-				let player_id = {
+				// Get player credentials from state
+				let credentials = {
 					let st = shared.borrow();
-					st.player_id
+					st.player_id.zip(st.secret.clone())
 				};
 
-				let Some(player_id) = player_id else {
+				let Some((player_id, secret)) = credentials else {
 					set_status.set("No player ID yet".into());
 					return;
 				};
 
-				// Create request with player ID metadata
-				let mut request = Request::new(CreateShapeRequest {});
-				request.metadata_mut().insert(
-					"player-id",
-					player_id.to_string().parse().unwrap(),
-				);
+				// Create request with player credentials
+				let mut request =
+					Request::new(CreateShapeRequest { template_id: None });
+				authorize(&mut request, player_id, &secret);
 
 				match client.create_shape(request).await {
 					Ok(resp) => {
@@ -725,13 +1049,13 @@ fn App() -> impl IntoView {
 			spawn_local(async move {
 				let mut client = grpc_client().await;
 
-				// Get player ID from state
-				let player_id = {
+				// Get player credentials from state
+				let credentials = {
 					let st = shared.borrow();
-					st.player_id
+					st.player_id.zip(st.secret.clone())
 				};
 
-				let Some(player_id) = player_id else {
+				let Some((player_id, secret)) = credentials else {
 					set_status.set("No player ID yet".into());
 					return;
 				};
@@ -745,13 +1069,10 @@ fn App() -> impl IntoView {
 						),
 					));
 
-					// Clear queue with player ID metadata
+					// Clear queue with player credentials
 					let mut clear_req =
 						Request::new(grpc::ClearQueueRequest { unit_id });
-					clear_req.metadata_mut().insert(
-						"player-id",
-						player_id.to_string().parse().unwrap(),
-					);
+					authorize(&mut clear_req, player_id, &secret);
 
 					// no need to cllear
 					match client.clear_queue(clear_req).await {
@@ -774,7 +1095,7 @@ fn App() -> impl IntoView {
 						}
 					}
 
-					// Queue task with player ID metadata
+					// Queue task with player credentials
 					let mut queue_req = Request::new(grpc::SetQueueRequest {
 						unit_id,
 						tasks: vec![grpc::Task {
@@ -783,10 +1104,7 @@ fn App() -> impl IntoView {
 							})),
 						}],
 					});
-					queue_req.metadata_mut().insert(
-						"player-id",
-						player_id.to_string().parse().unwrap(),
-					);
+					authorize(&mut queue_req, player_id, &secret);
 
 					match client.queue(queue_req).await {
 						Ok(_) => {
@@ -925,8 +1243,8 @@ fn App() -> impl IntoView {
 				let (click_x, click_y) =
 					state.zoom.pixel_to_map((mouse_x, mouse_y), &canvas);
 				let click_point = Point {
-					x: click_x as Coord,
-					y: click_y as Coord,
+					x: from_meters(click_x),
+					y: from_meters(click_y),
 				};
 
 				// let intersecting_units: Vec<UnitId> = state
@@ -949,17 +1267,17 @@ fn App() -> impl IntoView {
 					.map(|(&unit_id, pos)| {
 						// could calc distance, and check > 0
 						let center = pos.center();
-						let dist = ((center.x - (click_x as Coord)).powi(2)
-							+ (center.y - (click_y as Coord)).powi(2))
-						.sqrt();
-						(unit_id, dist)
+						(unit_id, center.distance_to(&click_point))
 					})
-					.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+					.min_by_key(|(_, dist)| *dist)
 				{
 					web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(
 						&format!(
 							"Right click - shapes at ({:.2}m, {:.2}m): {:?} (distance: {:.2}m)",
-							click_x, click_y, id, dist
+							click_x,
+							click_y,
+							id,
+							to_meters(dist)
 						),
 					));
 				} else {
@@ -987,8 +1305,8 @@ fn App() -> impl IntoView {
 				let (click_x, click_y) =
 					state.zoom.pixel_to_map((mouse_x, mouse_y), &canvas);
 				let click_point = Point {
-					x: click_x as Coord,
-					y: click_y as Coord,
+					x: from_meters(click_x),
+					y: from_meters(click_y),
 				};
 
 				web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(
@@ -1007,18 +1325,16 @@ fn App() -> impl IntoView {
 					.map(|(&unit_id, pos)| {
 						// could calc distance, and check > 0
 						let center = pos.center();
-						let dist = ((center.x - (click_x as Coord)).powi(2)
-							+ (center.y - (click_y as Coord)).powi(2))
-						.sqrt();
-						(unit_id, dist)
+						(unit_id, center.distance_to(&click_point))
 					})
-					.min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+					.min_by_key(|(_, dist)| *dist)
 				{
 					state.selected_units.insert(id);
 					web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(
 						&format!(
 							"Selected unit: {} (distance: {:.2}m)",
-							id, dist
+							id,
+							to_meters(dist)
 						),
 					));
 				} else {
@@ -1054,14 +1370,14 @@ fn App() -> impl IntoView {
 					web_sys::console::log_1(&wasm_bindgen::JsValue::from_str(
 						&format!(
 							"Rectangle drawn - Center: ({:.2}m, {:.2}m), Width: {:.2}m, Height: {:.2}m, Bounds: X[{:.2}, {:.2}], Y[{:.2}, {:.2}]",
-							rec.center().x,
-							rec.center().y,
-							rec.width(),
-							rec.height(),
-							rec.min.x,
-							rec.max.x,
-							rec.min.y,
-							rec.max.y
+							to_meters(rec.center().x),
+							to_meters(rec.center().y),
+							to_meters(rec.width()),
+							to_meters(rec.height()),
+							to_meters(rec.min.x),
+							to_meters(rec.max.x),
+							to_meters(rec.min.y),
+							to_meters(rec.max.y)
 						),
 					));
 				}
@@ -1125,6 +1441,9 @@ fn App() -> impl IntoView {
 			<div style="margin-top: 8px; font-family: monospace; font-size: 12px; color: #666;">
 				{move || bounds_display.get()}
 			</div>
+			<div style="margin-top: 4px; font-size: 12px; color: #666;">
+				{move || lobby_summary(&lobby.get())}
+			</div>
 			<div style="margin-top: 8px;">
 				<canvas
 					id="canvas"